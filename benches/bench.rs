@@ -5,6 +5,10 @@ fn load_player(c: &mut Criterion) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
     let http_client = reqwest::Client::new();
 
+    // The roster starts out empty, without it every player page would be for "Unknown"
+    let db_connection = rusqlite::Connection::open(rating_update::rater::DB_NAME).unwrap();
+    rating_update::website::load_characters(&db_connection).unwrap();
+
     runtime.block_on(async {
         tokio::spawn(rating_update::website::run());
     });
@@ -12,7 +16,6 @@ fn load_player(c: &mut Criterion) {
     c.bench_function("load_player", |b| {
         // Find a number of players to run the benchmark on
         let players = {
-            let db_connection = rusqlite::Connection::open(rating_update::rater::DB_NAME).unwrap();
             let mut stmt = db_connection
                 .prepare("SELECT id, char_id FROM ranking_global LIMIT 50")
                 .unwrap();
@@ -35,7 +38,7 @@ fn load_player(c: &mut Criterion) {
                         .get(format!(
                             "http://localhost/player/{:X}/{}",
                             id,
-                            rating_update::website::CHAR_NAMES[char_id].0
+                            rating_update::website::character(char_id).0
                         ))
                         .send()
                         .await
@@ -1,4 +1,7 @@
-CREATE TABLE games (
+-- Runs on every start to create whatever is missing, so every statement has to be safe to repeat.
+-- Changes to tables that already exist go in rater::migrate.
+
+CREATE TABLE IF NOT EXISTS games (
     timestamp INTEGER NOT NULL,
     id_a INTEGER NOT NULL,
     name_a TEXT NOT NULL,
@@ -13,20 +16,20 @@ CREATE TABLE games (
     PRIMARY KEY (timestamp, id_a, id_b)
 );
 
-CREATE INDEX games_char_a ON games(char_a);
-CREATE INDEX games_char_b ON games(char_b);
+CREATE INDEX IF NOT EXISTS games_char_a ON games(char_a);
+CREATE INDEX IF NOT EXISTS games_char_b ON games(char_b);
 
 -- Indices for speeding up player character match history lookup
-CREATE INDEX games_id_char_a ON games (
+CREATE INDEX IF NOT EXISTS games_id_char_a ON games (
 	id_a,
 	char_a
 );
-CREATE INDEX games_id_char_b ON games (
+CREATE INDEX IF NOT EXISTS games_id_char_b ON games (
 	id_b,
 	char_b
 );
 
-CREATE TABLE game_ratings (
+CREATE TABLE IF NOT EXISTS game_ratings (
     timestamp INTEGER NOT NULL,
     id_a INTEGER NOT NULL,
     value_a REAL NOT NULL,
@@ -39,25 +42,40 @@ CREATE TABLE game_ratings (
     PRIMARY KEY (timestamp, id_a, id_b, winner)
 );
 
-CREATE INDEX ratings_value_a ON game_ratings(value_a);
-CREATE INDEX ratings_value_b ON game_ratings(value_b);
-CREATE INDEX ratings_dev_a ON game_ratings(deviation_a);
-CREATE INDEX ratings_dev_b ON game_ratings(deviation_b);
+CREATE INDEX IF NOT EXISTS ratings_value_a ON game_ratings(value_a);
+CREATE INDEX IF NOT EXISTS ratings_value_b ON game_ratings(value_b);
+CREATE INDEX IF NOT EXISTS ratings_dev_a ON game_ratings(deviation_a);
+CREATE INDEX IF NOT EXISTS ratings_dev_b ON game_ratings(deviation_b);
+CREATE INDEX IF NOT EXISTS ratings_id_a ON game_ratings(id_a);
+CREATE INDEX IF NOT EXISTS ratings_id_b ON game_ratings(id_b);
 
-CREATE TABLE players  (
+CREATE TABLE IF NOT EXISTS players  (
     id INTEGER NOT NULL PRIMARY KEY,
     floor INTEGER NOT NULL,
     name TEXT NOT NULL,
     platform INTEGER NOT NULL
 );
 
-CREATE TABLE player_names (
+-- Every name a player has used, with the times of the first and last game under it
+CREATE TABLE IF NOT EXISTS player_names (
     id INTEGER NOT NULL,
     name TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
     PRIMARY KEY(id, name)
 );
 
-CREATE TABLE player_ratings (
+CREATE INDEX IF NOT EXISTS player_names_name ON player_names(name);
+
+-- Search index over every name in player_names, see rater::normalize_name
+CREATE VIRTUAL TABLE IF NOT EXISTS player_name_search USING fts5(
+    normalized_name,
+    id UNINDEXED,
+    name UNINDEXED,
+    tokenize = 'trigram'
+);
+
+CREATE TABLE IF NOT EXISTS player_ratings (
     id INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     wins INTEGER NOT NULL,
@@ -81,7 +99,7 @@ CREATE TABLE player_ratings (
     PRIMARY KEY(id, char_id)
 );
 
-CREATE TABLE daily_ratings (
+CREATE TABLE IF NOT EXISTS daily_ratings (
     id INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     timestamp INTEGER NOT NULL,
@@ -90,10 +108,10 @@ CREATE TABLE daily_ratings (
     PRIMARY KEY(id, char_id, timestamp)
 );
 
-CREATE INDEX player_value ON player_ratings(value);
-CREATE INDEX player_dev ON player_ratings(deviation);
+CREATE INDEX IF NOT EXISTS player_value ON player_ratings(value);
+CREATE INDEX IF NOT EXISTS player_dev ON player_ratings(deviation);
 
-CREATE TABLE player_matchups (
+CREATE TABLE IF NOT EXISTS player_matchups (
     id INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     opp_char_id INTEGER NOT NULL,
@@ -105,7 +123,7 @@ CREATE TABLE player_matchups (
     PRIMARY KEY(id, char_id, opp_char_id)
 );
 
-CREATE TABLE global_matchups(
+CREATE TABLE IF NOT EXISTS global_matchups(
     char_id INTEGER NOT NULL,
     opp_char_id INTEGER NOT NULL,
     rating_value REAL NOT NULL,
@@ -115,7 +133,7 @@ CREATE TABLE global_matchups(
     PRIMARY KEY(char_id, opp_char_id)
 );
 
-CREATE TABLE top_1000_matchups(
+CREATE TABLE IF NOT EXISTS top_1000_matchups(
     char_id INTEGER NOT NULL,
     opp_char_id INTEGER NOT NULL,
     rating_value REAL NOT NULL,
@@ -125,7 +143,7 @@ CREATE TABLE top_1000_matchups(
     PRIMARY KEY(char_id, opp_char_id)
 );
 
-CREATE TABLE top_100_matchups(
+CREATE TABLE IF NOT EXISTS top_100_matchups(
     char_id INTEGER NOT NULL,
     opp_char_id INTEGER NOT NULL,
     rating_value REAL NOT NULL,
@@ -135,7 +153,7 @@ CREATE TABLE top_100_matchups(
     PRIMARY KEY(char_id, opp_char_id)
 );
 
-CREATE TABLE proportional_matchups(
+CREATE TABLE IF NOT EXISTS proportional_matchups(
     char_id INTEGER NOT NULL,
     opp_char_id INTEGER NOT NULL,
     rating_value REAL NOT NULL,
@@ -145,14 +163,24 @@ CREATE TABLE proportional_matchups(
     PRIMARY KEY(char_id, opp_char_id)
 );
 
-CREATE TABLE player_floor_distribution(
+CREATE TABLE IF NOT EXISTS player_floor_distribution(
     floor INTEGER NOT NULL,
     player_count INTEGER NOT NULL,
     game_count INTEGER NOT NULL,
     PRIMARY KEY(floor)
 );
 
-CREATE TABLE player_rating_distribution(
+-- The rating new players start at from the floor of their first game, learned from the established
+-- players of each floor
+CREATE TABLE IF NOT EXISTS floor_rating_priors(
+    floor INTEGER NOT NULL,
+    player_count INTEGER NOT NULL,
+    value REAL NOT NULL,
+    deviation REAL NOT NULL,
+    PRIMARY KEY(floor)
+);
+
+CREATE TABLE IF NOT EXISTS player_rating_distribution(
     min_rating INTEGER NOT NULL,
     max_rating INTEGER NOT NULL,
     player_count INTEGER NOT NULL,
@@ -160,85 +188,473 @@ CREATE TABLE player_rating_distribution(
     PRIMARY KEY(min_rating, max_rating)
 );
 
-CREATE TABLE ranking_global (
+-- Rating at each tenth of a percent of the established players, char_id -1 is all characters.
+CREATE TABLE IF NOT EXISTS rating_percentiles(
+    char_id INTEGER NOT NULL,
+    permille INTEGER NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY(char_id, permille)
+);
+
+CREATE TABLE IF NOT EXISTS rating_percentile_counts(
+    char_id INTEGER NOT NULL,
+    player_count INTEGER NOT NULL,
+    PRIMARY KEY(char_id)
+);
+
+CREATE TABLE IF NOT EXISTS ranking_global (
     global_rank INTEGER NOT NULL,
     id INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     PRIMARY KEY(global_rank)
 );
 
-CREATE TABLE ranking_character (
+CREATE TABLE IF NOT EXISTS ranking_character (
     character_rank INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     id INTEGER NOT NULL,
     PRIMARY KEY(character_rank, char_id)
 );
 
-CREATE TABLE character_popularity_global (
+CREATE TABLE IF NOT EXISTS ranking_global_platform (
+    platform INTEGER NOT NULL,
+    global_rank INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    PRIMARY KEY(platform, global_rank)
+);
+
+CREATE TABLE IF NOT EXISTS ranking_character_platform (
+    platform INTEGER NOT NULL,
+    character_rank INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    PRIMARY KEY(platform, char_id, character_rank)
+);
+
+-- Daily snapshots of the top of the rankings, keyed by the timestamp of the day's midnight
+CREATE TABLE IF NOT EXISTS ranking_global_history (
+    timestamp INTEGER NOT NULL,
+    global_rank INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    losses INTEGER NOT NULL,
+    value REAL NOT NULL,
+    deviation REAL NOT NULL,
+    PRIMARY KEY(timestamp, global_rank)
+);
+
+CREATE INDEX IF NOT EXISTS ranking_global_history_player ON ranking_global_history(id, char_id);
+
+CREATE TABLE IF NOT EXISTS ranking_character_history (
+    timestamp INTEGER NOT NULL,
+    character_rank INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    losses INTEGER NOT NULL,
+    value REAL NOT NULL,
+    deviation REAL NOT NULL,
+    PRIMARY KEY(timestamp, char_id, character_rank)
+);
+
+CREATE INDEX IF NOT EXISTS ranking_character_history_player ON ranking_character_history(id, char_id);
+
+-- Patch 0 is the rolling window, other patches hold the games since their release, up until the
+-- next patch was released. sample_size is the number of character picks the popularity is based on.
+CREATE TABLE IF NOT EXISTS character_popularity_global (
+    patch_id INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     popularity REAL NOT NULL,
-    PRIMARY KEY(char_id)
+    sample_size INTEGER NOT NULL,
+    PRIMARY KEY(patch_id, char_id)
 );
 
-CREATE TABLE character_popularity_rating (
+CREATE TABLE IF NOT EXISTS character_popularity_rating (
+    patch_id INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     rating_bracket INTEGER NOT NULL,
     popularity REAL NOT NULL,
-    PRIMARY KEY(char_id, rating_bracket)
+    sample_size INTEGER NOT NULL,
+    PRIMARY KEY(patch_id, char_id, rating_bracket)
 );
 
-CREATE TABLE fraud_index (
+-- Snapshots of the rolling window popularity of every statistics period
+CREATE TABLE IF NOT EXISTS character_popularity_global_history (
+    timestamp INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
-    player_count INTEGER NOT NULL,
-    avg_delta REAL NOT NULL,
-    PRIMARY KEY(char_id)
+    popularity REAL NOT NULL,
+    sample_size INTEGER NOT NULL,
+    PRIMARY KEY(timestamp, char_id)
+);
+
+CREATE TABLE IF NOT EXISTS character_popularity_rating_history (
+    timestamp INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    rating_bracket INTEGER NOT NULL,
+    popularity REAL NOT NULL,
+    sample_size INTEGER NOT NULL,
+    PRIMARY KEY(timestamp, char_id, rating_bracket)
 );
 
-CREATE TABLE fraud_index_higher_rated (
+-- Matchups of the last weeks per rating bracket, expected_wins sums the pre-game win chances.
+CREATE TABLE IF NOT EXISTS bracket_matchups(
+    patch_id INTEGER NOT NULL,
+    rating_bracket INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
-    player_count INTEGER NOT NULL,
-    avg_delta REAL NOT NULL,
-    PRIMARY KEY(char_id)
+    opp_char_id INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    losses INTEGER NOT NULL,
+    expected_wins REAL NOT NULL,
+    rating_delta_sum REAL NOT NULL,
+    PRIMARY KEY(patch_id, rating_bracket, char_id, opp_char_id)
 );
 
-CREATE TABLE fraud_index_highest_rated (
+-- Matchups fitted from the players' pre-game ratings, the offset is in rating points.
+CREATE TABLE IF NOT EXISTS estimated_matchups(
+    patch_id INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    opp_char_id INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    losses INTEGER NOT NULL,
+    rating_offset REAL NOT NULL,
+    rating_offset_deviation REAL NOT NULL,
+    PRIMARY KEY(patch_id, char_id, opp_char_id)
+);
+
+-- Snapshots of the fraud index of every statistics period, for each rating threshold
+CREATE TABLE IF NOT EXISTS fraud_index (
+    timestamp INTEGER NOT NULL,
+    rating_threshold INTEGER NOT NULL,
     char_id INTEGER NOT NULL,
     player_count INTEGER NOT NULL,
     avg_delta REAL NOT NULL,
-    PRIMARY KEY(char_id)
+    PRIMARY KEY(timestamp, rating_threshold, char_id)
+);
+
+-- Tier list of every statistics period, the scores are in rating points
+CREATE TABLE IF NOT EXISTS tier_list (
+    timestamp INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    matchup_score REAL NOT NULL,
+    fraud_score REAL NOT NULL,
+    representation_score REAL NOT NULL,
+    score REAL NOT NULL,
+    tier TEXT NOT NULL,
+    PRIMARY KEY(timestamp, char_id)
+);
+
+CREATE TABLE IF NOT EXISTS patches (
+    patch_id INTEGER NOT NULL PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    label TEXT NOT NULL UNIQUE,
+    notes TEXT
+);
+
+CREATE TABLE IF NOT EXISTS seasons (
+    season_id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    start_timestamp INTEGER NOT NULL,
+    end_timestamp INTEGER NOT NULL,
+    archived BOOLEAN NOT NULL
+);
+
+-- Final standings of each season, recalculated until the season is archived
+CREATE TABLE IF NOT EXISTS season_ratings (
+    season_id INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    losses INTEGER NOT NULL,
+    value REAL NOT NULL,
+    deviation REAL NOT NULL,
+    peak_value REAL,
+    peak_deviation REAL,
+    peak_timestamp INTEGER,
+    global_rank INTEGER,
+    character_rank INTEGER,
+    PRIMARY KEY(season_id, id, char_id)
 );
 
-CREATE TABLE vip_status (
+CREATE INDEX IF NOT EXISTS season_ratings_global_rank ON season_ratings(season_id, global_rank);
+CREATE INDEX IF NOT EXISTS season_ratings_character_rank ON season_ratings(season_id, char_id, character_rank);
+
+CREATE TABLE IF NOT EXISTS vip_status (
     id INTEGER NOT NULL,
     vip_status TEXT NOT NULL,
     notes TEXT NOT NULL,
     PRIMARY KEY(id)
 );
 
-CREATE TABLE cheater_status (
+CREATE TABLE IF NOT EXISTS cheater_status (
     id INTEGER NOT NULL,
     cheater_status TEXT NOT NULL,
     notes TEXT NOT NULL,
     PRIMARY KEY(id)
 );
 
-CREATE TABLE hidden_status (
+-- A row exists while the player is hidden, every hide and unhide is logged in hidden_status_history
+CREATE TABLE IF NOT EXISTS hidden_status (
     id INTEGER NOT NULL,
-    hidden_status TEXT,
+    hidden_status TEXT NOT NULL,
     notes TEXT NOT NULL,
-    code TEXT,
+    timestamp INTEGER NOT NULL,
+    PRIMARY KEY(id)
+);
+
+CREATE TABLE IF NOT EXISTS hidden_status_history (
+    id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    notes TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS hidden_status_history_id ON hidden_status_history(id, timestamp);
+
+
+-- Moderators authenticate to the admin API with their token
+CREATE TABLE IF NOT EXISTS moderators (
+    name TEXT NOT NULL,
+    token TEXT NOT NULL UNIQUE,
+    created INTEGER NOT NULL,
+    PRIMARY KEY(name)
+);
+
+-- Every cheater, VIP and hidden status change made by a moderator or from the command line
+CREATE TABLE IF NOT EXISTS moderation_log (
+    timestamp INTEGER NOT NULL,
+    moderator TEXT NOT NULL,
+    action TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    status TEXT,
+    notes TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS moderation_log_id ON moderation_log(id, timestamp);
+
+CREATE TRIGGER IF NOT EXISTS moderation_log_no_update BEFORE UPDATE ON moderation_log
+BEGIN
+    SELECT RAISE(ABORT, 'moderation_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS moderation_log_no_delete BEFORE DELETE ON moderation_log
+BEGIN
+    SELECT RAISE(ABORT, 'moderation_log is append-only');
+END;
+
+
+-- Cheat reports submitted by anyone, resolved by a moderator as confirmed or dismissed. Links are
+-- separated by newlines
+CREATE TABLE IF NOT EXISTS cheat_reports (
+    report_id INTEGER PRIMARY KEY,
+    id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    links TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    status TEXT NOT NULL,
+    resolved_by TEXT,
+    resolved_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS cheat_reports_id ON cheat_reports(id, status);
+CREATE INDEX IF NOT EXISTS cheat_reports_ip ON cheat_reports(ip, timestamp);
+
+
+-- Players whose recent games look improbable, written every statistics period for moderators to
+-- review. Nothing here marks anyone
+CREATE TABLE IF NOT EXISTS anomalies (
+    timestamp INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    score REAL NOT NULL,
+    explanation TEXT NOT NULL,
+    PRIMARY KEY(timestamp, id, kind)
+);
+
+
+-- Characters whose first games went far better than their rating expected, see detect_smurfs
+CREATE TABLE IF NOT EXISTS smurf_flags (
+    id INTEGER NOT NULL,
+    char_id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    game_count INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    expected_wins REAL NOT NULL,
+    z REAL NOT NULL,
+    PRIMARY KEY(id, char_id)
+);
+
+-- Players a flagged smurf is likely an alt of, with the parts of the score
+CREATE TABLE IF NOT EXISTS alt_links (
+    id INTEGER NOT NULL,
+    alt_id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    score REAL NOT NULL,
+    name_similarity REAL NOT NULL,
+    shared_opponents REAL NOT NULL,
+    hour_overlap REAL NOT NULL,
+    PRIMARY KEY(id, alt_id)
+);
+
+-- Contiguous play periods of a player, split by idle gaps longer than the session gap. Games from
+-- the late_ columns are the ones played after the first few of the session
+CREATE TABLE IF NOT EXISTS player_sessions (
+    id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    game_count INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    expected_wins REAL NOT NULL,
+    rating_change REAL NOT NULL,
+    opponent_count INTEGER NOT NULL,
+    late_games INTEGER NOT NULL,
+    late_wins INTEGER NOT NULL,
+    late_expected_wins REAL NOT NULL,
+    loss_streak INTEGER NOT NULL,
+    longest_loss_streak INTEGER NOT NULL,
+    PRIMARY KEY(id, start)
+);
+
+-- Opponents of each player's latest session, used to count the distinct ones
+CREATE TABLE IF NOT EXISTS player_session_opponents (
+    id INTEGER NOT NULL,
+    opponent_id INTEGER NOT NULL,
+    PRIMARY KEY(id, opponent_id)
+);
+
+
+-- Games per player and UTC day, day being the timestamp of its midnight
+CREATE TABLE IF NOT EXISTS player_daily_activity (
+    id INTEGER NOT NULL,
+    day INTEGER NOT NULL,
+    game_count INTEGER NOT NULL,
+    PRIMARY KEY(id, day)
+);
+
+-- Games per player by UTC day of the week, 0 being Monday, and hour of the day
+CREATE TABLE IF NOT EXISTS player_hourly_activity (
+    id INTEGER NOT NULL,
+    weekday INTEGER NOT NULL,
+    hour INTEGER NOT NULL,
+    game_count INTEGER NOT NULL,
+    PRIMARY KEY(id, weekday, hour)
+);
+
+-- Games and distinct players per UTC day across everyone
+CREATE TABLE IF NOT EXISTS daily_activity (
+    day INTEGER NOT NULL,
+    game_count INTEGER NOT NULL,
+    player_count INTEGER NOT NULL,
+    PRIMARY KEY(day)
+);
+
+-- Games by UTC day of the week and hour of the day across everyone
+CREATE TABLE IF NOT EXISTS hourly_activity (
+    weekday INTEGER NOT NULL,
+    hour INTEGER NOT NULL,
+    game_count INTEGER NOT NULL,
+    PRIMARY KEY(weekday, hour)
+);
+
+
+-- Regions are declared by the players themselves, confirmed with a code in their R-Code comment
+CREATE TABLE IF NOT EXISTS player_regions (
+    id INTEGER NOT NULL,
+    region TEXT NOT NULL,
+    PRIMARY KEY(id)
+);
+
+-- Pending confirmations of hide, unhide and region changes. A code is valid until expires
+CREATE TABLE IF NOT EXISTS verifications (
+    id INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    code TEXT NOT NULL,
+    payload TEXT,
+    created INTEGER NOT NULL,
+    expires INTEGER NOT NULL,
+    PRIMARY KEY(id, action)
+);
+
+-- The last time each player's R-Code comment was looked at, it is looked at most once per poll
+-- interval whatever the verification
+CREATE TABLE IF NOT EXISTS verification_polls (
+    id INTEGER NOT NULL,
+    last_poll INTEGER NOT NULL,
     PRIMARY KEY(id)
 );
 
+-- Every verification started, to limit how many can be started for a player in a day
+CREATE TABLE IF NOT EXISTS verification_starts (
+    id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS verification_starts_id ON verification_starts(id, timestamp);
 
-CREATE TABLE config (
+-- The roster, new characters are added with the add_character command
+CREATE TABLE IF NOT EXISTS characters (
+    char_id INTEGER NOT NULL,
+    short TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    PRIMARY KEY(char_id)
+);
+
+INSERT OR IGNORE INTO characters VALUES
+    (0, 'SO', 'Sol'),
+    (1, 'KY', 'Ky'),
+    (2, 'MA', 'May'),
+    (3, 'AX', 'Axl'),
+    (4, 'CH', 'Chipp'),
+    (5, 'PO', 'Potemkin'),
+    (6, 'FA', 'Faust'),
+    (7, 'MI', 'Millia'),
+    (8, 'ZA', 'Zato-1'),
+    (9, 'RA', 'Ramlethal'),
+    (10, 'LE', 'Leo'),
+    (11, 'NA', 'Nagoriyuki'),
+    (12, 'GI', 'Giovanna'),
+    (13, 'AN', 'Anji'),
+    (14, 'IN', 'I-No'),
+    (15, 'GO', 'Goldlewis'),
+    (16, 'JC', 'Jack-O'''),
+    (17, 'HA', 'Happy Chaos'),
+    (18, 'BA', 'Baiken'),
+    (19, 'TE', 'Testament'),
+    (20, 'BI', 'Bridget'),
+    (21, 'SI', 'Sin'),
+    (22, 'BE', 'Bedman?'),
+    (23, 'AS', 'Asuka'),
+    (24, 'JN', 'Johnny'),
+    (25, 'EL', 'Elphelt'),
+    (26, 'AB', 'A.B.A.');
+
+-- Character ids seen in games that aren't in the roster yet
+CREATE TABLE IF NOT EXISTS unknown_characters (
+    char_id INTEGER NOT NULL,
+    first_seen INTEGER NOT NULL,
+    PRIMARY KEY(char_id)
+);
+
+CREATE TABLE IF NOT EXISTS config (
     last_update INTEGER NOT NULL
 );
 
-CREATE TABLE hits (
+-- Settings of the rating update, changed with the set_ commands once a backtest shows they help.
+-- A NULL seeding_weight starts every new character at the default rating
+CREATE TABLE IF NOT EXISTS rating_config (
+    seeding_weight REAL,
+    seeding_deviation REAL NOT NULL,
+    floor_priors BOOLEAN NOT NULL,
+    smurf_policy TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS hits (
     page TEXT NOT NULL,
     hit_count INTEGER NOT NULL,
     PRIMARY KEY(page)
 );
 
-INSERT INTO config VALUES(1675132574);
+INSERT INTO config SELECT 1675132574 WHERE NOT EXISTS(SELECT 1 FROM config);
+INSERT INTO rating_config SELECT NULL, 250.0, 0, 'fast_convergence' WHERE NOT EXISTS(SELECT 1 FROM rating_config);
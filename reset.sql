@@ -7,11 +7,29 @@ DELETE FROM global_matchups;
 DELETE FROM top_1000_matchups;
 DELETE FROM proportional_matchups;
 DELETE FROM top_100_matchups;
+DELETE FROM bracket_matchups;
+DELETE FROM estimated_matchups;
+DELETE FROM tier_list;
 DELETE FROM player_names;
+DELETE FROM player_name_search;
 DELETE FROM ranking_character;
 DELETE FROM ranking_global;
+DELETE FROM ranking_character_platform;
+DELETE FROM ranking_global_platform;
+DELETE FROM ranking_character_history;
+DELETE FROM ranking_global_history;
+DELETE FROM season_ratings;
+UPDATE seasons SET archived = 0;
 DELETE FROM player_rating_distribution;
 DELETE FROM player_floor_distribution;
+DELETE FROM rating_percentiles;
+DELETE FROM rating_percentile_counts;
+DELETE FROM player_sessions;
+DELETE FROM player_session_opponents;
+DELETE FROM player_daily_activity;
+DELETE FROM player_hourly_activity;
+DELETE FROM daily_activity;
+DELETE FROM hourly_activity;
 
 DELETE FROM config;
 INSERT INTO config VALUES(1635717600);
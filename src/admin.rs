@@ -0,0 +1,611 @@
+use crate::{
+    api::{self, format_date, RatingDiffStats},
+    glicko::Rating,
+    rater,
+    website::{self, RatingsDbConn},
+};
+use rocket::{
+    http::Status,
+    outcome::try_outcome,
+    request::{FromRequest, Outcome, Request},
+    serde::{json::Json, Deserialize, Serialize},
+};
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// A moderator, authenticated with `Authorization: Bearer <token>`. Tokens are created with the
+/// add_moderator command.
+pub struct Moderator {
+    name: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Moderator {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let token = match req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+        {
+            Some(token) => token.to_owned(),
+            None => return Outcome::Failure((Status::Unauthorized, ())),
+        };
+
+        let conn = try_outcome!(req.guard::<RatingsDbConn>().await);
+        let name: Option<String> = conn
+            .run(move |conn| {
+                conn.query_row(
+                    "SELECT name FROM moderators WHERE token = ?",
+                    params![token],
+                    |r| r.get(0),
+                )
+                .optional()
+                .unwrap()
+            })
+            .await;
+
+        match name {
+            Some(name) => Outcome::Success(Moderator { name }),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ModerationEntry {
+    id: String,
+    name: Option<String>,
+    status: String,
+    notes: String,
+}
+
+#[derive(Serialize)]
+pub struct ModerationLogEntry {
+    date: String,
+    moderator: String,
+    action: String,
+    kind: String,
+    id: String,
+    status: Option<String>,
+    notes: String,
+}
+
+#[derive(Deserialize)]
+pub struct ModerationRequest {
+    status: Option<String>,
+    #[serde(default)]
+    notes: String,
+}
+
+#[derive(Deserialize)]
+pub struct NotesRequest {
+    notes: String,
+}
+
+/// Lists every player with a cheater, VIP or hidden status.
+#[get("/api/admin/<kind>")]
+pub async fn list_status(
+    _moderator: Moderator,
+    conn: RatingsDbConn,
+    kind: &str,
+) -> Option<Json<Vec<ModerationEntry>>> {
+    let table = rater::moderation_table(kind)?;
+
+    Some(Json(
+        conn.run(move |conn| {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {table}.id, name, {table}, notes
+                    FROM {table}
+                    LEFT JOIN players ON players.id = {table}.id
+                    ORDER BY name"
+                ))
+                .unwrap();
+            stmt.query_map([], |r| {
+                Ok(ModerationEntry {
+                    id: format!("{:X}", r.get::<_, i64>(0)?),
+                    name: r.get(1)?,
+                    status: r.get(2)?,
+                    notes: r.get(3)?,
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+        })
+        .await,
+    ))
+}
+
+/// Marks a player. The status defaults to "VIP" and "hidden", a cheater needs a cheater type.
+#[post("/api/admin/<kind>/<player>", data = "<request>")]
+pub async fn mark_status(
+    moderator: Moderator,
+    conn: RatingsDbConn,
+    kind: &str,
+    player: &str,
+    request: Json<ModerationRequest>,
+) -> Option<Json<ModerationEntry>> {
+    rater::moderation_table(kind)?;
+    let id = i64::from_str_radix(player, 16).ok()?;
+    let ModerationRequest { status, notes } = request.into_inner();
+    let status = match (kind, status) {
+        (_, Some(status)) => status,
+        ("vip", None) => "VIP".to_owned(),
+        ("hidden", None) => "hidden".to_owned(),
+        _ => return None,
+    };
+    let kind = kind.to_owned();
+
+    conn.run(move |conn| {
+        let tx = conn.transaction().unwrap();
+        rater::set_status(&tx, &moderator.name, &kind, id, &status, &notes).unwrap();
+        tx.commit().unwrap();
+        get_status(conn, &kind, id)
+    })
+    .await
+    .map(Json)
+}
+
+#[delete("/api/admin/<kind>/<player>?<notes>")]
+pub async fn unmark_status(
+    moderator: Moderator,
+    conn: RatingsDbConn,
+    kind: &str,
+    player: &str,
+    notes: Option<&str>,
+) -> Option<Json<bool>> {
+    rater::moderation_table(kind)?;
+    let id = i64::from_str_radix(player, 16).ok()?;
+    let kind = kind.to_owned();
+    let notes = notes.unwrap_or("").to_owned();
+
+    Some(Json(
+        conn.run(move |conn| {
+            rater::clear_status(conn, &moderator.name, &kind, id, &notes).unwrap()
+        })
+        .await,
+    ))
+}
+
+#[put("/api/admin/<kind>/<player>/notes", data = "<request>")]
+pub async fn edit_status_notes(
+    moderator: Moderator,
+    conn: RatingsDbConn,
+    kind: &str,
+    player: &str,
+    request: Json<NotesRequest>,
+) -> Option<Json<ModerationEntry>> {
+    rater::moderation_table(kind)?;
+    let id = i64::from_str_radix(player, 16).ok()?;
+    let kind = kind.to_owned();
+
+    conn.run(move |conn| {
+        if !rater::set_status_notes(conn, &moderator.name, &kind, id, &request.notes).unwrap() {
+            return None;
+        }
+        get_status(conn, &kind, id)
+    })
+    .await
+    .map(Json)
+}
+
+/// The moderation log, newest first, optionally for a single player.
+#[get("/api/admin/log?<player>&<count>")]
+pub async fn moderation_log(
+    _moderator: Moderator,
+    conn: RatingsDbConn,
+    player: Option<&str>,
+    count: Option<i64>,
+) -> Option<Json<Vec<ModerationLogEntry>>> {
+    let id = match player {
+        Some(player) => Some(i64::from_str_radix(player, 16).ok()?),
+        None => None,
+    };
+    let count = count.unwrap_or(100).clamp(1, 1000);
+
+    Some(Json(
+        conn.run(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT timestamp, moderator, action, kind, id, status, notes
+                    FROM moderation_log
+                    WHERE ?1 IS NULL OR id = ?1
+                    ORDER BY timestamp DESC
+                    LIMIT ?2",
+                )
+                .unwrap();
+            stmt.query_map(params![id, count], |r| {
+                Ok(ModerationLogEntry {
+                    date: format_date(r.get(0)?),
+                    moderator: r.get(1)?,
+                    action: r.get(2)?,
+                    kind: r.get(3)?,
+                    id: format!("{:X}", r.get::<_, i64>(4)?),
+                    status: r.get(5)?,
+                    notes: r.get(6)?,
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+        })
+        .await,
+    ))
+}
+
+fn get_status(conn: &Connection, kind: &str, id: i64) -> Option<ModerationEntry> {
+    let table = rater::moderation_table(kind)?;
+
+    conn.query_row(
+        &format!(
+            "SELECT name, {table}, notes
+            FROM {table}
+            LEFT JOIN players ON players.id = {table}.id
+            WHERE {table}.id = ?"
+        ),
+        params![id],
+        |r| {
+            Ok(ModerationEntry {
+                id: format!("{:X}", id),
+                name: r.get(0)?,
+                status: r.get(1)?,
+                notes: r.get(2)?,
+            })
+        },
+    )
+    .optional()
+    .unwrap()
+}
+
+/// How far above the player an opponent has to be rated for a win against them to be an upset.
+const UPSET_MARGIN: f64 = 300.0;
+/// Most players shown in the report queue.
+const REPORT_QUEUE_SIZE: i64 = 100;
+
+#[derive(Serialize)]
+pub struct ReportedPlayer {
+    id: String,
+    name: Option<String>,
+    cheater_status: Option<String>,
+    report_count: i64,
+    reports: Vec<Report>,
+    signals: CheatSignals,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    report_id: i64,
+    date: String,
+    reason: String,
+    links: Vec<String>,
+    status: String,
+    resolved_by: Option<String>,
+}
+
+/// What the games of a reported player say on their own.
+#[derive(Serialize)]
+pub struct CheatSignals {
+    upset_games: i64,
+    upset_wins: i64,
+    expected_upset_wins: f64,
+    longest_upset_streak: i64,
+    rating_experience: RatingDiffStats,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    cheater_status: Option<String>,
+    #[serde(default)]
+    notes: String,
+}
+
+/// Players with open cheat reports, most reported first.
+#[get("/api/admin/reports")]
+pub async fn report_queue(_moderator: Moderator, conn: RatingsDbConn) -> Json<Vec<ReportedPlayer>> {
+    Json(
+        conn.run(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id FROM cheat_reports
+                    WHERE status = 'open'
+                    GROUP BY id
+                    ORDER BY COUNT(*) DESC, MIN(timestamp)
+                    LIMIT ?",
+                )
+                .unwrap();
+            let ids = stmt
+                .query_map(params![REPORT_QUEUE_SIZE], |r| r.get(0))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect::<Vec<i64>>();
+
+            ids.into_iter()
+                .map(|id| reported_player(conn, id, true))
+                .collect()
+        })
+        .await,
+    )
+}
+
+/// Every report against a player, open or resolved.
+#[get("/api/admin/reports/<player>")]
+pub async fn player_reports(
+    _moderator: Moderator,
+    conn: RatingsDbConn,
+    player: &str,
+) -> Option<Json<ReportedPlayer>> {
+    let id = i64::from_str_radix(player, 16).ok()?;
+
+    Some(Json(
+        conn.run(move |conn| reported_player(conn, id, false)).await,
+    ))
+}
+
+/// Confirms the open reports against a player and marks them as a cheater, or dismisses them when
+/// no cheater status is given.
+#[post("/api/admin/reports/<player>/resolve", data = "<request>")]
+pub async fn resolve_reports(
+    moderator: Moderator,
+    conn: RatingsDbConn,
+    player: &str,
+    request: Json<ResolveRequest>,
+) -> Option<Json<usize>> {
+    let id = i64::from_str_radix(player, 16).ok()?;
+    let ResolveRequest {
+        cheater_status,
+        notes,
+    } = request.into_inner();
+
+    Some(Json(
+        conn.run(move |conn| {
+            rater::resolve_reports(conn, &moderator.name, id, cheater_status.as_deref(), &notes)
+                .unwrap()
+        })
+        .await,
+    ))
+}
+
+#[derive(Serialize)]
+pub struct Anomaly {
+    id: String,
+    name: Option<String>,
+    cheater_status: Option<String>,
+    kind: String,
+    score: f64,
+    explanation: String,
+}
+
+/// The players flagged by the last anomaly scan, highest score first.
+#[get("/api/admin/anomalies")]
+pub async fn anomalies(_moderator: Moderator, conn: RatingsDbConn) -> Json<Vec<Anomaly>> {
+    Json(
+        conn.run(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT anomalies.id, name, cheater_status, kind, score, explanation
+                    FROM anomalies
+                    LEFT JOIN players ON players.id = anomalies.id
+                    LEFT JOIN cheater_status ON cheater_status.id = anomalies.id
+                    WHERE timestamp = (SELECT MAX(timestamp) FROM anomalies)
+                    ORDER BY score DESC",
+                )
+                .unwrap();
+            stmt.query_map([], |r| {
+                Ok(Anomaly {
+                    id: format!("{:X}", r.get::<_, i64>(0)?),
+                    name: r.get(1)?,
+                    cheater_status: r.get(2)?,
+                    kind: r.get(3)?,
+                    score: r.get(4)?,
+                    explanation: r.get(5)?,
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+        })
+        .await,
+    )
+}
+
+#[derive(Serialize)]
+pub struct SmurfFlag {
+    id: String,
+    name: Option<String>,
+    character: String,
+    date: String,
+    game_count: i64,
+    wins: i64,
+    expected_wins: f64,
+    z: f64,
+    alts: Vec<AltLink>,
+}
+
+#[derive(Serialize)]
+pub struct AltLink {
+    id: String,
+    name: Option<String>,
+    score: f64,
+    name_similarity: f64,
+    shared_opponents: f64,
+    hour_overlap: f64,
+}
+
+/// Flagged smurfs, newest first, with the players they are likely alts of.
+#[get("/api/admin/smurfs?<count>")]
+pub async fn smurfs(
+    _moderator: Moderator,
+    conn: RatingsDbConn,
+    count: Option<i64>,
+) -> Json<Vec<SmurfFlag>> {
+    let count = count.unwrap_or(100).clamp(1, 1000);
+
+    Json(
+        conn.run(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT smurf_flags.id, name, char_id, timestamp, game_count, wins, expected_wins, z
+                    FROM smurf_flags
+                    LEFT JOIN players ON players.id = smurf_flags.id
+                    ORDER BY timestamp DESC, z DESC
+                    LIMIT ?",
+                )
+                .unwrap();
+            let mut alt_stmt = conn
+                .prepare(
+                    "SELECT alt_id, name, score, name_similarity, shared_opponents, hour_overlap
+                    FROM alt_links
+                    LEFT JOIN players ON players.id = alt_links.alt_id
+                    WHERE alt_links.id = ?
+                    ORDER BY score DESC",
+                )
+                .unwrap();
+
+            let mut rows = stmt.query(params![count]).unwrap();
+            let mut flags = Vec::new();
+            while let Some(row) = rows.next().unwrap() {
+                let id: i64 = row.get(0).unwrap();
+                let alts = alt_stmt
+                    .query_map(params![id], |r| {
+                        Ok(AltLink {
+                            id: format!("{:X}", r.get::<_, i64>(0)?),
+                            name: r.get(1)?,
+                            score: r.get(2)?,
+                            name_similarity: r.get(3)?,
+                            shared_opponents: r.get(4)?,
+                            hour_overlap: r.get(5)?,
+                        })
+                    })
+                    .unwrap()
+                    .map(|r| r.unwrap())
+                    .collect();
+
+                flags.push(SmurfFlag {
+                    id: format!("{:X}", id),
+                    name: row.get(1).unwrap(),
+                    character: website::character(row.get::<_, usize>(2).unwrap())
+                        .1
+                        .to_owned(),
+                    date: format_date(row.get(3).unwrap()),
+                    game_count: row.get(4).unwrap(),
+                    wins: row.get(5).unwrap(),
+                    expected_wins: row.get(6).unwrap(),
+                    z: row.get(7).unwrap(),
+                    alts,
+                });
+            }
+            flags
+        })
+        .await,
+    )
+}
+
+fn reported_player(conn: &Connection, id: i64, open_only: bool) -> ReportedPlayer {
+    let name: Option<String> = conn
+        .query_row("SELECT name FROM players WHERE id = ?", params![id], |r| {
+            r.get(0)
+        })
+        .optional()
+        .unwrap();
+    let cheater_status: Option<String> = conn
+        .query_row(
+            "SELECT cheater_status FROM cheater_status WHERE id = ?",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()
+        .unwrap();
+
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT report_id, timestamp, reason, links, status, resolved_by
+            FROM cheat_reports
+            WHERE id = ? AND (status = 'open' OR NOT ?)
+            ORDER BY timestamp DESC",
+        )
+        .unwrap();
+    let reports = stmt
+        .query_map(params![id, open_only], |r| {
+            Ok(Report {
+                report_id: r.get(0)?,
+                date: format_date(r.get(1)?),
+                reason: r.get(2)?,
+                links: r
+                    .get::<_, String>(3)?
+                    .lines()
+                    .map(|l| l.to_owned())
+                    .collect(),
+                status: r.get(4)?,
+                resolved_by: r.get(5)?,
+            })
+        })
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<_>>();
+
+    ReportedPlayer {
+        id: format!("{:X}", id),
+        name,
+        cheater_status,
+        report_count: reports.len() as i64,
+        reports,
+        signals: cheat_signals(conn, id),
+    }
+}
+
+/// Counts the player's wins against opponents rated at least UPSET_MARGIN above them, next to how
+/// many of those games their rating says they should have won.
+fn cheat_signals(conn: &Connection, id: i64) -> CheatSignals {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT id_a, value_a, deviation_a, value_b, deviation_b, winner
+            FROM game_ratings
+            WHERE (id_a = ?1 OR id_b = ?1) AND valid
+            ORDER BY timestamp",
+        )
+        .unwrap();
+    let mut rows = stmt.query(params![id]).unwrap();
+
+    let mut upset_games = 0;
+    let mut upset_wins = 0;
+    let mut expected_upset_wins = 0.0;
+    let mut streak = 0;
+    let mut longest_upset_streak = 0;
+
+    while let Some(row) = rows.next().unwrap() {
+        let id_a: i64 = row.get(0).unwrap();
+        let rating_a = Rating::new(row.get(1).unwrap(), row.get(2).unwrap());
+        let rating_b = Rating::new(row.get(3).unwrap(), row.get(4).unwrap());
+        let winner: i64 = row.get(5).unwrap();
+
+        let (own, opponent, won) = if id_a == id {
+            (rating_a, rating_b, winner == 1)
+        } else {
+            (rating_b, rating_a, winner == 2)
+        };
+
+        if opponent.value - own.value < UPSET_MARGIN {
+            continue;
+        }
+
+        upset_games += 1;
+        expected_upset_wins += own.expected(opponent);
+        if won {
+            upset_wins += 1;
+            streak += 1;
+            longest_upset_streak = longest_upset_streak.max(streak);
+        } else {
+            streak = 0;
+        }
+    }
+
+    CheatSignals {
+        upset_games,
+        upset_wins,
+        expected_upset_wins,
+        longest_upset_streak,
+        rating_experience: api::player_rating_experience(conn, id),
+    }
+}
@@ -1,9 +1,13 @@
 use crate::ggst_api;
-use chrono::{Duration, NaiveDateTime, Utc};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
 use fxhash::FxHashMap;
 use rand::distributions::{Alphanumeric, DistString};
-use rocket::serde::{json::Json, Serialize};
+use rocket::{
+    http::Status,
+    serde::{json::Json, Deserialize, Serialize},
+};
 use rusqlite::{named_params, params, Connection, OptionalExtension};
+use std::{collections::BTreeMap, net::SocketAddr};
 
 use crate::{
     glicko,
@@ -44,6 +48,36 @@ fn to_platform_string(i: i64) -> &'static str {
     }
 }
 
+fn from_platform_string(s: &str) -> Option<i64> {
+    match s {
+        "PS" => Some(1),
+        "XB" => Some(2),
+        "PC" => Some(3),
+        _ => None,
+    }
+}
+
+/// Shown instead of the name of a hidden player.
+pub const HIDDEN_NAME: &str = "Hidden player";
+
+/// Whether the player chose to be hidden. Hidden players are left out of every list and search,
+/// their own pages only show a notice and their names and ids are redacted from the histories of
+/// other players. Queries over many players check `hidden_status IS NULL` instead.
+fn is_hidden(conn: &Connection, id: i64) -> bool {
+    conn.query_row(
+        "SELECT hidden_status IS NOT NULL FROM hidden_status WHERE id = ?",
+        params![id],
+        |r| r.get(0),
+    )
+    .optional()
+    .unwrap()
+    .unwrap_or(false)
+}
+
+pub async fn is_player_hidden(conn: &RatingsDbConn, id: i64) -> bool {
+    conn.run(move |conn| is_hidden(conn, id)).await
+}
+
 impl Activity {
     fn calculate(conn: &mut Connection, time_offset: i64) -> Self {
         let t = Utc::now().timestamp() - time_offset;
@@ -160,11 +194,54 @@ pub async fn stats_inner(conn: &RatingsDbConn) -> Stats {
     .await
 }
 
+#[derive(Serialize)]
+pub struct Patch {
+    id: i64,
+    timestamp: i64,
+    date: String,
+    label: String,
+    notes: Option<String>,
+}
+
+/// The patches released between `from` and `to`, to annotate time series with.
+fn get_patches(conn: &Connection, from: i64, to: i64) -> Vec<Patch> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT patch_id, timestamp, label, notes FROM patches
+            WHERE timestamp >= ? AND timestamp <= ?
+            ORDER BY timestamp ASC",
+        )
+        .unwrap();
+    let mut rows = stmt.query(params![from, to]).unwrap();
+
+    let mut patches = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        let timestamp: i64 = row.get(1).unwrap();
+        patches.push(Patch {
+            id: row.get(0).unwrap(),
+            timestamp,
+            date: format_date(timestamp),
+            label: row.get(2).unwrap(),
+            notes: row.get(3).unwrap(),
+        });
+    }
+    patches
+}
+
+#[get("/api/patches")]
+pub async fn patches(conn: RatingsDbConn) -> Json<Vec<Patch>> {
+    Json(patches_inner(&conn).await)
+}
+
+pub async fn patches_inner(conn: &RatingsDbConn) -> Vec<Patch> {
+    conn.run(|conn| get_patches(conn, i64::MIN, i64::MAX)).await
+}
+
 #[get("/api/daily_games?<length>")]
 pub async fn daily_games(
     conn: RatingsDbConn,
     length: Option<i64>,
-) -> Json<(Vec<String>, Vec<i64>, Vec<i64>)> {
+) -> Json<(Vec<String>, Vec<i64>, Vec<i64>, Vec<Patch>)> {
     Json(
         conn.run(move |conn| {
             let tx = conn.transaction().unwrap();
@@ -209,6 +286,11 @@ pub async fn daily_games(
                         .unwrap()
                     })
                     .collect(),
+                get_patches(
+                    &tx,
+                    then.and_hms_opt(0, 0, 0).unwrap().timestamp(),
+                    Utc::now().timestamp(),
+                ),
             )
         })
         .await,
@@ -219,7 +301,7 @@ pub async fn daily_games(
 pub async fn weekly_games(
     conn: RatingsDbConn,
     length: Option<i64>,
-) -> Json<(Vec<String>, Vec<i64>, Vec<i64>)> {
+) -> Json<(Vec<String>, Vec<i64>, Vec<i64>, Vec<Patch>)> {
     Json(
         conn.run(move |conn| {
             let tx = conn.transaction().unwrap();
@@ -264,6 +346,11 @@ pub async fn weekly_games(
                         .unwrap()
                     })
                     .collect(),
+                get_patches(
+                    &tx,
+                    then.and_hms_opt(0, 0, 0).unwrap().timestamp(),
+                    Utc::now().timestamp(),
+                ),
             )
         })
         .await,
@@ -274,7 +361,7 @@ pub async fn weekly_games(
 pub async fn daily_character_games(
     conn: RatingsDbConn,
     length: Option<i64>,
-) -> Json<(Vec<String>, Vec<String>, Vec<Vec<i64>>)> {
+) -> Json<(Vec<String>, Vec<String>, Vec<Vec<i64>>, Vec<Patch>)> {
     Json(
         conn.run(move |conn| {
             let tx = conn.transaction().unwrap();
@@ -288,8 +375,11 @@ pub async fn daily_character_games(
                     .take(length as usize)
                     .map(|date| date.format("%Y-%m-%d").to_string())
                     .collect(),
-                website::CHAR_NAMES.iter().map(|c| c.0.to_owned()).collect(),
-                (0..website::CHAR_NAMES.len())
+                website::char_names()
+                    .iter()
+                    .map(|c| c.0.to_owned())
+                    .collect(),
+                (0..website::char_names().len())
                     .map(|c| {
                         then.iter_days()
                             .take(length as usize)
@@ -309,6 +399,11 @@ pub async fn daily_character_games(
                             .collect()
                     })
                     .collect(),
+                get_patches(
+                    &tx,
+                    then.and_hms_opt(0, 0, 0).unwrap().timestamp(),
+                    Utc::now().timestamp(),
+                ),
             )
         })
         .await,
@@ -360,10 +455,10 @@ impl RankingPlayer {
             name,
             platform: to_platform_string(platform),
             id: format!("{:X}", rated_player.id),
-            character: website::CHAR_NAMES[rated_player.char_id as usize]
+            character: website::character(rated_player.char_id as usize)
                 .1
                 .to_owned(),
-            character_short: website::CHAR_NAMES[rated_player.char_id as usize]
+            character_short: website::character(rated_player.char_id as usize)
                 .0
                 .to_owned(),
             game_count: (rated_player.win_count + rated_player.loss_count) as i32,
@@ -375,37 +470,105 @@ impl RankingPlayer {
         }
     }
 }
-#[get("/api/top/all")]
-pub async fn top_all(conn: RatingsDbConn) -> Json<Vec<RankingPlayer>> {
-    Json(top_all_inner(&conn).await)
+/// Optional filters for the leaderboards. A date shows the snapshot from that day, otherwise a
+/// region and platform restrict the current rankings to those players. Snapshots aren't kept per
+/// region or platform, so a date can't be combined with the others.
+#[derive(FromForm, Serialize, Default)]
+pub struct RankingFilter {
+    pub date: Option<String>,
+    pub platform: Option<String>,
+    pub region: Option<String>,
+}
+
+impl RankingFilter {
+    /// Drops any filters that don't parse, and the region and platform along with a date, so the
+    /// page only echoes back the filters that were applied.
+    pub fn validated(self) -> Self {
+        let date = self.date.filter(|d| parse_date(d).is_some());
+        let undated = date.is_none();
+        Self {
+            date,
+            platform: self
+                .platform
+                .filter(|p| undated && from_platform_string(p).is_some()),
+            region: self.region.filter(|r| undated && get_region(r).is_some()),
+        }
+    }
+
+    /// The rankings the filters ask for, or BadRequest if a date is combined with the others.
+    fn parse(&self) -> std::result::Result<RankingScope, Status> {
+        let date = self.date.as_deref().and_then(parse_date);
+        let region = self.region.as_deref().and_then(get_region);
+        let platform = self.platform.as_deref().and_then(from_platform_string);
+        Ok(match (date, region, platform) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => return Err(Status::BadRequest),
+            (Some(timestamp), None, None) => RankingScope::Snapshot(timestamp),
+            (None, Some(region), platform) => RankingScope::Region(region, platform),
+            (None, None, Some(platform)) => RankingScope::Platform(platform),
+            (None, None, None) => RankingScope::Current,
+        })
+    }
+}
+
+enum RankingScope {
+    Snapshot(i64),
+    Region(&'static str, Option<i64>),
+    Platform(i64),
+    Current,
+}
+
+#[get("/api/top/all?<filter..>")]
+pub async fn top_all(
+    conn: RatingsDbConn,
+    filter: RankingFilter,
+) -> std::result::Result<Json<Vec<RankingPlayer>>, Status> {
+    top_all_filtered(&conn, &filter).await.map(Json)
+}
+
+pub async fn top_all_filtered(
+    conn: &RatingsDbConn,
+    filter: &RankingFilter,
+) -> std::result::Result<Vec<RankingPlayer>, Status> {
+    Ok(match filter.parse()? {
+        RankingScope::Snapshot(timestamp) => top_all_snapshot_inner(conn, timestamp).await,
+        RankingScope::Region(region, platform) => {
+            top_region_inner(conn, region, platform, None).await
+        }
+        RankingScope::Platform(platform) => top_platform_inner(conn, platform, None).await,
+        RankingScope::Current => top_all_inner(conn).await,
+    })
 }
 
 #[get("/api/player_rating/<player>")]
-pub async fn player_rating_all(conn: RatingsDbConn, player: &str) -> Json<Vec<Rating>> {
-    let id = i64::from_str_radix(&player, 16).unwrap();
-    let mut res = vec![Rating::default(); website::CHAR_NAMES.len()];
-    Json(
-        conn.run(move |conn| {
-            let mut stmt = conn
-                .prepare(
-                    "SELECT char_id, value, deviation 
+pub async fn player_rating_all(conn: RatingsDbConn, player: &str) -> Option<Json<Vec<Rating>>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    let mut res = vec![Rating::default(); website::char_names().len()];
+    conn.run(move |conn| {
+        if is_hidden(conn, id) {
+            return None;
+        }
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT char_id, value, deviation 
         FROM player_ratings
         WHERE id = ?",
-                )
-                .unwrap();
+            )
+            .unwrap();
 
-            let mut rows = stmt.query(params![id]).unwrap();
-            while let Some(row) = rows.next().unwrap() {
-                let char_id: usize = row.get(0).unwrap();
-                res[char_id] = Rating::new(row.get(1).unwrap(), row.get(2).unwrap());
+        let mut rows = stmt.query(params![id]).unwrap();
+        while let Some(row) = rows.next().unwrap() {
+            let char_id: usize = row.get(0).unwrap();
+            if let Some(slot) = res.get_mut(char_id) {
+                *slot = Rating::new(row.get(1).unwrap(), row.get(2).unwrap());
             }
+        }
 
-            res
-        })
-        .await,
-    )
+        Some(Json(res))
+    })
+    .await
 
-    //for char_id in 0..website::CHAR_NAMES.len() {
+    //for char_id in 0..website::char_names().len() {
     //    let conn.run(move |conn| {
     //        conn
     //            .query_row(
@@ -425,8 +588,11 @@ pub async fn player_rating(
     player: &str,
     character_short: &str,
 ) -> Option<Json<Rating>> {
-    let id = i64::from_str_radix(&player, 16).unwrap();
-    if let Some(char_id) = website::CHAR_NAMES
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    if let Some(char_id) = website::char_names()
         .iter()
         .position(|(c, _)| *c == character_short)
     {
@@ -453,14 +619,195 @@ pub async fn player_rating(
     }
 }
 
+#[derive(FromFormField, Clone, Copy, PartialEq)]
+pub enum HistoryResolution {
+    Day,
+    Week,
+    Game,
+}
+
+#[derive(Serialize)]
+pub struct RatingHistory {
+    points: Vec<RatingHistoryPoint>,
+    change_week: Option<f64>,
+    change_month: Option<f64>,
+    patches: Vec<Patch>,
+}
+
+#[derive(Serialize)]
+pub struct RatingHistoryPoint {
+    timestamp: i64,
+    value: f64,
+    deviation: f64,
+}
+
+#[get("/api/history/<player>/<character_short>?<from>&<to>&<resolution>")]
+pub async fn player_rating_history(
+    conn: RatingsDbConn,
+    player: &str,
+    character_short: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+    resolution: Option<HistoryResolution>,
+) -> Option<Json<RatingHistory>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    let char_id = website::char_names()
+        .iter()
+        .position(|(c, _)| *c == character_short)? as i64;
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or(i64::MAX);
+    let resolution = resolution.unwrap_or(HistoryResolution::Day);
+
+    conn.run(move |conn| {
+        let (value, deviation): (f64, f64) = conn
+            .query_row(
+                "SELECT value, deviation FROM player_ratings WHERE id=? AND char_id=?",
+                params![id, char_id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()
+            .unwrap()?;
+
+        let points = match resolution {
+            HistoryResolution::Day | HistoryResolution::Week => {
+                let mut stmt = conn
+                    .prepare_cached(
+                        "SELECT timestamp, value, deviation
+                        FROM daily_ratings
+                        WHERE id = ? AND char_id = ? AND timestamp >= ? AND timestamp <= ?
+                        ORDER BY timestamp ASC",
+                    )
+                    .unwrap();
+                let mut rows = stmt.query(params![id, char_id, from, to]).unwrap();
+
+                let mut points = Vec::<RatingHistoryPoint>::new();
+                while let Some(row) = rows.next().unwrap() {
+                    let point = RatingHistoryPoint {
+                        timestamp: row.get(0).unwrap(),
+                        value: row.get(1).unwrap(),
+                        deviation: row.get(2).unwrap(),
+                    };
+
+                    match points.last_mut() {
+                        Some(last)
+                            if resolution == HistoryResolution::Week
+                                && week(last.timestamp) == week(point.timestamp) =>
+                        {
+                            *last = point
+                        }
+                        _ => points.push(point),
+                    }
+                }
+                points
+            }
+            HistoryResolution::Game => {
+                let mut stmt = conn
+                    .prepare_cached(
+                        "SELECT timestamp, value_a AS value, deviation_a AS deviation
+                        FROM games NATURAL JOIN game_ratings
+                        WHERE games.id_a = :id AND games.char_a = :char_id
+                            AND timestamp >= :from AND timestamp <= :to
+
+                        UNION
+
+                        SELECT timestamp, value_b AS value, deviation_b AS deviation
+                        FROM games NATURAL JOIN game_ratings
+                        WHERE games.id_b = :id AND games.char_b = :char_id
+                            AND timestamp >= :from AND timestamp <= :to
+
+                        ORDER BY timestamp ASC",
+                    )
+                    .unwrap();
+                let mut rows = stmt
+                    .query(named_params! {
+                        ":id": id,
+                        ":char_id": char_id,
+                        ":from": from,
+                        ":to": to,
+                    })
+                    .unwrap();
+
+                let mut points = Vec::new();
+                while let Some(row) = rows.next().unwrap() {
+                    points.push(RatingHistoryPoint {
+                        timestamp: row.get(0).unwrap(),
+                        value: row.get(1).unwrap(),
+                        deviation: row.get(2).unwrap(),
+                    });
+                }
+                points
+            }
+        };
+
+        let now = Utc::now().timestamp();
+        let current = Rating::new(value, deviation);
+
+        Some(Json(RatingHistory {
+            change_week: get_rating_change_since(
+                conn,
+                id,
+                char_id,
+                current,
+                now - 7 * 24 * 60 * 60,
+            ),
+            change_month: get_rating_change_since(
+                conn,
+                id,
+                char_id,
+                current,
+                now - 30 * 24 * 60 * 60,
+            ),
+            patches: match (points.first(), points.last()) {
+                (Some(first), Some(last)) => get_patches(conn, first.timestamp, last.timestamp),
+                _ => Vec::new(),
+            },
+            points,
+        }))
+    })
+    .await
+}
+
+/// The change in rating since the last daily rating recorded at or before `since`. Only
+/// available when the player had a low deviation rating at that point.
+fn get_rating_change_since(
+    conn: &Connection,
+    id: i64,
+    char_id: i64,
+    current: Rating,
+    since: i64,
+) -> Option<f64> {
+    if current.deviation >= rater::LOW_DEVIATION {
+        return None;
+    }
+
+    conn.query_row(
+        "SELECT value
+        FROM daily_ratings
+        WHERE id = ? AND char_id = ? AND timestamp <= ?
+        ORDER BY timestamp DESC
+        LIMIT 1",
+        params![id, char_id, since],
+        |r| r.get::<_, f64>(0),
+    )
+    .optional()
+    .unwrap()
+    .map(|then| current.value - then)
+}
+
 #[get("/api/accuracy/<player>/<character_short>")]
 pub async fn player_rating_accuracy(
     conn: RatingsDbConn,
     player: &str,
     character_short: &str,
 ) -> Option<Json<Vec<f64>>> {
-    let id = i64::from_str_radix(&player, 16).unwrap();
-    if let Some(char_id) = website::CHAR_NAMES
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    if let Some(char_id) = website::char_names()
         .iter()
         .position(|(c, _)| *c == character_short)
     {
@@ -551,6 +898,7 @@ pub async fn top_all_inner(conn: &RatingsDbConn) -> Vec<RankingPlayer> {
                  LEFT JOIN vip_status ON vip_status.id = player_ratings.id
                  LEFT JOIN cheater_status ON cheater_status.id = player_ratings.id
                  LEFT JOIN hidden_status ON hidden_status.id = player_ratings.id
+                 WHERE hidden_status IS NULL
                  LIMIT 100",
             )
             .unwrap();
@@ -582,40 +930,274 @@ pub async fn top_all_inner(conn: &RatingsDbConn) -> Vec<RankingPlayer> {
     .await
 }
 
-#[get("/api/active_players")]
-pub async fn active_players(conn: RatingsDbConn) -> Json<Vec<i64>> {
-    Json(
-        conn.run(|conn| {
-            let now = Utc::now().timestamp();
+const ACTIVITY_WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+/// How many days of active players are reported globally.
+const ACTIVITY_DAYS: i64 = 14;
 
-            let mut res = Vec::new();
-            for i in 0..14 {
-                let before = now - i * 24 * 60 * 60;
-                let after = now - (i + 1) * 24 * 60 * 60;
-
-                res.push(
-                    conn.query_row(
-                        "SELECT COUNT(id) FROM
-                        (SELECT id_a AS id FROM games WHERE timestamp > ? AND timestamp < ?
-                        UNION
-                        SELECT id_b AS id FROM games WHERE timestamp > ? AND timestamp < ?)",
-                        params![after, before, after, before],
-                        |r| r.get(0),
-                    )
-                    .unwrap(),
-                );
-            }
+/// Games at a given time, `intensity` being relative to the busiest one of the series.
+#[derive(Serialize)]
+pub struct ActivityCount {
+    label: String,
+    game_count: i64,
+    intensity: f64,
+}
 
-            res
+/// One row of an activity heatmap, with the games of each UTC hour of that day of the week.
+#[derive(Serialize)]
+pub struct ActivityWeekday {
+    weekday: &'static str,
+    hours: Vec<ActivityCount>,
+}
+
+#[derive(Serialize)]
+pub struct ActiveDay {
+    date: String,
+    game_count: i64,
+    player_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct GlobalActivity {
+    heatmap: Vec<ActivityWeekday>,
+    weeks: Vec<ActivityCount>,
+    /// The latest days first, today included.
+    days: Vec<ActiveDay>,
+}
+
+#[derive(Serialize)]
+pub struct PlayerActivity {
+    heatmap: Vec<ActivityWeekday>,
+    weeks: Vec<ActivityCount>,
+    days_played: i64,
+    /// Consecutive days played up to today or yesterday.
+    current_streak: i64,
+    longest_streak: i64,
+    longest_streak_start: Option<String>,
+    longest_streak_end: Option<String>,
+}
+
+fn activity_intensity(game_count: i64, max: i64) -> f64 {
+    if max > 0 {
+        (100.0 * game_count as f64 / max as f64).round() / 100.0
+    } else {
+        0.0
+    }
+}
+
+fn day_timestamp(timestamp: i64) -> i64 {
+    NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .unwrap()
+        .date()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .timestamp()
+}
+
+/// The midnight of the Monday of the week `day` falls on.
+fn week_timestamp(day: i64) -> i64 {
+    let weekday = NaiveDateTime::from_timestamp_opt(day, 0)
+        .unwrap()
+        .weekday()
+        .num_days_from_monday() as i64;
+    day_timestamp(day) - weekday * 24 * 60 * 60
+}
+
+/// Turns games by day of the week and hour, as (weekday, hour, game count) rows, into a heatmap.
+fn activity_heatmap(rows: impl Iterator<Item = (usize, usize, i64)>) -> Vec<ActivityWeekday> {
+    let mut counts = [[0; 24]; 7];
+    for (weekday, hour, game_count) in rows {
+        counts[weekday][hour] = game_count;
+    }
+    let max = counts.iter().flatten().copied().max().unwrap_or(0);
+
+    counts
+        .iter()
+        .zip(ACTIVITY_WEEKDAYS)
+        .map(|(hours, weekday)| ActivityWeekday {
+            weekday,
+            hours: hours
+                .iter()
+                .enumerate()
+                .map(|(hour, &game_count)| ActivityCount {
+                    label: format!("{:02}:00", hour),
+                    game_count,
+                    intensity: activity_intensity(game_count, max),
+                })
+                .collect(),
         })
-        .await,
-    )
+        .collect()
+}
+
+/// Sums games by day, as (day, game count) rows, into the last `weeks` weeks up to the one of
+/// `now`. Weeks without games are kept so the series has no holes.
+fn activity_weeks(
+    rows: impl Iterator<Item = (i64, i64)>,
+    weeks: i64,
+    now: i64,
+) -> Vec<ActivityCount> {
+    const WEEK: i64 = 7 * 24 * 60 * 60;
+    let last_week = week_timestamp(now);
+    let first_week = last_week - (weeks - 1) * WEEK;
+
+    let mut counts = vec![0; weeks as usize];
+    for (day, game_count) in rows {
+        let week = week_timestamp(day);
+        if week >= first_week && week <= last_week {
+            counts[((week - first_week) / WEEK) as usize] += game_count;
+        }
+    }
+    let max = counts.iter().copied().max().unwrap_or(0);
+
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, game_count)| ActivityCount {
+            label: format_date(first_week + i as i64 * WEEK),
+            game_count,
+            intensity: activity_intensity(game_count, max),
+        })
+        .collect()
+}
+
+#[get("/api/activity?<weeks>")]
+pub async fn activity(conn: RatingsDbConn, weeks: Option<i64>) -> Json<GlobalActivity> {
+    Json(activity_inner(&conn, weeks.unwrap_or(26)).await)
+}
+
+/// When everyone plays, from the activity aggregates kept up to date by the rater.
+pub async fn activity_inner(conn: &RatingsDbConn, weeks: i64) -> GlobalActivity {
+    let weeks = weeks.clamp(1, 520);
+    let now = Utc::now().timestamp();
+
+    conn.run(move |conn| {
+        let mut stmt = conn
+            .prepare_cached("SELECT weekday, hour, game_count FROM hourly_activity")
+            .unwrap();
+        let heatmap = activity_heatmap(
+            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+                .unwrap()
+                .map(|r| r.unwrap()),
+        );
+
+        let mut stmt = conn
+            .prepare_cached("SELECT day, game_count FROM daily_activity WHERE day >= ?")
+            .unwrap();
+        let since = week_timestamp(now) - weeks * 7 * 24 * 60 * 60;
+        let weeks = activity_weeks(
+            stmt.query_map(params![since], |r| Ok((r.get(0)?, r.get(1)?)))
+                .unwrap()
+                .map(|r| r.unwrap()),
+            weeks,
+            now,
+        );
+
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT day, game_count, player_count FROM daily_activity
+                ORDER BY day DESC
+                LIMIT ?",
+            )
+            .unwrap();
+        let days = stmt
+            .query_map(params![ACTIVITY_DAYS], |r| {
+                Ok(ActiveDay {
+                    date: format_date(r.get(0)?),
+                    game_count: r.get(1)?,
+                    player_count: r.get(2)?,
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+
+        GlobalActivity {
+            heatmap,
+            weeks,
+            days,
+        }
+    })
+    .await
+}
+
+#[get("/api/activity/<player>?<weeks>")]
+pub async fn player_activity(
+    conn: RatingsDbConn,
+    player: &str,
+    weeks: Option<i64>,
+) -> Option<Json<PlayerActivity>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    let weeks = weeks.unwrap_or(52).clamp(1, 520);
+    let now = Utc::now().timestamp();
+
+    Some(Json(
+        conn.run(move |conn| get_player_activity(conn, id, weeks, now))
+            .await,
+    ))
+}
+
+/// When the player plays and for how many days in a row as of `now`, from the activity aggregates
+/// kept up to date by the rater.
+fn get_player_activity(conn: &Connection, id: i64, weeks: i64, now: i64) -> PlayerActivity {
+    const DAY: i64 = 24 * 60 * 60;
+
+    let mut stmt = conn
+        .prepare_cached("SELECT weekday, hour, game_count FROM player_hourly_activity WHERE id = ?")
+        .unwrap();
+    let heatmap = activity_heatmap(
+        stmt.query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap()),
+    );
+
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT day, game_count FROM player_daily_activity
+            WHERE id = ?
+            ORDER BY day ASC",
+        )
+        .unwrap();
+    let days: Vec<(i64, i64)> = stmt
+        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+
+    let mut longest: Option<(i64, i64)> = None;
+    let mut streak_start = 0;
+    for (i, &(day, _)) in days.iter().enumerate() {
+        if i == 0 || day - days[i - 1].0 != DAY {
+            streak_start = day;
+        }
+        if longest.map_or(true, |(start, end)| day - streak_start > end - start) {
+            longest = Some((streak_start, day));
+        }
+    }
+
+    let today = day_timestamp(now);
+    let current_streak = match days.last() {
+        Some(&(last, _)) if today - last <= DAY => (last - streak_start) / DAY + 1,
+        _ => 0,
+    };
+
+    PlayerActivity {
+        heatmap,
+        weeks: activity_weeks(days.iter().copied(), weeks, now),
+        days_played: days.len() as i64,
+        current_streak,
+        longest_streak: longest.map_or(0, |(start, end)| (end - start) / DAY + 1),
+        longest_streak_start: longest.map(|(start, _)| format_date(start)),
+        longest_streak_end: longest.map(|(_, end)| format_date(end)),
+    }
 }
 
 #[derive(Serialize)]
 pub struct PlayerLookupPlayer {
     id: String,
     name: String,
+    formerly_known_as: Option<String>,
     characters: Vec<PlayerLookupCharacter>,
 }
 
@@ -632,11 +1214,15 @@ pub async fn player_lookup(conn: RatingsDbConn, name: String) -> Json<Vec<Player
     Json(
         conn.run(move |conn| {
             let players = {
+                // The most recently used of the matching names is the one reported
                 let mut stmt = conn
                     .prepare(
-                        "SELECT id, name 
+                        "SELECT players.id, players.name, player_names.name, MAX(last_seen)
                     FROM player_names
-                    WHERE name LIKE ?
+                    JOIN players ON players.id = player_names.id
+                    LEFT JOIN hidden_status ON hidden_status.id = players.id
+                    WHERE player_names.name LIKE ? AND hidden_status IS NULL
+                    GROUP BY players.id
                     ",
                     )
                     .unwrap();
@@ -644,9 +1230,12 @@ pub async fn player_lookup(conn: RatingsDbConn, name: String) -> Json<Vec<Player
 
                 let mut players = Vec::new();
                 while let Some(row) = rows.next().unwrap() {
+                    let name: String = row.get(1).unwrap();
+                    let matched_name: String = row.get(2).unwrap();
                     players.push((
                         row.get::<_, i64>(0).unwrap(),
-                        row.get::<_, String>(1).unwrap(),
+                        (matched_name != name).then_some(matched_name),
+                        name,
                     ));
                 }
 
@@ -661,13 +1250,13 @@ pub async fn player_lookup(conn: RatingsDbConn, name: String) -> Json<Vec<Player
                         WHERE id = ? ",
                 )
                 .unwrap();
-            for (id, name) in players {
+            for (id, formerly_known_as, name) in players {
                 let mut rows = stmt.query(params![id]).unwrap();
 
                 let mut characters = Vec::new();
                 while let Some(row) = rows.next().unwrap() {
                     characters.push(PlayerLookupCharacter {
-                        shortname: website::CHAR_NAMES[row.get::<_, usize>(0).unwrap()].0,
+                        shortname: website::character(row.get::<_, usize>(0).unwrap()).0,
                         rating: row.get::<_, f64>(1).unwrap().round() as i64,
                         deviation: (row.get::<_, f64>(2).unwrap() * 2.0).round() as i64,
                         game_count: row.get(3).unwrap(),
@@ -677,6 +1266,7 @@ pub async fn player_lookup(conn: RatingsDbConn, name: String) -> Json<Vec<Player
                 r.push(PlayerLookupPlayer {
                     id: format!("{:X}", id),
                     name,
+                    formerly_known_as,
                     characters,
                 });
             }
@@ -690,6 +1280,7 @@ pub async fn player_lookup(conn: RatingsDbConn, name: String) -> Json<Vec<Player
 #[derive(Serialize)]
 pub struct SearchResultPlayer {
     name: String,
+    formerly_known_as: Option<String>,
     platform: &'static str,
     vip_status: Option<String>,
     cheater_status: Option<String>,
@@ -712,6 +1303,20 @@ pub async fn search_exact(conn: RatingsDbConn, name: String) -> Json<Vec<SearchR
     Json(search_inner(&conn, name, true).await)
 }
 
+/// How well a name matched the search, results are ordered by this before activity and rating.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+struct NameMatch {
+    /// 3 for the whole name, 2 for a prefix, 1 for anywhere else and 0 for a fuzzy match
+    kind: u8,
+    /// Dice coefficient of the trigrams, 1 for anything but fuzzy matches
+    similarity: f64,
+}
+
+/// Below this many players found by substring, fuzzy matches are searched as well.
+const FUZZY_SEARCH_MIN_RESULTS: usize = 20;
+/// Minimum trigram similarity of a fuzzy match.
+const FUZZY_SEARCH_SIMILARITY: f64 = 0.4;
+
 pub async fn search_inner(
     conn: &RatingsDbConn,
     search: String,
@@ -720,54 +1325,66 @@ pub async fn search_inner(
     if let Ok(res) = conn.run(move |c| {
         info!("Searching for {}", search);
 
+        let matches = name_matches(c, &search, exact)?;
+
         let mut stmt = c
-            .prepare(
+            .prepare_cached(
                 "SELECT * FROM
-                    player_names
-                    NATURAL JOIN players
+                    players
                     NATURAL JOIN player_ratings
-                    LEFT JOIN vip_status ON vip_status.id = player_names.id
-                    LEFT JOIN cheater_status ON cheater_status.id = player_names.id
-                    LEFT JOIN hidden_status ON hidden_status.id = player_names.id
-                    WHERE name LIKE ?
-                    ORDER BY wins DESC
-                    LIMIT 1000
+                    LEFT JOIN vip_status ON vip_status.id = players.id
+                    LEFT JOIN cheater_status ON cheater_status.id = players.id
+                    LEFT JOIN hidden_status ON hidden_status.id = players.id
+                    WHERE players.id = ? AND hidden_status IS NULL
                     ",
             )?;
 
-        let mut rows = if exact {
-            stmt.query(params![search])
-        } else {
-            stmt.query(params![format!("%{}%", search)])
-        }
-        .unwrap();
-
         let mut res = Vec::new();
 
-        while let Some(row) = rows.next().unwrap() {
-            let rating: Rating =
-                Rating::new(row.get("value").unwrap(), row.get("deviation").unwrap());
-            let platform: i64 = row.get("platform").unwrap();
-            res.push(SearchResultPlayer {
-                name: row.get("name").unwrap(),
-                platform: to_platform_string(platform),
-                id: format!("{:X}", row.get::<_, i64>("id").unwrap()),
-                character: website::CHAR_NAMES[row.get::<_, usize>("char_id").unwrap()]
-                    .1
-                    .to_owned(),
-                character_short: website::CHAR_NAMES[row.get::<_, usize>("char_id").unwrap()]
-                    .0
-                    .to_owned(),
-                rating_value: rating.value.round() as i64,
-                rating_deviation: (rating.deviation * 2.0).round() as i64,
-                game_count: row.get::<_, i32>("wins").unwrap()
-                    + row.get::<_, i32>("losses").unwrap(),
-                vip_status: row.get::<_, Option<String>>("vip_status").unwrap(),
-                cheater_status: row.get::<_, Option<String>>("cheater_status").unwrap(),
-                hidden_status: row.get::<_, Option<String>>("hidden_status").unwrap(),
-            });
+        for (id, (matched_name, name_match)) in matches {
+            let mut rows = stmt.query(params![id])?;
+            while let Some(row) = rows.next().unwrap() {
+                let rating: Rating =
+                    Rating::new(row.get("value").unwrap(), row.get("deviation").unwrap());
+                let platform: i64 = row.get("platform").unwrap();
+                let name: String = row.get("name").unwrap();
+                res.push((
+                    name_match,
+                    SearchResultPlayer {
+                        formerly_known_as: (matched_name != name).then(|| matched_name.clone()),
+                        name,
+                        platform: to_platform_string(platform),
+                        id: format!("{:X}", id),
+                        character: website::character(row.get::<_, usize>("char_id").unwrap())
+                            .1
+                            .to_owned(),
+                        character_short: website::character(
+                            row.get::<_, usize>("char_id").unwrap(),
+                        )
+                        .0
+                        .to_owned(),
+                        rating_value: rating.value.round() as i64,
+                        rating_deviation: (rating.deviation * 2.0).round() as i64,
+                        game_count: row.get::<_, i32>("wins").unwrap()
+                            + row.get::<_, i32>("losses").unwrap(),
+                        vip_status: row.get::<_, Option<String>>("vip_status").unwrap(),
+                        cheater_status: row.get::<_, Option<String>>("cheater_status").unwrap(),
+                        hidden_status: row.get::<_, Option<String>>("hidden_status").unwrap(),
+                    },
+                ));
+            }
         }
-        Result::Ok(res.into_iter().collect())
+
+        res.sort_by(|(match_a, a), (match_b, b)| {
+            match_b
+                .partial_cmp(match_a)
+                .unwrap()
+                .then(b.game_count.cmp(&a.game_count))
+                .then(b.rating_value.cmp(&a.rating_value))
+        });
+        res.truncate(1000);
+
+        Result::Ok(res.into_iter().map(|(_, player)| player).collect())
     })
     .await {
         res
@@ -776,9 +1393,288 @@ pub async fn search_inner(
     }
 }
 
-#[get("/api/top/<char_id>")]
-pub async fn top_char(conn: RatingsDbConn, char_id: i64) -> Json<Vec<RankingPlayer>> {
-    Json(top_char_inner(&conn, char_id).await)
+/// The players with a current or former name matching the search, with the best matching name
+/// of each. Exact searches compare the whole name, other searches go through the trigram index
+/// of the normalized names and fall back to fuzzy matching when there are few results.
+fn name_matches(
+    conn: &Connection,
+    search: &str,
+    exact: bool,
+) -> rusqlite::Result<FxHashMap<i64, (String, NameMatch)>> {
+    let mut matches = FxHashMap::<i64, (String, NameMatch)>::default();
+    let add = |matches: &mut FxHashMap<i64, (String, NameMatch)>,
+               id: i64,
+               name: String,
+               name_match: NameMatch| match matches.get(&id) {
+        Some((_, best)) if *best >= name_match => {}
+        _ => {
+            matches.insert(id, (name, name_match));
+        }
+    };
+
+    if exact {
+        let mut stmt =
+            conn.prepare_cached("SELECT id, name FROM player_names WHERE name LIKE ?")?;
+        let mut rows = stmt.query(params![search])?;
+        while let Some(row) = rows.next()? {
+            add(
+                &mut matches,
+                row.get(0)?,
+                row.get(1)?,
+                NameMatch {
+                    kind: 3,
+                    similarity: 1.0,
+                },
+            );
+        }
+        return Ok(matches);
+    }
+
+    let query = rater::normalize_name(search);
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+
+    // The trigram index can't look up anything shorter than a trigram, those are scanned
+    let mut stmt = if query.chars().count() >= 3 {
+        conn.prepare_cached(
+            "SELECT id, name, normalized_name FROM player_name_search
+            WHERE player_name_search MATCH ?
+            LIMIT 5000",
+        )?
+    } else {
+        conn.prepare_cached(
+            "SELECT id, name, normalized_name FROM player_name_search
+            WHERE normalized_name LIKE ?
+            LIMIT 5000",
+        )?
+    };
+    let mut rows = if query.chars().count() >= 3 {
+        stmt.query(params![quote(&query)])?
+    } else {
+        stmt.query(params![format!("%{}%", query)])?
+    };
+    while let Some(row) = rows.next()? {
+        let normalized_name: String = row.get(2)?;
+        let kind = if normalized_name == query {
+            3
+        } else if normalized_name.starts_with(&query) {
+            2
+        } else {
+            1
+        };
+        add(
+            &mut matches,
+            row.get(0)?,
+            row.get(1)?,
+            NameMatch {
+                kind,
+                similarity: 1.0,
+            },
+        );
+    }
+    drop(rows);
+
+    let query_trigrams = rater::trigrams(&query);
+    if matches.len() < FUZZY_SEARCH_MIN_RESULTS && !query_trigrams.is_empty() {
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, name, normalized_name FROM player_name_search
+            WHERE player_name_search MATCH ?
+            ORDER BY rank
+            LIMIT 1000",
+        )?;
+        let any_trigram = query_trigrams
+            .iter()
+            .map(|t| quote(t))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let mut rows = stmt.query(params![any_trigram])?;
+        while let Some(row) = rows.next()? {
+            let normalized_name: String = row.get(2)?;
+            let similarity =
+                rater::trigram_similarity(&rater::trigrams(&normalized_name), &query_trigrams);
+            if similarity >= FUZZY_SEARCH_SIMILARITY {
+                add(
+                    &mut matches,
+                    row.get(0)?,
+                    row.get(1)?,
+                    NameMatch {
+                        kind: 0,
+                        similarity,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+#[get("/api/top/<char_id>?<filter..>")]
+pub async fn top_char(
+    conn: RatingsDbConn,
+    char_id: i64,
+    filter: RankingFilter,
+) -> std::result::Result<Json<Vec<RankingPlayer>>, Status> {
+    top_char_filtered(&conn, char_id, &filter).await.map(Json)
+}
+
+pub async fn top_char_filtered(
+    conn: &RatingsDbConn,
+    char_id: i64,
+    filter: &RankingFilter,
+) -> std::result::Result<Vec<RankingPlayer>, Status> {
+    Ok(match filter.parse()? {
+        RankingScope::Snapshot(timestamp) => {
+            top_char_snapshot_inner(conn, char_id, timestamp).await
+        }
+        RankingScope::Region(region, platform) => {
+            top_region_inner(conn, region, platform, Some(char_id)).await
+        }
+        RankingScope::Platform(platform) => top_platform_inner(conn, platform, Some(char_id)).await,
+        RankingScope::Current => top_char_inner(conn, char_id).await,
+    })
+}
+
+/// The top 100 of the platform rankings, either overall or for a single character.
+pub async fn top_platform_inner(
+    conn: &RatingsDbConn,
+    platform: i64,
+    char_id: Option<i64>,
+) -> Vec<RankingPlayer> {
+    conn.run(move |c| {
+        let mut stmt = match char_id {
+            None => c.prepare_cached(
+                "SELECT
+                    player_ratings.id as id, player_ratings.char_id as char_id,
+                    wins, losses,
+                    value, deviation, last_decay,
+                    name, players.platform as platform,
+                    vip_status, cheater_status, hidden_status
+                 FROM ranking_global_platform AS ranking
+                 JOIN player_ratings ON
+                    player_ratings.id = ranking.id AND player_ratings.char_id = ranking.char_id
+                 JOIN players ON players.id = ranking.id
+                 LEFT JOIN vip_status ON vip_status.id = ranking.id
+                 LEFT JOIN cheater_status ON cheater_status.id = ranking.id
+                 LEFT JOIN hidden_status ON hidden_status.id = ranking.id
+                 WHERE ranking.platform = :platform AND hidden_status IS NULL
+                 ORDER BY global_rank ASC
+                 LIMIT 100",
+            ),
+            Some(_) => c.prepare_cached(
+                "SELECT
+                    player_ratings.id as id, player_ratings.char_id as char_id,
+                    wins, losses,
+                    value, deviation, last_decay,
+                    name, players.platform as platform,
+                    vip_status, cheater_status, hidden_status
+                 FROM ranking_character_platform AS ranking
+                 JOIN player_ratings ON
+                    player_ratings.id = ranking.id AND player_ratings.char_id = ranking.char_id
+                 JOIN players ON players.id = ranking.id
+                 LEFT JOIN vip_status ON vip_status.id = ranking.id
+                 LEFT JOIN cheater_status ON cheater_status.id = ranking.id
+                 LEFT JOIN hidden_status ON hidden_status.id = ranking.id
+                 WHERE ranking.platform = :platform
+                    AND ranking.char_id = :char_id
+                    AND hidden_status IS NULL
+                 ORDER BY character_rank ASC
+                 LIMIT 100",
+            ),
+        }
+        .unwrap();
+
+        let mut rows = match char_id {
+            None => stmt.query(named_params! { ":platform": platform }),
+            Some(char_id) => stmt.query(named_params! {
+                ":platform": platform,
+                ":char_id": char_id,
+            }),
+        }
+        .unwrap();
+
+        ranking_players(&mut rows)
+    })
+    .await
+}
+
+/// The top 100 of the players who have declared a region, optionally only those on a platform.
+/// Only a small fraction of players declare a region, so unlike the other rankings this is
+/// calculated on request.
+pub async fn top_region_inner(
+    conn: &RatingsDbConn,
+    region: &'static str,
+    platform: Option<i64>,
+    char_id: Option<i64>,
+) -> Vec<RankingPlayer> {
+    conn.run(move |c| {
+        let mut stmt = c
+            .prepare_cached(
+                "SELECT
+                    player_ratings.id as id, player_ratings.char_id as char_id,
+                    wins, losses,
+                    value, deviation, last_decay,
+                    name, platform, vip_status, cheater_status, hidden_status
+                 FROM player_regions
+                 JOIN player_ratings ON player_ratings.id = player_regions.id
+                 JOIN players ON players.id = player_regions.id
+                 LEFT JOIN vip_status ON vip_status.id = player_regions.id
+                 LEFT JOIN cheater_status ON cheater_status.id = player_regions.id
+                 LEFT JOIN hidden_status ON hidden_status.id = player_regions.id
+                 WHERE region = :region
+                    AND (:platform IS NULL OR platform = :platform)
+                    AND (:char_id IS NULL OR player_ratings.char_id = :char_id)
+                    AND deviation < :low_deviation
+                    AND cheater_status IS NULL
+                    AND hidden_status IS NULL
+                 ORDER BY value DESC
+                 LIMIT 100",
+            )
+            .unwrap();
+
+        let mut rows = stmt
+            .query(named_params! {
+                ":region": region,
+                ":platform": platform,
+                ":char_id": char_id,
+                ":low_deviation": rater::LOW_DEVIATION,
+            })
+            .unwrap();
+
+        ranking_players(&mut rows)
+    })
+    .await
+}
+
+fn ranking_players(rows: &mut rusqlite::Rows) -> Vec<RankingPlayer> {
+    let mut res = Vec::with_capacity(100);
+    let mut i = 1;
+    while let Some(row) = rows.next().unwrap() {
+        let name = row.get("name").unwrap();
+        let platform = row.get("platform").unwrap();
+        let vip_status = row.get("vip_status").unwrap();
+        let cheater_status = row.get("cheater_status").unwrap();
+        let hidden_status = row.get("hidden_status").unwrap();
+        res.push(RankingPlayer::from_db(
+            i,
+            name,
+            platform,
+            vip_status,
+            cheater_status,
+            hidden_status,
+            RatedPlayer::from_row(row),
+        ));
+        i += 1;
+    }
+
+    res
+}
+
+/// Looks up one of the regions players can declare by its short name.
+pub fn get_region(short: &str) -> Option<&'static str> {
+    website::REGIONS
+        .iter()
+        .find(|(r, _)| *r == short)
+        .map(|(r, _)| *r)
 }
 
 pub async fn top_char_inner(conn: &RatingsDbConn, char_id: i64) -> Vec<RankingPlayer> {
@@ -796,7 +1692,7 @@ pub async fn top_char_inner(conn: &RatingsDbConn, char_id: i64) -> Vec<RankingPl
                  LEFT JOIN vip_status ON vip_status.id = player_ratings.id
                  LEFT JOIN cheater_status ON cheater_status.id = player_ratings.id
                  LEFT JOIN hidden_status ON hidden_status.id = player_ratings.id
-                 WHERE char_id = ?
+                 WHERE char_id = ? AND hidden_status IS NULL
                  LIMIT 100
                  ",
             )
@@ -828,130 +1724,854 @@ pub async fn top_char_inner(conn: &RatingsDbConn, char_id: i64) -> Vec<RankingPl
     .await
 }
 
-#[derive(Serialize)]
-pub struct PlayerData {
-    name: String,
-    platform: String,
-    other_names: Option<Vec<String>>,
-    characters: Vec<PlayerCharacterData>,
+/// Number of the week a timestamp falls in.
+fn week(timestamp: i64) -> i64 {
+    //Weeks start on monday, the epoch was on a thursday
+    (timestamp - 4 * 24 * 60 * 60).div_euclid(7 * 24 * 60 * 60)
 }
 
-#[derive(Serialize)]
-pub struct PlayerDataChar {
-    id: String,
-    name: String,
-    platform: &'static str,
-    vip_status: Option<String>,
-    cheater_status: Option<String>,
-    other_names: Option<Vec<String>>,
-    other_characters: Vec<OtherPlayerCharacter>,
-    data: PlayerCharacterData,
-    pub hidden_status: Option<String>,
+/// Parses a `YYYY-MM-DD` date into the timestamp of its midnight.
+pub fn parse_date(date: &str) -> Option<i64> {
+    Some(
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .timestamp(),
+    )
 }
 
-#[derive(Serialize)]
-struct OtherPlayerCharacter {
-    character_name: String,
-    character_shortname: String,
-    rating_value: i64,
-    rating_deviation: i64,
-    game_count: i32,
+fn ranking_snapshot_players(
+    stmt: &mut rusqlite::CachedStatement,
+    params: impl rusqlite::Params,
+) -> Vec<RankingPlayer> {
+    let mut rows = stmt.query(params).unwrap();
+
+    let mut res = Vec::with_capacity(100);
+    while let Some(row) = rows.next().unwrap() {
+        let rated_player = RatedPlayer {
+            id: row.get("id").unwrap(),
+            char_id: row.get("char_id").unwrap(),
+            win_count: row.get("wins").unwrap(),
+            loss_count: row.get("losses").unwrap(),
+            rating: Rating::new(row.get("value").unwrap(), row.get("deviation").unwrap()),
+            last_decay: 0,
+            top_rating: None,
+            top_defeated: None,
+            character_rank: None,
+        };
+        res.push(RankingPlayer::from_db(
+            row.get("rank").unwrap(),
+            row.get("name").unwrap(),
+            row.get("platform").unwrap(),
+            row.get("vip_status").unwrap(),
+            row.get("cheater_status").unwrap(),
+            row.get("hidden_status").unwrap(),
+            rated_player,
+        ));
+    }
+
+    res
 }
 
-#[derive(Serialize)]
-struct PlayerCharacterData {
-    character_name: String,
-    rating_value: i64,
-    rating_deviation: i64,
-    global_rank: Option<i32>,
-    character_rank: Option<i32>,
+/// The global top 100 as it was on the last snapshot at or before `timestamp`.
+pub async fn top_all_snapshot_inner(conn: &RatingsDbConn, timestamp: i64) -> Vec<RankingPlayer> {
+    conn.run(move |c| {
+        let mut stmt = c
+            .prepare_cached(
+                "SELECT
+                    global_rank AS rank, ranking_global_history.id AS id, char_id,
+                    wins, losses, value, deviation,
+                    name, platform, vip_status, cheater_status, hidden_status
+                 FROM ranking_global_history
+                 NATURAL JOIN players
+                 LEFT JOIN vip_status ON vip_status.id = ranking_global_history.id
+                 LEFT JOIN cheater_status ON cheater_status.id = ranking_global_history.id
+                 LEFT JOIN hidden_status ON hidden_status.id = ranking_global_history.id
+                 WHERE hidden_status IS NULL AND timestamp = (
+                    SELECT MAX(timestamp) FROM ranking_global_history WHERE timestamp <= ?)
+                 ORDER BY global_rank ASC
+                 LIMIT 100",
+            )
+            .unwrap();
 
-    top_rating_value: Option<i64>,
-    top_rating_deviation: Option<i64>,
-    top_rating_timestamp: Option<String>,
+        ranking_snapshot_players(&mut stmt, params![timestamp])
+    })
+    .await
+}
 
-    top_defeated_id: Option<String>,
-    top_defeated_char_id: Option<&'static str>,
-    top_defeated_name: Option<String>,
-    top_defeated_value: Option<i64>,
-    top_defeated_deviation: Option<i64>,
-    top_defeated_floor: Option<String>,
-    top_defeated_timestamp: Option<String>,
+/// The character top 100 as it was on the last snapshot at or before `timestamp`.
+pub async fn top_char_snapshot_inner(
+    conn: &RatingsDbConn,
+    char_id: i64,
+    timestamp: i64,
+) -> Vec<RankingPlayer> {
+    conn.run(move |c| {
+        let mut stmt = c
+            .prepare_cached(
+                "SELECT
+                    character_rank AS rank, ranking_character_history.id AS id, char_id,
+                    wins, losses, value, deviation,
+                    name, platform, vip_status, cheater_status, hidden_status
+                 FROM ranking_character_history
+                 NATURAL JOIN players
+                 LEFT JOIN vip_status ON vip_status.id = ranking_character_history.id
+                 LEFT JOIN cheater_status ON cheater_status.id = ranking_character_history.id
+                 LEFT JOIN hidden_status ON hidden_status.id = ranking_character_history.id
+                 WHERE char_id = :char_id AND hidden_status IS NULL AND timestamp = (
+                    SELECT MAX(timestamp) FROM ranking_character_history
+                    WHERE char_id = :char_id AND timestamp <= :timestamp)
+                 ORDER BY character_rank ASC
+                 LIMIT 100",
+            )
+            .unwrap();
 
-    win_rate: f64,
-    game_count: i32,
-    matchups: Vec<PlayerMatchup>,
+        ranking_snapshot_players(
+            &mut stmt,
+            named_params! {
+                ":char_id": char_id,
+                ":timestamp": timestamp,
+            },
+        )
+    })
+    .await
 }
 
 #[derive(Serialize)]
-pub struct PlayerCharacterHistory {
-    history: Vec<PlayerSet>,
+pub struct RankHistory {
+    points: Vec<RankHistoryPoint>,
+    patches: Vec<Patch>,
+    best_global_rank: Option<i64>,
+    best_character_rank: Option<i64>,
+    days_first_global: i64,
+    days_first_character: i64,
 }
 
-const MATCHUP_MIN_GAMES: i64 = 250;
+#[derive(Serialize)]
+pub struct RankHistoryPoint {
+    timestamp: i64,
+    global_rank: Option<i64>,
+    character_rank: Option<i64>,
+}
 
 #[derive(Serialize)]
-struct PlayerSet {
-    timestamp: String,
-    own_rating_value: i64,
-    own_rating_deviation: i64,
-    floor: String,
-    opponent_name: String,
-    opponent_platform: &'static str,
-    opponent_vip: Option<&'static str>,
-    opponent_cheater: Option<&'static str>,
-    opponent_hidden: Option<&'static str>,
-    opponent_id: String,
-    opponent_character: &'static str,
-    opponent_character_short: &'static str,
-    opponent_rating_value: i64,
-    opponent_rating_deviation: i64,
-    expected_outcome: String,
-    rating_change: String,
-    rating_change_class: &'static str,
-    rating_change_sequence: String,
-    result_wins: i32,
-    result_losses: i32,
-    result_percent: f64,
+pub struct Percentiles {
+    overall: Option<Percentile>,
+    character: Option<Percentile>,
 }
 
 #[derive(Serialize)]
-struct PlayerMatchup {
-    character: String,
-    game_count: i64,
-    win_rate: f64,
-    rating_offset: String,
-    rating_deviation: i64,
-    rating: i64,
-    rating_change_class: &'static str,
+pub struct Percentile {
+    /// Percentage of the established players rated below this one.
+    percentile: f64,
+    player_count: i64,
+    /// Whether this was calculated from the player's rank rather than interpolated.
+    exact: bool,
 }
 
-pub async fn get_player_highest_rated_character(conn: &RatingsDbConn, id: i64) -> Option<i64> {
+#[get("/api/percentile/<player>/<character_short>")]
+pub async fn percentile(
+    conn: RatingsDbConn,
+    player: &str,
+    character_short: &str,
+) -> Option<Json<Percentiles>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    let char_id = website::char_names()
+        .iter()
+        .position(|(c, _)| *c == character_short)? as i64;
+
     conn.run(move |conn| {
-        conn.query_row(
-            "SELECT char_id
-        FROM player_ratings
-        WHERE id=?
-        ORDER BY value - 3.0  * deviation DESC
-        LIMIT 1",
-            params![id],
+        let (value, global_rank, character_rank): (f64, Option<i32>, Option<i32>) = conn
+            .query_row(
+                "SELECT value, global_rank, character_rank
+                FROM player_ratings
+                    LEFT JOIN ranking_global ON
+                        ranking_global.id = player_ratings.id AND
+                        ranking_global.char_id = player_ratings.char_id
+                    LEFT JOIN ranking_character ON
+                        ranking_character.id = player_ratings.id AND
+                        ranking_character.char_id = player_ratings.char_id
+                    WHERE player_ratings.id = ? AND player_ratings.char_id = ?",
+                params![id, char_id],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .optional()
+            .unwrap()?;
+
+        Some(Json(Percentiles {
+            overall: get_percentile(conn, None, value, global_rank),
+            character: get_percentile(conn, Some(char_id), value, character_rank),
+        }))
+    })
+    .await
+}
+
+/// The percentile of a rating among the established players of a character, or of all
+/// characters if `char_id` is `None`. Ranked players get an exact value from their rank, for
+/// everyone else it is interpolated from `rating_percentiles`.
+fn get_percentile(
+    conn: &Connection,
+    char_id: Option<i64>,
+    value: f64,
+    rank: Option<i32>,
+) -> Option<Percentile> {
+    let char_id = char_id.unwrap_or(-1);
+    let player_count: i64 = conn
+        .query_row(
+            "SELECT player_count FROM rating_percentile_counts WHERE char_id = ?",
+            params![char_id],
             |r| r.get(0),
         )
         .optional()
-        .unwrap()
+        .unwrap()?;
+
+    if let Some(rank) = rank {
+        return Some(Percentile {
+            percentile: (100.0 * (player_count - rank as i64) as f64 / player_count as f64)
+                .max(0.0),
+            player_count,
+            exact: true,
+        });
+    }
+
+    let below: Option<(i64, f64)> = conn
+        .query_row(
+            "SELECT permille, value FROM rating_percentiles
+            WHERE char_id = ? AND value <= ?
+            ORDER BY permille DESC LIMIT 1",
+            params![char_id, value],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()
+        .unwrap();
+    let above: Option<(i64, f64)> = conn
+        .query_row(
+            "SELECT permille, value FROM rating_percentiles
+            WHERE char_id = ? AND value > ?
+            ORDER BY permille ASC LIMIT 1",
+            params![char_id, value],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()
+        .unwrap();
+
+    let permille = match (below, above) {
+        (Some((p_lo, v_lo)), Some((p_hi, v_hi))) => {
+            p_lo as f64 + (value - v_lo) / (v_hi - v_lo) * (p_hi - p_lo) as f64
+        }
+        (Some(_), None) => 1000.0,
+        (None, _) => 0.0,
+    };
+
+    Some(Percentile {
+        percentile: permille / 10.0,
+        player_count,
+        exact: false,
     })
-    .await
 }
 
-#[derive(Serialize)]
-pub struct RecentSet {
-    timestamp: String,
-    player_a: SetPlayer,
-    player_b: SetPlayer,
-    win_chance: String,
-    wins_a: i64,
-    wins_b: i64,
-}
+#[get("/api/rank_history/<player>/<character_short>")]
+pub async fn rank_history(
+    conn: RatingsDbConn,
+    player: &str,
+    character_short: &str,
+) -> Option<Json<RankHistory>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    let char_id = website::char_names()
+        .iter()
+        .position(|(c, _)| *c == character_short)? as i64;
+
+    Some(Json(
+        conn.run(move |conn| {
+            let mut points = BTreeMap::<i64, RankHistoryPoint>::new();
+
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT timestamp, global_rank
+                    FROM ranking_global_history
+                    WHERE id = ? AND char_id = ?",
+                )
+                .unwrap();
+            let mut rows = stmt.query(params![id, char_id]).unwrap();
+            while let Some(row) = rows.next().unwrap() {
+                let timestamp: i64 = row.get(0).unwrap();
+                points
+                    .entry(timestamp)
+                    .or_insert(RankHistoryPoint {
+                        timestamp,
+                        global_rank: None,
+                        character_rank: None,
+                    })
+                    .global_rank = Some(row.get(1).unwrap());
+            }
+
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT timestamp, character_rank
+                    FROM ranking_character_history
+                    WHERE id = ? AND char_id = ?",
+                )
+                .unwrap();
+            let mut rows = stmt.query(params![id, char_id]).unwrap();
+            while let Some(row) = rows.next().unwrap() {
+                let timestamp: i64 = row.get(0).unwrap();
+                points
+                    .entry(timestamp)
+                    .or_insert(RankHistoryPoint {
+                        timestamp,
+                        global_rank: None,
+                        character_rank: None,
+                    })
+                    .character_rank = Some(row.get(1).unwrap());
+            }
+
+            let points: Vec<_> = points.into_values().collect();
+
+            RankHistory {
+                best_global_rank: points.iter().filter_map(|p| p.global_rank).min(),
+                best_character_rank: points.iter().filter_map(|p| p.character_rank).min(),
+                days_first_global: points.iter().filter(|p| p.global_rank == Some(1)).count()
+                    as i64,
+                days_first_character: points
+                    .iter()
+                    .filter(|p| p.character_rank == Some(1))
+                    .count() as i64,
+                patches: match (points.first(), points.last()) {
+                    (Some(first), Some(last)) => get_patches(conn, first.timestamp, last.timestamp),
+                    _ => Vec::new(),
+                },
+                points,
+            }
+        })
+        .await,
+    ))
+}
+
+#[derive(Serialize)]
+pub struct Season {
+    id: i64,
+    name: String,
+    start: String,
+    end: String,
+    archived: bool,
+}
+
+#[derive(Serialize)]
+pub struct SeasonStandings {
+    season: Season,
+    champions: Vec<SeasonPlayer>,
+    players: Vec<SeasonPlayer>,
+}
+
+#[derive(Serialize)]
+pub struct SeasonPlayer {
+    pos: i64,
+    id: String,
+    name: String,
+    platform: &'static str,
+    character: &'static str,
+    character_short: &'static str,
+    game_count: i64,
+    rating_value: i64,
+    rating_deviation: i64,
+    peak_rating_value: Option<i64>,
+    peak_rating_deviation: Option<i64>,
+    peak_rating_timestamp: Option<String>,
+    vip_status: Option<String>,
+    cheater_status: Option<String>,
+    hidden_status: Option<String>,
+}
+
+pub fn format_date(timestamp: i64) -> String {
+    NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .unwrap()
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn season_from_row(row: &rusqlite::Row) -> rusqlite::Result<Season> {
+    Ok(Season {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        start: format_date(row.get(2)?),
+        end: format_date(row.get(3)?),
+        archived: row.get(4)?,
+    })
+}
+
+#[derive(Serialize)]
+pub struct TierList {
+    date: String,
+    tiers: Vec<Tier>,
+}
+
+#[derive(Serialize)]
+pub struct Tier {
+    tier: &'static str,
+    characters: Vec<TierCharacter>,
+}
+
+#[derive(Serialize)]
+pub struct TierCharacter {
+    character: &'static str,
+    character_short: &'static str,
+    score: f64,
+    matchup_score: f64,
+    fraud_score: f64,
+    representation_score: f64,
+}
+
+#[derive(Serialize)]
+pub struct TierHistory {
+    dates: Vec<String>,
+    characters: Vec<TierHistoryCharacter>,
+    patches: Vec<Patch>,
+}
+
+#[derive(Serialize)]
+pub struct TierHistoryCharacter {
+    character: &'static str,
+    character_short: &'static str,
+    tiers: Vec<Option<&'static str>>,
+}
+
+/// How finely tier_history samples the tier lists. Unlike a rating history there are no games to
+/// go by, only the tier lists themselves.
+#[derive(FromFormField, Clone, Copy, PartialEq)]
+pub enum TierHistoryResolution {
+    Day,
+    Week,
+    Snapshot,
+}
+
+fn to_tier(tier: &str) -> &'static str {
+    rater::TIERS
+        .iter()
+        .find(|(t, _)| *t == tier)
+        .map(|(t, _)| *t)
+        .unwrap_or("?")
+}
+
+#[get("/api/tiers")]
+pub async fn tiers(conn: RatingsDbConn) -> Option<Json<TierList>> {
+    tiers_inner(&conn).await.map(Json)
+}
+
+pub async fn tiers_inner(conn: &RatingsDbConn) -> Option<TierList> {
+    conn.run(|conn| {
+        let timestamp: i64 = conn
+            .query_row("SELECT MAX(timestamp) FROM tier_list", [], |r| r.get(0))
+            .unwrap_or(None)?;
+
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT char_id, tier, score, matchup_score, fraud_score, representation_score
+                FROM tier_list
+                WHERE timestamp = ?
+                ORDER BY score DESC",
+            )
+            .unwrap();
+        let mut rows = stmt.query(params![timestamp]).unwrap();
+
+        let mut tiers: Vec<Tier> = rater::TIERS
+            .iter()
+            .map(|(tier, _)| Tier {
+                tier,
+                characters: Vec::new(),
+            })
+            .collect();
+        let round = |x: f64| (x * 10.0).round() / 10.0;
+        while let Some(row) = rows.next().unwrap() {
+            let char_id: usize = row.get(0).unwrap();
+            let tier = to_tier(&row.get::<_, String>(1).unwrap());
+            if let Some(t) = tiers.iter_mut().find(|t| t.tier == tier) {
+                t.characters.push(TierCharacter {
+                    character: website::character(char_id).1,
+                    character_short: website::character(char_id).0,
+                    score: round(row.get(2).unwrap()),
+                    matchup_score: round(row.get(3).unwrap()),
+                    fraud_score: round(row.get(4).unwrap()),
+                    representation_score: round(row.get(5).unwrap()),
+                });
+            }
+        }
+
+        Some(TierList {
+            date: format_date(timestamp),
+            tiers,
+        })
+    })
+    .await
+}
+
+#[get("/api/tiers/history?<from>&<to>&<resolution>")]
+pub async fn tier_history(
+    conn: RatingsDbConn,
+    from: Option<&str>,
+    to: Option<&str>,
+    resolution: Option<TierHistoryResolution>,
+) -> Json<TierHistory> {
+    let to = to.and_then(parse_date).unwrap_or(Utc::now().timestamp());
+    let from = from
+        .and_then(parse_date)
+        .unwrap_or(to - 8 * 7 * 24 * 60 * 60);
+    let resolution = resolution.unwrap_or(TierHistoryResolution::Day);
+    Json(tier_history_inner(&conn, from, to, resolution).await)
+}
+
+/// The tier of every character over time. Day and week keep the last tier list of each, snapshot
+/// keeps every tier list.
+pub async fn tier_history_inner(
+    conn: &RatingsDbConn,
+    from: i64,
+    to: i64,
+    resolution: TierHistoryResolution,
+) -> TierHistory {
+    conn.run(move |conn| {
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT DISTINCT timestamp FROM tier_list
+                WHERE timestamp >= ? AND timestamp <= ?
+                ORDER BY timestamp ASC",
+            )
+            .unwrap();
+        let mut timestamps = Vec::<i64>::new();
+        let mut rows = stmt.query(params![from, to]).unwrap();
+        while let Some(row) = rows.next().unwrap() {
+            let timestamp: i64 = row.get(0).unwrap();
+            let day = |t: i64| t.div_euclid(24 * 60 * 60);
+            match timestamps.last_mut() {
+                Some(last)
+                    if (resolution == TierHistoryResolution::Day
+                        && day(*last) == day(timestamp))
+                        || (resolution == TierHistoryResolution::Week
+                            && week(*last) == week(timestamp)) =>
+                {
+                    *last = timestamp
+                }
+                _ => timestamps.push(timestamp),
+            }
+        }
+
+        let mut characters: Vec<TierHistoryCharacter> = website::char_names()
+            .iter()
+            .map(|(short, name)| TierHistoryCharacter {
+                character: name,
+                character_short: short,
+                tiers: vec![None; timestamps.len()],
+            })
+            .collect();
+
+        let mut stmt = conn
+            .prepare_cached("SELECT char_id, tier FROM tier_list WHERE timestamp = ?")
+            .unwrap();
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            let mut rows = stmt.query(params![timestamp]).unwrap();
+            while let Some(row) = rows.next().unwrap() {
+                let char_id: usize = row.get(0).unwrap();
+                characters[char_id].tiers[i] = Some(to_tier(&row.get::<_, String>(1).unwrap()));
+            }
+        }
+
+        TierHistory {
+            patches: match (timestamps.first(), timestamps.last()) {
+                (Some(first), Some(last)) => get_patches(conn, *first, *last),
+                _ => Vec::new(),
+            },
+            dates: timestamps.into_iter().map(format_date).collect(),
+            characters,
+        }
+    })
+    .await
+}
+
+#[get("/api/seasons")]
+pub async fn seasons(conn: RatingsDbConn) -> Json<Vec<Season>> {
+    Json(seasons_inner(&conn).await)
+}
+
+pub async fn seasons_inner(conn: &RatingsDbConn) -> Vec<Season> {
+    conn.run(|conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT season_id, name, start_timestamp, end_timestamp, archived
+                FROM seasons
+                ORDER BY start_timestamp DESC",
+            )
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut res = Vec::new();
+        while let Some(row) = rows.next().unwrap() {
+            res.push(season_from_row(row).unwrap());
+        }
+        res
+    })
+    .await
+}
+
+#[get("/api/season/<season_id>")]
+pub async fn season(conn: RatingsDbConn, season_id: i64) -> Option<Json<SeasonStandings>> {
+    season_inner(&conn, season_id).await.map(Json)
+}
+
+pub async fn season_inner(conn: &RatingsDbConn, season_id: i64) -> Option<SeasonStandings> {
+    conn.run(move |conn| {
+        let season = conn
+            .query_row(
+                "SELECT season_id, name, start_timestamp, end_timestamp, archived
+                FROM seasons
+                WHERE season_id = ?",
+                params![season_id],
+                season_from_row,
+            )
+            .optional()
+            .unwrap()?;
+
+        fn season_players(
+            conn: &Connection,
+            season_id: i64,
+            condition: &str,
+            rank: &str,
+        ) -> Vec<SeasonPlayer> {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT
+                        {rank} AS pos, season_ratings.id AS id, char_id,
+                        wins, losses, value, deviation,
+                        peak_value, peak_deviation, peak_timestamp,
+                        name, platform, vip_status, cheater_status, hidden_status
+                    FROM season_ratings
+                    NATURAL JOIN players
+                    LEFT JOIN vip_status ON vip_status.id = season_ratings.id
+                    LEFT JOIN cheater_status ON cheater_status.id = season_ratings.id
+                    LEFT JOIN hidden_status ON hidden_status.id = season_ratings.id
+                    WHERE season_id = ? AND hidden_status IS NULL AND {condition}",
+                ))
+                .unwrap();
+            let mut rows = stmt.query(params![season_id]).unwrap();
+
+            let mut res = Vec::new();
+            while let Some(row) = rows.next().unwrap() {
+                let char_id: usize = row.get("char_id").unwrap();
+                let platform: i64 = row.get("platform").unwrap();
+                let wins: i64 = row.get("wins").unwrap();
+                let losses: i64 = row.get("losses").unwrap();
+                let value: f64 = row.get("value").unwrap();
+                let deviation: f64 = row.get("deviation").unwrap();
+                let peak_value: Option<f64> = row.get("peak_value").unwrap();
+                let peak_deviation: Option<f64> = row.get("peak_deviation").unwrap();
+                let peak_timestamp: Option<i64> = row.get("peak_timestamp").unwrap();
+
+                res.push(SeasonPlayer {
+                    pos: row.get("pos").unwrap(),
+                    id: format!("{:X}", row.get::<_, i64>("id").unwrap()),
+                    name: row.get("name").unwrap(),
+                    platform: to_platform_string(platform),
+                    character: website::character(char_id).1,
+                    character_short: website::character(char_id).0,
+                    game_count: wins + losses,
+                    rating_value: value.round() as i64,
+                    rating_deviation: (2.0 * deviation).round() as i64,
+                    peak_rating_value: peak_value.map(|r| r.round() as i64),
+                    peak_rating_deviation: peak_deviation.map(|d| (2.0 * d).round() as i64),
+                    peak_rating_timestamp: peak_timestamp.map(format_date),
+                    vip_status: row.get("vip_status").unwrap(),
+                    cheater_status: row.get("cheater_status").unwrap(),
+                    hidden_status: row.get("hidden_status").unwrap(),
+                });
+            }
+            res
+        }
+
+        Some(SeasonStandings {
+            season,
+            champions: season_players(
+                conn,
+                season_id,
+                "character_rank = 1 ORDER BY char_id ASC",
+                "character_rank",
+            ),
+            players: season_players(
+                conn,
+                season_id,
+                "global_rank <= 100 ORDER BY global_rank ASC",
+                "global_rank",
+            ),
+        })
+    })
+    .await
+}
+
+#[derive(Serialize)]
+pub struct PlayerData {
+    name: String,
+    platform: String,
+    other_names: Option<Vec<String>>,
+    characters: Vec<PlayerCharacterData>,
+}
+
+#[derive(Serialize)]
+pub struct PlayerDataChar {
+    id: String,
+    name: String,
+    platform: &'static str,
+    vip_status: Option<String>,
+    cheater_status: Option<String>,
+    other_names: Option<Vec<String>>,
+    aliases: Vec<PlayerAlias>,
+    other_characters: Vec<OtherPlayerCharacter>,
+    data: PlayerCharacterData,
+    pub hidden_status: Option<String>,
+    region: Option<&'static str>,
+    sessions: Vec<PlayerSession>,
+    activity: PlayerActivity,
+}
+
+#[derive(Serialize)]
+struct PlayerAlias {
+    name: String,
+    first_seen: String,
+    last_seen: String,
+}
+
+/// A contiguous play period of a player, across all their characters.
+#[derive(Serialize)]
+pub struct PlayerSession {
+    start: String,
+    end: String,
+    duration_minutes: i64,
+    game_count: i64,
+    wins: i64,
+    losses: i64,
+    rating_change: f64,
+    rating_change_class: &'static str,
+    opponent_count: i64,
+    early_loss_rate: f64,
+    late_games: i64,
+    late_loss_rate: Option<f64>,
+    late_expected_loss_rate: Option<f64>,
+    longest_loss_streak: i64,
+}
+
+#[derive(Serialize)]
+struct OtherPlayerCharacter {
+    character_name: String,
+    character_shortname: String,
+    rating_value: i64,
+    rating_deviation: i64,
+    game_count: i32,
+}
+
+#[derive(Serialize)]
+struct PlayerCharacterData {
+    character_name: String,
+    rating_value: i64,
+    rating_deviation: i64,
+    global_rank: Option<i32>,
+    character_rank: Option<i32>,
+
+    top_rating_value: Option<i64>,
+    top_rating_deviation: Option<i64>,
+    top_rating_timestamp: Option<String>,
+
+    top_defeated_id: Option<String>,
+    top_defeated_char_id: Option<&'static str>,
+    top_defeated_name: Option<String>,
+    top_defeated_value: Option<i64>,
+    top_defeated_deviation: Option<i64>,
+    top_defeated_floor: Option<String>,
+    top_defeated_timestamp: Option<String>,
+
+    rating_change_week: Option<String>,
+    rating_change_month: Option<String>,
+
+    percentile_overall: Option<String>,
+    percentile_character: Option<String>,
+
+    win_rate: f64,
+    game_count: i32,
+    matchups: Vec<PlayerMatchup>,
+}
+
+#[derive(Serialize)]
+pub struct PlayerCharacterHistory {
+    history: Vec<PlayerSet>,
+}
+
+const MATCHUP_MIN_GAMES: i64 = 250;
+/// How many of the latest sessions are shown on the player page.
+const PLAYER_PAGE_SESSIONS: i64 = 10;
+/// How many weeks of games are shown on the player page.
+const PLAYER_PAGE_ACTIVITY_WEEKS: i64 = 26;
+
+#[derive(Serialize)]
+struct PlayerSet {
+    timestamp: String,
+    own_rating_value: i64,
+    own_rating_deviation: i64,
+    floor: String,
+    opponent_name: String,
+    opponent_platform: &'static str,
+    opponent_vip: Option<&'static str>,
+    opponent_cheater: Option<&'static str>,
+    opponent_hidden: Option<&'static str>,
+    opponent_id: String,
+    opponent_character: &'static str,
+    opponent_character_short: &'static str,
+    opponent_rating_value: i64,
+    opponent_rating_deviation: i64,
+    expected_outcome: String,
+    rating_change: String,
+    rating_change_class: &'static str,
+    rating_change_sequence: String,
+    result_wins: i32,
+    result_losses: i32,
+    result_percent: f64,
+}
+
+#[derive(Serialize)]
+struct PlayerMatchup {
+    character: String,
+    game_count: i64,
+    win_rate: f64,
+    rating_offset: String,
+    rating_deviation: i64,
+    rating: i64,
+    rating_change_class: &'static str,
+}
+
+pub async fn get_player_highest_rated_character(conn: &RatingsDbConn, id: i64) -> Option<i64> {
+    conn.run(move |conn| {
+        conn.query_row(
+            "SELECT char_id
+        FROM player_ratings
+        WHERE id=?
+        ORDER BY value - 3.0  * deviation DESC
+        LIMIT 1",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()
+        .unwrap()
+    })
+    .await
+}
+
+#[derive(Serialize)]
+pub struct RecentSet {
+    timestamp: String,
+    player_a: SetPlayer,
+    player_b: SetPlayer,
+    win_chance: String,
+    wins_a: i64,
+    wins_b: i64,
+}
 
 #[derive(Serialize)]
 pub struct SetPlayer {
@@ -1000,6 +2620,10 @@ pub async fn get_player_char_history(
     offset: i64,
     group_games: bool,
 ) -> Option<PlayerCharacterHistory> {
+    if is_player_hidden(conn, id).await {
+        return None;
+    }
+
     if let Ok(res) = conn.run(move |conn| {
         let history = {
             let mut stmt = conn
@@ -1149,38 +2773,59 @@ pub async fn get_player_data_char(
     id: i64,
     char_id: i64,
 ) -> Result<Option<PlayerDataChar>> {
+    let now = Utc::now().timestamp();
     conn.run(move |conn| {
+        if is_hidden(conn, id) {
+            return Ok(None);
+        }
+
         if conn.query_row(
             "SELECT EXISTS(SELECT 1 FROM players WHERE id=?)",
             params![id],
             |r| r.get(0),
         )? {
-            let (name, platform, vip_status, cheater_status, hidden_status): (
+            let (name, platform, vip_status, cheater_status, hidden_status, region): (
                 String,
                 i64,
                 Option<String>,
                 Option<String>,
                 Option<String>,
+                Option<String>,
             ) = conn.query_row(
-                "SELECT name, platform, vip_status, cheater_status, hidden_status FROM players
+                "SELECT name, platform, vip_status, cheater_status, hidden_status, region
+                        FROM players
                         LEFT JOIN vip_status ON vip_status.id = players.id
                         LEFT JOIN cheater_status ON cheater_status.id = players.id
                         LEFT JOIN hidden_status ON hidden_status.id = players.id
+                        LEFT JOIN player_regions ON player_regions.id = players.id
                            WHERE players.id=?
                            ",
                 params![id],
-                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+                |r| {
+                    Ok((
+                        r.get(0)?,
+                        r.get(1)?,
+                        r.get(2)?,
+                        r.get(3)?,
+                        r.get(4)?,
+                        r.get(5)?,
+                    ))
+                },
             )?;
             info!(
                 "Loading data for {} ({})",
                 name,
-                website::CHAR_NAMES[char_id as usize].0
+                website::character(char_id as usize).0
             );
 
             let other_names = get_player_other_names(conn, id, &name);
+            let aliases = get_player_aliases(conn, id);
 
             let other_characters = get_player_other_characters(conn, id);
 
+            let sessions = get_player_sessions(conn, id, PLAYER_PAGE_SESSIONS);
+            let activity = get_player_activity(conn, id, PLAYER_PAGE_ACTIVITY_WEEKS, now);
+
             let character_data = get_player_character_data(conn, id, char_id)?.unwrap();
 
             Ok(Some(PlayerDataChar {
@@ -1191,8 +2836,17 @@ pub async fn get_player_data_char(
                 cheater_status,
                 other_characters,
                 other_names,
+                aliases,
+                sessions,
+                activity,
                 data: character_data,
                 hidden_status,
+                region: region.as_deref().and_then(|r| {
+                    website::REGIONS
+                        .iter()
+                        .find(|(short, _)| *short == r)
+                        .map(|(_, name)| *name)
+                }),
             }))
         } else {
             Ok(None)
@@ -1201,6 +2855,111 @@ pub async fn get_player_data_char(
     .await
 }
 
+/// Every name of the player, most recently used first.
+fn get_player_aliases(conn: &Connection, id: i64) -> Vec<PlayerAlias> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT name, first_seen, last_seen FROM player_names
+            WHERE id = ?
+            ORDER BY last_seen DESC",
+        )
+        .unwrap();
+    let mut rows = stmt.query(params![id]).unwrap();
+    let mut aliases = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        aliases.push(PlayerAlias {
+            name: row.get(0).unwrap(),
+            first_seen: format_date(row.get(1).unwrap()),
+            last_seen: format_date(row.get(2).unwrap()),
+        });
+    }
+    aliases
+}
+
+#[get("/api/sessions/<player>?<count>")]
+pub async fn player_sessions(
+    conn: RatingsDbConn,
+    player: &str,
+    count: Option<i64>,
+) -> Option<Json<Vec<PlayerSession>>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
+    let count = count.unwrap_or(20).clamp(1, 100);
+
+    Some(Json(
+        conn.run(move |conn| get_player_sessions(conn, id, count))
+            .await,
+    ))
+}
+
+/// The player's latest sessions, newest first. Loss rates are in percent, the late ones only cover
+/// the games after the first `rater::SESSION_LATE_GAMES` of the session.
+fn get_player_sessions(conn: &Connection, id: i64, count: i64) -> Vec<PlayerSession> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT start, end, game_count, wins, rating_change, opponent_count,
+                late_games, late_wins, late_expected_wins, longest_loss_streak
+            FROM player_sessions
+            WHERE id = ?
+            ORDER BY start DESC
+            LIMIT ?",
+        )
+        .unwrap();
+    let mut rows = stmt.query(params![id, count]).unwrap();
+    let mut sessions = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        let start: i64 = row.get(0).unwrap();
+        let end: i64 = row.get(1).unwrap();
+        let game_count: i64 = row.get(2).unwrap();
+        let wins: i64 = row.get(3).unwrap();
+        let rating_change: f64 = row.get(4).unwrap();
+        let late_games: i64 = row.get(6).unwrap();
+        let late_wins: i64 = row.get(7).unwrap();
+        let late_expected_wins: f64 = row.get(8).unwrap();
+
+        let early_games = game_count - late_games;
+        let early_wins = wins - late_wins;
+
+        sessions.push(PlayerSession {
+            start: NaiveDateTime::from_timestamp_opt(start, 0)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            end: NaiveDateTime::from_timestamp_opt(end, 0)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            duration_minutes: (end - start) / 60,
+            game_count,
+            wins,
+            losses: game_count - wins,
+            rating_change: (10.0 * rating_change).round() / 10.0,
+            rating_change_class: if rating_change >= 20.0 {
+                "rating-up"
+            } else if rating_change >= 0.0 {
+                "rating-barely-up"
+            } else if rating_change >= -20.0 {
+                "rating-barely-down"
+            } else {
+                "rating-down"
+            },
+            opponent_count: row.get(5).unwrap(),
+            early_loss_rate: (100.0 * (early_games - early_wins) as f64 / early_games as f64)
+                .round(),
+            late_games,
+            late_loss_rate: (late_games > 0)
+                .then(|| (100.0 * (late_games - late_wins) as f64 / late_games as f64).round()),
+            late_expected_loss_rate: (late_games > 0).then(|| {
+                (100.0 * (late_games as f64 - late_expected_wins) / late_games as f64).round()
+            }),
+            longest_loss_streak: row.get(9).unwrap(),
+        });
+    }
+    sessions
+}
+
 fn get_player_other_names(conn: &Connection, id: i64, name: &str) -> Option<Vec<String>> {
     let mut stmt = conn
         .prepare_cached("SELECT name FROM player_names WHERE id=?")
@@ -1240,8 +2999,8 @@ fn get_player_other_characters(conn: &Connection, id: i64) -> Vec<OtherPlayerCha
         let game_count: i32 = row.get::<_, i32>(1).unwrap() + row.get::<_, i32>(2).unwrap();
         let rating = Rating::new(row.get(3).unwrap(), row.get(4).unwrap());
 
-        let character_name = website::CHAR_NAMES[char_id].1.to_owned();
-        let character_shortname = website::CHAR_NAMES[char_id].0.to_owned();
+        let character_name = website::character(char_id).1.to_owned();
+        let character_shortname = website::character(char_id).0.to_owned();
         other_characters.push(OtherPlayerCharacter {
             character_name,
             character_shortname,
@@ -1325,8 +3084,17 @@ fn get_player_character_data(
         Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
         Err(err) => return Err(err.into()),
     };
+
+    let top_defeated_hidden = top_defeated_id.map_or(false, |id| is_hidden(conn, id));
+    let top_defeated_id = top_defeated_id.filter(|_| !top_defeated_hidden);
+    let top_defeated_name = if top_defeated_hidden {
+        Some(HIDDEN_NAME.to_owned())
+    } else {
+        top_defeated_name
+    };
     {
-        let character_name = website::CHAR_NAMES[char_id as usize].1.to_owned();
+        let character_name = website::character(char_id as usize).1.to_owned();
+        let now = Utc::now().timestamp();
 
         let matchups = {
             let mut stmt = conn
@@ -1353,7 +3121,7 @@ fn get_player_character_data(
                 let losses: i64 = row.get(4).unwrap();
                 let rating_offset = rating_value - value;
                 matchups.push(PlayerMatchup {
-                    character: website::CHAR_NAMES[opp_char_id].1.to_owned(),
+                    character: website::character(opp_char_id).1.to_owned(),
                     game_count: wins + losses,
                     win_rate: (100.0 * wins as f64 / (wins + losses) as f64).round(),
                     rating_offset: format!("{:+.0} ±{:.0}", rating_offset, 2.0 * rating_deviation),
@@ -1391,7 +3159,7 @@ fn get_player_character_data(
                     .to_string()
             }),
             top_defeated_id: top_defeated_id.map(|id| format!("{:X}", id)),
-            top_defeated_char_id: top_defeated_char_id.map(|id| website::CHAR_NAMES[id as usize].0),
+            top_defeated_char_id: top_defeated_char_id.map(|id| website::character(id as usize).0),
             top_defeated_name,
             top_defeated_value: top_defeated_value.map(|r| r.round() as i64),
             top_defeated_deviation: top_defeated_deviation.map(|r| (2.0 * r).round() as i64),
@@ -1402,6 +3170,26 @@ fn get_player_character_data(
                     .format("%Y-%m-%d")
                     .to_string()
             }),
+            rating_change_week: get_rating_change_since(
+                conn,
+                id,
+                char_id,
+                Rating::new(value, deviation),
+                now - 7 * 24 * 60 * 60,
+            )
+            .map(|c| format!("{:+.0}", c)),
+            rating_change_month: get_rating_change_since(
+                conn,
+                id,
+                char_id,
+                Rating::new(value, deviation),
+                now - 30 * 24 * 60 * 60,
+            )
+            .map(|c| format!("{:+.0}", c)),
+            percentile_overall: get_percentile(conn, None, value, global_rank)
+                .map(|p| format!("{:.1}", (100.0 - p.percentile).max(0.1))),
+            percentile_character: get_percentile(conn, Some(char_id), value, character_rank)
+                .map(|p| format!("{:.1}", (100.0 - p.percentile).max(0.1))),
             matchups,
             character_rank,
             global_rank,
@@ -1466,11 +3254,19 @@ impl RawPlayerSet {
             own_rating_value: self.own_value.round() as i64,
             own_rating_deviation: (2.0 * self.own_deviation).round() as i64,
             floor: stringify_floor(self.floor),
-            opponent_name: self.opponent_name,
+            opponent_name: if self.opponent_hidden {
+                HIDDEN_NAME.to_owned()
+            } else {
+                self.opponent_name
+            },
             opponent_platform: self.opponent_platform,
-            opponent_id: format!("{:X}", self.opponent_id),
-            opponent_character_short: website::CHAR_NAMES[self.opponent_char as usize].0,
-            opponent_character: website::CHAR_NAMES[self.opponent_char as usize].1,
+            opponent_id: if self.opponent_hidden {
+                String::new()
+            } else {
+                format!("{:X}", self.opponent_id)
+            },
+            opponent_character_short: website::character(self.opponent_char as usize).0,
+            opponent_character: website::character(self.opponent_char as usize).1,
 
             opponent_rating_value: self.opponent_value.round() as i64,
             opponent_rating_deviation: (2.0 * self.opponent_deviation).round() as i64,
@@ -1643,13 +3439,22 @@ pub struct CharacterMatchups {
 pub struct Matchup {
     matchup: String,
     win_rate: f64,
+    win_rate_low: f64,
+    win_rate_high: f64,
     game_count: i64,
     rating_delta: String,
     expected: f64,
+    expected_low: f64,
+    expected_high: f64,
     suspicious: bool,
+    /// The 95% interval of either the raw win rate or the expected win rate contains 50%.
+    inconclusive: bool,
     evaluation: &'static str,
 }
 
+/// z-score of the 95% confidence intervals on the matchup charts.
+const MATCHUP_CONFIDENCE_Z: f64 = 1.96;
+
 fn get_evaluation(r: f64, game_count: i64) -> &'static str {
     if game_count < MATCHUP_MIN_GAMES {
         return "none";
@@ -1672,60 +3477,229 @@ fn get_evaluation(r: f64, game_count: i64) -> &'static str {
     }
 }
 
-pub async fn get_matchups(conn: &RatingsDbConn, table: &'static str) -> Vec<CharacterMatchups> {
+pub async fn get_matchups(conn: &RatingsDbConn, table: &'static str) -> Vec<CharacterMatchups> {
+    conn.run(move |conn| {
+        let tx = conn.transaction().unwrap();
+        let mut all_matchups = FxHashMap::default();
+
+        let mut stmt = tx
+            .prepare(&format!(
+                "SELECT char_id, opp_char_id, rating_value, rating_deviation, wins, losses FROM {}",
+                table
+            ))
+            .unwrap();
+
+        let mut rows = stmt.query([]).unwrap();
+
+        while let Some(row) = rows.next().unwrap() {
+            let char_id: i64 = row.get(0).unwrap();
+            let opp_char_id: i64 = row.get(1).unwrap();
+            let rating_value: f64 = row.get(2).unwrap();
+            let rating_deviation: f64 = row.get(3).unwrap();
+            let wins: i64 = row.get(4).unwrap();
+            let losses: i64 = row.get(5).unwrap();
+
+            all_matchups.insert(
+                (char_id, opp_char_id),
+                (rating_value, rating_deviation, wins, losses),
+            );
+        }
+
+        (0..website::char_names().len() as i64)
+            .map(|c| CharacterMatchups {
+                name: website::character(c as usize).1.to_owned(),
+                matchups: (0..website::char_names().len() as i64)
+                    .map(|o| {
+                        let (own_value, own_deviation, wins, losses) =
+                            *all_matchups.get(&(c, o)).unwrap_or(&(1500.0, 350.0, 0, 0));
+
+                        let (opp_value, opp_deviation, ..) =
+                            *all_matchups.get(&(o, c)).unwrap_or(&(1500.0, 350.0, 0, 0));
+
+                        let own = Rating::new(own_value, own_deviation);
+                        let opp = Rating::new(opp_value, opp_deviation);
+                        let expected = own.expected(opp);
+                        let (expected_low, expected_high) =
+                            own.expected_interval(opp, MATCHUP_CONFIDENCE_Z);
+                        let (win_rate_low, win_rate_high) =
+                            glicko::wilson_interval(wins, losses, MATCHUP_CONFIDENCE_Z);
+
+                        Matchup {
+                            matchup: format!(
+                                "{} vs {}",
+                                website::character(c as usize).0,
+                                website::character(o as usize).0
+                            ),
+                            win_rate: (100.0 * wins as f64 / (wins + losses) as f64).round(),
+                            win_rate_low: (100.0 * win_rate_low).round(),
+                            win_rate_high: (100.0 * win_rate_high).round(),
+                            game_count: wins + losses,
+                            rating_delta: format!("{:+.0}", own_value - opp_value),
+                            expected: (100.0 * expected).round(),
+                            expected_low: (100.0 * expected_low).round(),
+                            expected_high: (100.0 * expected_high).round(),
+                            evaluation: get_evaluation(expected, wins + losses),
+                            suspicious: wins + losses < MATCHUP_MIN_GAMES,
+                            inconclusive: (expected_low < 0.5 && expected_high > 0.5)
+                                || (win_rate_low < 0.5 && win_rate_high > 0.5),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    })
+    .await
+}
+
+/// Matchups within one rating bracket. `expected` here is the win rate with the rating
+/// difference of each game taken out, i.e. what the matchup would be between equal players.
+pub async fn get_bracket_matchups(
+    conn: &RatingsDbConn,
+    bracket: usize,
+    patch_id: i64,
+) -> Vec<CharacterMatchups> {
+    conn.run(move |conn| {
+        let mut all_matchups = FxHashMap::default();
+
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT char_id, opp_char_id, wins, losses, expected_wins, rating_delta_sum
+                FROM bracket_matchups
+                WHERE rating_bracket = ? AND patch_id = ?",
+            )
+            .unwrap();
+
+        let mut rows = stmt.query(params![bracket, patch_id]).unwrap();
+
+        while let Some(row) = rows.next().unwrap() {
+            let char_id: i64 = row.get(0).unwrap();
+            let opp_char_id: i64 = row.get(1).unwrap();
+            let wins: i64 = row.get(2).unwrap();
+            let losses: i64 = row.get(3).unwrap();
+            let expected_wins: f64 = row.get(4).unwrap();
+            let rating_delta_sum: f64 = row.get(5).unwrap();
+
+            all_matchups.insert(
+                (char_id, opp_char_id),
+                (wins, losses, expected_wins, rating_delta_sum),
+            );
+        }
+
+        (0..website::char_names().len() as i64)
+            .map(|c| CharacterMatchups {
+                name: website::character(c as usize).1.to_owned(),
+                matchups: (0..website::char_names().len() as i64)
+                    .map(|o| {
+                        let (wins, losses, expected_wins, rating_delta_sum) =
+                            *all_matchups.get(&(c, o)).unwrap_or(&(0, 0, 0.0, 0.0));
+                        let game_count = wins + losses;
+                        let n = game_count.max(1) as f64;
+
+                        let (win_rate_low, win_rate_high) =
+                            glicko::wilson_interval(wins, losses, MATCHUP_CONFIDENCE_Z);
+                        let adjustment = (0.5 * game_count as f64 - expected_wins) / n;
+                        let expected = if game_count > 0 {
+                            wins as f64 / n + adjustment
+                        } else {
+                            0.5
+                        };
+                        let expected_low = (win_rate_low + adjustment).clamp(0.0, 1.0);
+                        let expected_high = (win_rate_high + adjustment).clamp(0.0, 1.0);
+
+                        Matchup {
+                            matchup: format!(
+                                "{} vs {}",
+                                website::character(c as usize).0,
+                                website::character(o as usize).0
+                            ),
+                            win_rate: (100.0 * wins as f64 / n).round(),
+                            win_rate_low: (100.0 * win_rate_low).round(),
+                            win_rate_high: (100.0 * win_rate_high).round(),
+                            game_count,
+                            rating_delta: format!("{:+.0}", rating_delta_sum / n),
+                            expected: (100.0 * expected).round(),
+                            expected_low: (100.0 * expected_low).round(),
+                            expected_high: (100.0 * expected_high).round(),
+                            evaluation: get_evaluation(expected, game_count),
+                            suspicious: game_count < MATCHUP_MIN_GAMES,
+                            inconclusive: expected_low < 0.5 && expected_high > 0.5,
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    })
+    .await
+}
+
+/// Matchups fitted from the players' pre-game ratings, so the skill of the players who pick a
+/// character doesn't show up as a matchup advantage. `rating_delta` is the fitted offset.
+pub async fn get_estimated_matchups(conn: &RatingsDbConn, patch_id: i64) -> Vec<CharacterMatchups> {
     conn.run(move |conn| {
-        let tx = conn.transaction().unwrap();
         let mut all_matchups = FxHashMap::default();
 
-        let mut stmt = tx
-            .prepare(&format!(
-                "SELECT char_id, opp_char_id, rating_value, rating_deviation, wins, losses FROM {}",
-                table
-            ))
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT char_id, opp_char_id, wins, losses, rating_offset, rating_offset_deviation
+                FROM estimated_matchups
+                WHERE patch_id = ?",
+            )
             .unwrap();
 
-        let mut rows = stmt.query([]).unwrap();
+        let mut rows = stmt.query(params![patch_id]).unwrap();
 
         while let Some(row) = rows.next().unwrap() {
             let char_id: i64 = row.get(0).unwrap();
             let opp_char_id: i64 = row.get(1).unwrap();
-            let rating_value: f64 = row.get(2).unwrap();
-            let rating_deviation: f64 = row.get(3).unwrap();
-            let wins: i64 = row.get(4).unwrap();
-            let losses: i64 = row.get(5).unwrap();
+            let wins: i64 = row.get(2).unwrap();
+            let losses: i64 = row.get(3).unwrap();
+            let offset: f64 = row.get(4).unwrap();
+            let offset_deviation: f64 = row.get(5).unwrap();
 
             all_matchups.insert(
                 (char_id, opp_char_id),
-                (rating_value, rating_deviation, wins, losses),
+                (wins, losses, offset, offset_deviation),
             );
         }
 
-        (0..website::CHAR_NAMES.len() as i64)
+        (0..website::char_names().len() as i64)
             .map(|c| CharacterMatchups {
-                name: website::CHAR_NAMES[c as usize].1.to_owned(),
-                matchups: (0..website::CHAR_NAMES.len() as i64)
+                name: website::character(c as usize).1.to_owned(),
+                matchups: (0..website::char_names().len() as i64)
                     .map(|o| {
-                        let (own_value, own_deviation, wins, losses) =
-                            *all_matchups.get(&(c, o)).unwrap_or(&(1500.0, 350.0, 0, 0));
-
-                        let (opp_value, opp_deviation, ..) =
-                            *all_matchups.get(&(o, c)).unwrap_or(&(1500.0, 350.0, 0, 0));
-
-                        let expected = Rating::new(own_value, own_deviation)
-                            .expected(Rating::new(opp_value, opp_deviation));
+                        let (wins, losses, offset, offset_deviation) = *all_matchups
+                            .get(&(c, o))
+                            .unwrap_or(&(0, 0, 0.0, glicko::INITIAL_DEVIATION));
+                        let game_count = wins + losses;
+
+                        let equal = Rating::new(0.0, 0.0);
+                        let expected = Rating::new(offset, 0.0).expected(equal);
+                        let expected_low =
+                            Rating::new(offset - MATCHUP_CONFIDENCE_Z * offset_deviation, 0.0)
+                                .expected(equal);
+                        let expected_high =
+                            Rating::new(offset + MATCHUP_CONFIDENCE_Z * offset_deviation, 0.0)
+                                .expected(equal);
+                        let (win_rate_low, win_rate_high) =
+                            glicko::wilson_interval(wins, losses, MATCHUP_CONFIDENCE_Z);
 
                         Matchup {
                             matchup: format!(
                                 "{} vs {}",
-                                website::CHAR_NAMES[c as usize].0,
-                                website::CHAR_NAMES[o as usize].0
+                                website::character(c as usize).0,
+                                website::character(o as usize).0
                             ),
-                            win_rate: (100.0 * wins as f64 / (wins + losses) as f64).round(),
-                            game_count: wins + losses,
-                            rating_delta: format!("{:+.0}", own_value - opp_value),
+                            win_rate: (100.0 * wins as f64 / game_count.max(1) as f64).round(),
+                            win_rate_low: (100.0 * win_rate_low).round(),
+                            win_rate_high: (100.0 * win_rate_high).round(),
+                            game_count,
+                            rating_delta: format!("{:+.0}", offset),
                             expected: (100.0 * expected).round(),
-                            evaluation: get_evaluation(expected, wins + losses),
-                            suspicious: wins + losses < MATCHUP_MIN_GAMES,
+                            expected_low: (100.0 * expected_low).round(),
+                            expected_high: (100.0 * expected_high).round(),
+                            evaluation: get_evaluation(expected, game_count),
+                            suspicious: game_count < MATCHUP_MIN_GAMES,
+                            inconclusive: expected_low < 0.5 && expected_high > 0.5,
                         }
                     })
                     .collect(),
@@ -1735,6 +3709,40 @@ pub async fn get_matchups(conn: &RatingsDbConn, table: &'static str) -> Vec<Char
     .await
 }
 
+#[get("/api/matchups/adjusted?<patch>")]
+pub async fn estimated_matchups(
+    conn: RatingsDbConn,
+    patch: Option<i64>,
+) -> Json<Vec<CharacterMatchups>> {
+    Json(get_estimated_matchups(&conn, patch.unwrap_or(0)).await)
+}
+
+#[get("/api/matchups/rating/<bracket>?<patch>")]
+pub async fn bracket_matchups(
+    conn: RatingsDbConn,
+    bracket: usize,
+    patch: Option<i64>,
+) -> Option<Json<Vec<CharacterMatchups>>> {
+    if bracket >= rater::POP_RATING_BRACKETS {
+        return None;
+    }
+    Some(Json(
+        get_bracket_matchups(&conn, bracket, patch.unwrap_or(0)).await,
+    ))
+}
+
+/// The displayed lower and upper bounds of a rating bracket.
+pub fn rating_bracket_bounds(bracket: usize) -> (usize, usize) {
+    (
+        if bracket > 0 { 1000 + bracket * 100 } else { 0 },
+        if bracket < rater::POP_RATING_BRACKETS - 1 {
+            1000 + (bracket + 1) * 100
+        } else {
+            3000
+        },
+    )
+}
+
 #[derive(Serialize)]
 pub struct FloorPlayers {
     floor: String,
@@ -1872,6 +3880,7 @@ struct RankCharacterPopularity {
 
 pub async fn character_popularity(
     conn: &RatingsDbConn,
+    patch_id: i64,
 ) -> (Vec<f64>, Vec<RankCharacterPopularities>) {
     conn.run(move |conn| {
         let global_popularities = {
@@ -1880,12 +3889,13 @@ pub async fn character_popularity(
                     "SELECT
                         popularity
                         FROM character_popularity_global
+                        WHERE patch_id = ?
                         ORDER BY char_id ASC",
                 )
                 .unwrap();
 
-            let mut rows = stmt.query([]).unwrap();
-            let mut v = Vec::with_capacity(website::CHAR_NAMES.len());
+            let mut rows = stmt.query(params![patch_id]).unwrap();
+            let mut v = Vec::with_capacity(website::char_names().len());
 
             while let Some(row) = rows.next().unwrap() {
                 let popularity: f64 = row.get(0).unwrap();
@@ -1904,21 +3914,18 @@ pub async fn character_popularity(
                         "SELECT
                         char_id, popularity
                         FROM character_popularity_rating
-                        WHERE rating_bracket = ?
+                        WHERE rating_bracket = ? AND patch_id = ?
                         ORDER BY char_id ASC",
                     )
                     .unwrap();
 
-                let mut rows = stmt.query(params![r]).unwrap();
+                let mut rows = stmt.query(params![r, patch_id]).unwrap();
 
+                let (rating_min, rating_max) = rating_bracket_bounds(r);
                 let mut res = RankCharacterPopularities {
-                    rating_min: if r > 0 { 1000 + r * 100 } else { 0 },
-                    rating_max: if r < rater::POP_RATING_BRACKETS - 1 {
-                        1000 + (r + 1) * 100
-                    } else {
-                        3000
-                    },
-                    characters: Vec::with_capacity(website::CHAR_NAMES.len()),
+                    rating_min,
+                    rating_max,
+                    characters: Vec::with_capacity(website::char_names().len()),
                 };
 
                 while let Some(row) = rows.next().unwrap() {
@@ -1957,77 +3964,366 @@ pub async fn character_popularity(
     .await
 }
 
+/// How many standard errors a week over week popularity change needs to count as a trend.
+const POPULARITY_TREND_Z: f64 = 3.0;
+
 #[derive(Serialize)]
-pub struct FraudStats {
-    character_name: &'static str,
-    player_count: i64,
-    average_offset: String,
+pub struct PopularityTrends {
+    date: String,
+    previous_date: String,
+    global: Vec<PopularityTrend>,
+    brackets: Vec<BracketPopularityTrends>,
+}
+
+#[derive(Serialize)]
+pub struct BracketPopularityTrends {
+    rating_min: usize,
+    rating_max: usize,
+    characters: Vec<PopularityTrend>,
+}
+
+#[derive(Serialize)]
+pub struct PopularityTrend {
+    character: &'static str,
+    character_short: &'static str,
+    before: f64,
+    after: f64,
+    z: f64,
+    rising: bool,
+}
+
+#[get("/api/popularity/trends?<at>")]
+pub async fn popularity_trends(
+    conn: RatingsDbConn,
+    at: Option<&str>,
+) -> Option<Json<PopularityTrends>> {
+    let at = at
+        .and_then(parse_date)
+        .map(|t| t + 24 * 60 * 60 - 1)
+        .unwrap_or(i64::MAX);
+    popularity_trends_inner(&conn, at).await.map(Json)
 }
 
-pub async fn get_fraud(conn: &RatingsDbConn) -> Vec<FraudStats> {
+/// Compares the last popularity snapshot up to `at` with the one from a week before, so the two
+/// windows don't overlap. Only the changes that pass a two proportion z-test are kept.
+pub async fn popularity_trends_inner(conn: &RatingsDbConn, at: i64) -> Option<PopularityTrends> {
     conn.run(move |conn| {
-        let mut stmt = conn
-            .prepare(
-                "SELECT char_id, player_count, avg_delta FROM fraud_index ORDER BY avg_delta DESC",
+        let latest: i64 = conn
+            .query_row(
+                "SELECT MAX(timestamp) FROM character_popularity_global_history
+                WHERE timestamp <= ?",
+                params![at],
+                |r| r.get(0),
             )
-            .unwrap();
+            .unwrap_or(None)?;
+        let previous: i64 = conn
+            .query_row(
+                "SELECT MAX(timestamp) FROM character_popularity_global_history
+                WHERE timestamp <= ?",
+                params![latest - 7 * 24 * 60 * 60],
+                |r| r.get(0),
+            )
+            .unwrap_or(None)?;
 
-        let mut rows = stmt.query(params![]).unwrap();
+        let global = {
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT char_id, popularity, sample_size
+                    FROM character_popularity_global_history
+                    WHERE timestamp = ?",
+                )
+                .unwrap();
+            let mut load = |timestamp: i64| {
+                let mut v = vec![None; website::char_names().len()];
+                let mut rows = stmt.query(params![timestamp]).unwrap();
+                while let Some(row) = rows.next().unwrap() {
+                    let char_id: usize = row.get(0).unwrap();
+                    if let Some(p) = v.get_mut(char_id) {
+                        *p = Some((row.get::<_, f64>(1).unwrap(), row.get(2).unwrap()));
+                    }
+                }
+                v
+            };
+            let before = load(previous);
+            let after = load(latest);
+            significant_popularity_changes(&before, &after)
+        };
 
-        let mut res = Vec::new();
-        while let Some(row) = rows.next().unwrap() {
-            res.push(FraudStats {
-                character_name: website::CHAR_NAMES[row.get::<_, usize>(0).unwrap()].1,
-                player_count: row.get(1).unwrap(),
-                average_offset: format!("{:+.1}", (row.get::<_, f64>(2).unwrap())),
-            });
-        }
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT char_id, popularity, sample_size
+                FROM character_popularity_rating_history
+                WHERE timestamp = ? AND rating_bracket = ?",
+            )
+            .unwrap();
+        let mut load = |timestamp: i64, bracket: usize| {
+            let mut v = vec![None; website::char_names().len()];
+            let mut rows = stmt.query(params![timestamp, bracket]).unwrap();
+            while let Some(row) = rows.next().unwrap() {
+                let char_id: usize = row.get(0).unwrap();
+                if let Some(p) = v.get_mut(char_id) {
+                    *p = Some((row.get::<_, f64>(1).unwrap(), row.get(2).unwrap()));
+                }
+            }
+            v
+        };
+        let brackets = (0..rater::POP_RATING_BRACKETS)
+            .map(|bracket| {
+                let (rating_min, rating_max) = rating_bracket_bounds(bracket);
+                let before = load(previous, bracket);
+                let after = load(latest, bracket);
+                BracketPopularityTrends {
+                    rating_min,
+                    rating_max,
+                    characters: significant_popularity_changes(&before, &after),
+                }
+            })
+            .collect();
 
-        res
+        Some(PopularityTrends {
+            date: format_date(latest),
+            previous_date: format_date(previous),
+            global,
+            brackets,
+        })
     })
     .await
 }
 
-pub async fn get_fraud_higher_rated(conn: &RatingsDbConn) -> Vec<FraudStats> {
-    conn.run(move |conn| {
-        let mut stmt = conn
-            .prepare("SELECT char_id, player_count, avg_delta FROM fraud_index_higher_rated ORDER BY avg_delta DESC")
-            .unwrap();
+/// The popularities are stored times two, as every game has two characters, and the sample
+/// sizes count character picks.
+fn significant_popularity_changes(
+    before: &[Option<(f64, i64)>],
+    after: &[Option<(f64, i64)>],
+) -> Vec<PopularityTrend> {
+    let mut res: Vec<PopularityTrend> = before
+        .iter()
+        .zip(after.iter())
+        .enumerate()
+        .filter_map(|(char_id, (before, after))| {
+            let (before, n_before) = (*before)?;
+            let (after, n_after) = (*after)?;
+            if n_before == 0 || n_after == 0 {
+                return None;
+            }
+            let (p_before, p_after) = (before / 2.0, after / 2.0);
+            let (n_before, n_after) = (n_before as f64, n_after as f64);
+            let pooled = (p_before * n_before + p_after * n_after) / (n_before + n_after);
+            let se = (pooled * (1.0 - pooled) * (1.0 / n_before + 1.0 / n_after)).sqrt();
+            if se <= 0.0 {
+                return None;
+            }
+            let z = (p_after - p_before) / se;
+            if z.abs() < POPULARITY_TREND_Z {
+                return None;
+            }
+            Some(PopularityTrend {
+                character: website::character(char_id).1,
+                character_short: website::character(char_id).0,
+                before: (before * 1000.0).round() / 10.0,
+                after: (after * 1000.0).round() / 10.0,
+                z: (z * 100.0).round() / 100.0,
+                rising: z > 0.0,
+            })
+        })
+        .collect();
+    res.sort_by(|a, b| b.z.partial_cmp(&a.z).unwrap());
+    res
+}
 
-        let mut rows = stmt.query(params![]).unwrap();
+#[derive(Serialize)]
+pub struct PopularityHistory {
+    character: &'static str,
+    dates: Vec<String>,
+    global: Vec<Option<f64>>,
+    brackets: Vec<BracketPopularityHistory>,
+    patches: Vec<Patch>,
+}
 
-        let mut res = Vec::new();
-        while let Some(row) = rows.next().unwrap() {
-            res.push(FraudStats {
-                character_name: website::CHAR_NAMES[row.get::<_, usize>(0).unwrap()].1,
-                player_count: row.get(1).unwrap(),
-                average_offset: format!("{:+.1}", (row.get::<_, f64>(2).unwrap())),
-            });
-        }
+#[derive(Serialize)]
+pub struct BracketPopularityHistory {
+    rating_min: usize,
+    rating_max: usize,
+    popularity: Vec<Option<f64>>,
+}
 
-        res
-    })
-    .await
+#[get("/api/popularity/history/<character_short>?<from>&<to>")]
+pub async fn popularity_history(
+    conn: RatingsDbConn,
+    character_short: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Option<Json<PopularityHistory>> {
+    let char_id = website::char_names()
+        .iter()
+        .position(|(c, _)| *c == character_short)?;
+    let to = to.and_then(parse_date).unwrap_or(Utc::now().timestamp());
+    let from = from
+        .and_then(parse_date)
+        .unwrap_or(to - 8 * 7 * 24 * 60 * 60);
+
+    Some(Json(
+        conn.run(move |conn| {
+            // One point per day, the last snapshot of it
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT MAX(timestamp) FROM character_popularity_global_history
+                    WHERE timestamp >= ? AND timestamp <= ?
+                    GROUP BY timestamp / 86400
+                    ORDER BY 1 ASC",
+                )
+                .unwrap();
+            let timestamps: Vec<i64> = stmt
+                .query_map(params![from, to], |r| r.get(0))
+                .unwrap()
+                .map(|t| t.unwrap())
+                .collect();
+
+            let mut global = vec![None; timestamps.len()];
+            let mut brackets: Vec<BracketPopularityHistory> = (0..rater::POP_RATING_BRACKETS)
+                .map(|bracket| {
+                    let (rating_min, rating_max) = rating_bracket_bounds(bracket);
+                    BracketPopularityHistory {
+                        rating_min,
+                        rating_max,
+                        popularity: vec![None; timestamps.len()],
+                    }
+                })
+                .collect();
+
+            for (i, timestamp) in timestamps.iter().enumerate() {
+                global[i] = conn
+                    .query_row(
+                        "SELECT popularity FROM character_popularity_global_history
+                        WHERE timestamp = ? AND char_id = ?",
+                        params![timestamp, char_id],
+                        |r| r.get::<_, f64>(0),
+                    )
+                    .optional()
+                    .unwrap()
+                    .map(|p| (p * 1000.0).round() / 10.0);
+
+                let mut stmt = conn
+                    .prepare_cached(
+                        "SELECT rating_bracket, popularity
+                        FROM character_popularity_rating_history
+                        WHERE timestamp = ? AND char_id = ?",
+                    )
+                    .unwrap();
+                let mut rows = stmt.query(params![timestamp, char_id]).unwrap();
+                while let Some(row) = rows.next().unwrap() {
+                    let bracket: usize = row.get(0).unwrap();
+                    let popularity: f64 = row.get(1).unwrap();
+                    if let Some(b) = brackets.get_mut(bracket) {
+                        b.popularity[i] = Some((popularity * 1000.0).round() / 10.0);
+                    }
+                }
+            }
+
+            PopularityHistory {
+                character: website::character(char_id).1,
+                dates: timestamps.iter().map(|t| format_date(*t)).collect(),
+                global,
+                brackets,
+                patches: get_patches(conn, from, to),
+            }
+        })
+        .await,
+    ))
+}
+
+#[derive(Serialize)]
+pub struct FraudStats {
+    character_name: &'static str,
+    player_count: i64,
+    average_offset: String,
+}
+
+#[derive(Serialize)]
+pub struct FraudIndex {
+    date: String,
+    threshold: i64,
+    characters: Vec<FraudStats>,
 }
 
-pub async fn get_fraud_highest_rated(conn: &RatingsDbConn) -> Vec<FraudStats> {
+/// The fraud index is only calculated above the ratings in `rater::FRAUD_THRESHOLDS`, any other
+/// threshold is a bad request.
+#[get("/api/fraud?<threshold>&<at>")]
+pub async fn fraud(
+    conn: RatingsDbConn,
+    threshold: Option<i64>,
+    at: Option<&str>,
+) -> std::result::Result<Json<FraudIndex>, (Status, String)> {
+    let threshold = threshold.unwrap_or(0);
+    if !rater::FRAUD_THRESHOLDS.contains(&threshold) {
+        return Err((
+            Status::BadRequest,
+            format!(
+                "Unsupported threshold {threshold}, use one of {:?}",
+                rater::FRAUD_THRESHOLDS
+            ),
+        ));
+    }
+    // A date includes the snapshots taken during that day
+    let at = at
+        .and_then(parse_date)
+        .map(|t| t + 24 * 60 * 60 - 1)
+        .unwrap_or(i64::MAX);
+
+    let (timestamp, characters) = get_fraud_at(&conn, threshold, at)
+        .await
+        .ok_or_else(|| (Status::NotFound, format!("No fraud index at that date")))?;
+    Ok(Json(FraudIndex {
+        date: format_date(timestamp),
+        threshold,
+        characters,
+    }))
+}
+
+/// The current fraud index above a rating threshold.
+pub async fn get_fraud(conn: &RatingsDbConn, threshold: i64) -> Vec<FraudStats> {
+    get_fraud_at(conn, threshold, i64::MAX)
+        .await
+        .map(|(_, f)| f)
+        .unwrap_or_default()
+}
+
+/// The last fraud index snapshot at or before `at`, with its timestamp.
+async fn get_fraud_at(
+    conn: &RatingsDbConn,
+    threshold: i64,
+    at: i64,
+) -> Option<(i64, Vec<FraudStats>)> {
     conn.run(move |conn| {
+        let timestamp: i64 = conn
+            .query_row(
+                "SELECT MAX(timestamp) FROM fraud_index
+                WHERE rating_threshold = ? AND timestamp <= ?",
+                params![threshold, at],
+                |r| r.get(0),
+            )
+            .unwrap_or(None)?;
+
         let mut stmt = conn
-            .prepare("SELECT char_id, player_count, avg_delta FROM fraud_index_highest_rated ORDER BY avg_delta DESC")
+            .prepare_cached(
+                "SELECT char_id, player_count, avg_delta FROM fraud_index
+                WHERE timestamp = ? AND rating_threshold = ?
+                ORDER BY avg_delta DESC",
+            )
             .unwrap();
 
-        let mut rows = stmt.query(params![]).unwrap();
+        let mut rows = stmt.query(params![timestamp, threshold]).unwrap();
 
         let mut res = Vec::new();
         while let Some(row) = rows.next().unwrap() {
             res.push(FraudStats {
-                character_name: website::CHAR_NAMES[row.get::<_, usize>(0).unwrap()].1,
+                character_name: website::character(row.get::<_, usize>(0).unwrap()).1,
                 player_count: row.get(1).unwrap(),
                 average_offset: format!("{:+.1}", (row.get::<_, f64>(2).unwrap())),
             });
         }
 
-        res
+        Some((timestamp, res))
     })
     .await
 }
@@ -2043,8 +4339,11 @@ pub async fn get_supporters(conn: &RatingsDbConn) -> Vec<VipPlayer> {
     conn.run(move |conn| {
         let mut stmt = conn
             .prepare(
-                "SELECT id, name, vip_status
-                    FROM vip_status NATURAL JOIN players",
+                "SELECT players.id, name, vip_status
+                    FROM vip_status
+                    NATURAL JOIN players
+                    LEFT JOIN hidden_status ON hidden_status.id = players.id
+                    WHERE hidden_status IS NULL",
             )
             .unwrap();
 
@@ -2084,138 +4383,144 @@ pub struct RatingDiffStats {
 pub async fn rating_experience_player(
     conn: RatingsDbConn,
     player_id: &str,
-) -> Json<RatingDiffStats> {
-    let id = i64::from_str_radix(player_id, 16).unwrap();
-
-    Json(
-        conn.run(move |conn| {
-            let mut stmt = conn
-                .prepare(
-                    "SELECT id_a, id_b, value_a, value_b
-                    FROM game_ratings
-                    WHERE deviation_a < ?
-                        AND deviation_b < ?
-                        AND (id_a = ?  OR id_b = ?)",
-                )
-                .unwrap();
+) -> Option<Json<RatingDiffStats>> {
+    let id = i64::from_str_radix(player_id, 16).ok()?;
+    if is_player_hidden(&conn, id).await {
+        return None;
+    }
 
-            let mut rows = stmt
-                .query(params![rater::LOW_DEVIATION, rater::LOW_DEVIATION, id, id,])
-                .unwrap();
+    Some(Json(
+        conn.run(move |conn| player_rating_experience(conn, id))
+            .await,
+    ))
+}
 
-            let mut counts: FxHashMap<i64, i64> = Default::default();
+/// How often the player faced opponents stronger or weaker than them, in 25 point steps.
+pub fn player_rating_experience(conn: &Connection, id: i64) -> RatingDiffStats {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id_a, id_b, value_a, value_b
+            FROM game_ratings
+            WHERE deviation_a < ?
+                AND deviation_b < ?
+                AND (id_a = ?  OR id_b = ?)",
+        )
+        .unwrap();
 
-            let mut total = 0.0;
-            let mut over_100 = 0.0;
-            let mut over_200 = 0.0;
-            let mut over_300 = 0.0;
-            let mut over_400 = 0.0;
-            let mut below_100 = 0.0;
-            let mut below_200 = 0.0;
-            let mut below_300 = 0.0;
-            let mut below_400 = 0.0;
+    let mut rows = stmt
+        .query(params![rater::LOW_DEVIATION, rater::LOW_DEVIATION, id, id,])
+        .unwrap();
 
-            while let Some(row) = rows.next().unwrap() {
-                let id_a: i64 = row.get(0).unwrap();
-                let id_b: i64 = row.get(1).unwrap();
-                let a: f64 = row.get(2).unwrap();
-                let b: f64 = row.get(3).unwrap();
+    let mut counts: FxHashMap<i64, i64> = Default::default();
 
-                if id_a == id {
-                    let delta = b - a;
+    let mut total = 0.0;
+    let mut over_100 = 0.0;
+    let mut over_200 = 0.0;
+    let mut over_300 = 0.0;
+    let mut over_400 = 0.0;
+    let mut below_100 = 0.0;
+    let mut below_200 = 0.0;
+    let mut below_300 = 0.0;
+    let mut below_400 = 0.0;
 
-                    if delta > 100.0 {
-                        over_100 += 1.0;
-                    }
-                    if delta > 200.0 {
-                        over_200 += 1.0;
-                    }
-                    if delta > 300.0 {
-                        over_300 += 1.0;
-                    }
-                    if delta > 400.0 {
-                        over_400 += 1.0;
-                    }
-                    if delta < -100.0 {
-                        below_100 += 1.0
-                    }
-                    if delta < -200.0 {
-                        below_200 += 1.0
-                    }
-                    if delta < -300.0 {
-                        below_300 += 1.0
-                    }
-                    if delta < -400.0 {
-                        below_400 += 1.0
-                    }
-                    total += 1.0;
+    while let Some(row) = rows.next().unwrap() {
+        let id_a: i64 = row.get(0).unwrap();
+        let id_b: i64 = row.get(1).unwrap();
+        let a: f64 = row.get(2).unwrap();
+        let b: f64 = row.get(3).unwrap();
 
-                    let bucket = ((delta + 12.5) / 25.0).floor() as i64;
+        if id_a == id {
+            let delta = b - a;
 
-                    *counts.entry(bucket).or_default() += 1;
-                }
+            if delta > 100.0 {
+                over_100 += 1.0;
+            }
+            if delta > 200.0 {
+                over_200 += 1.0;
+            }
+            if delta > 300.0 {
+                over_300 += 1.0;
+            }
+            if delta > 400.0 {
+                over_400 += 1.0;
+            }
+            if delta < -100.0 {
+                below_100 += 1.0
+            }
+            if delta < -200.0 {
+                below_200 += 1.0
+            }
+            if delta < -300.0 {
+                below_300 += 1.0
+            }
+            if delta < -400.0 {
+                below_400 += 1.0
+            }
+            total += 1.0;
 
-                if id_b == id {
-                    let delta = a - b;
+            let bucket = ((delta + 12.5) / 25.0).floor() as i64;
 
-                    if delta > 100.0 {
-                        over_100 += 1.0;
-                    }
-                    if delta > 200.0 {
-                        over_200 += 1.0;
-                    }
-                    if delta > 300.0 {
-                        over_300 += 1.0;
-                    }
-                    if delta > 400.0 {
-                        over_400 += 1.0;
-                    }
-                    if delta < -100.0 {
-                        below_100 += 1.0
-                    }
-                    if delta < -200.0 {
-                        below_200 += 1.0
-                    }
-                    if delta < -300.0 {
-                        below_300 += 1.0
-                    }
-                    if delta < -400.0 {
-                        below_400 += 1.0
-                    }
-                    total += 1.0;
+            *counts.entry(bucket).or_default() += 1;
+        }
 
-                    let bucket = ((delta + 12.5) / 25.0).floor() as i64;
+        if id_b == id {
+            let delta = a - b;
 
-                    *counts.entry(bucket).or_default() += 1;
-                }
+            if delta > 100.0 {
+                over_100 += 1.0;
+            }
+            if delta > 200.0 {
+                over_200 += 1.0;
+            }
+            if delta > 300.0 {
+                over_300 += 1.0;
+            }
+            if delta > 400.0 {
+                over_400 += 1.0;
+            }
+            if delta < -100.0 {
+                below_100 += 1.0
+            }
+            if delta < -200.0 {
+                below_200 += 1.0
             }
+            if delta < -300.0 {
+                below_300 += 1.0
+            }
+            if delta < -400.0 {
+                below_400 += 1.0
+            }
+            total += 1.0;
 
-            let min_bucket = -30;
-            let max_bucket = 30;
-            //let min_bucket = *counts.keys().min().unwrap();
-            //let max_bucket = *counts.keys().max().unwrap();
+            let bucket = ((delta + 12.5) / 25.0).floor() as i64;
 
-            RatingDiffStats {
-                over_100: over_100 / total,
-                over_200: over_200 / total,
-                over_300: over_300 / total,
-                over_400: over_400 / total,
-                below_100: below_100 / total,
-                below_200: below_200 / total,
-                below_300: below_300 / total,
-                below_400: below_400 / total,
-                difference_amounts: (min_bucket..=max_bucket)
-                    .into_iter()
-                    .map(|r| r * 25.0 as i64)
-                    .collect(),
-                difference_counts: (min_bucket..=max_bucket)
-                    .into_iter()
-                    .map(|r| (counts.get(&r).copied().unwrap_or(0) as f64 / total * 100.0))
-                    .collect(),
-            }
-        })
-        .await,
-    )
+            *counts.entry(bucket).or_default() += 1;
+        }
+    }
+
+    let min_bucket = -30;
+    let max_bucket = 30;
+    //let min_bucket = *counts.keys().min().unwrap();
+    //let max_bucket = *counts.keys().max().unwrap();
+
+    RatingDiffStats {
+        over_100: over_100 / total,
+        over_200: over_200 / total,
+        over_300: over_300 / total,
+        over_400: over_400 / total,
+        below_100: below_100 / total,
+        below_200: below_200 / total,
+        below_300: below_300 / total,
+        below_400: below_400 / total,
+        difference_amounts: (min_bucket..=max_bucket)
+            .into_iter()
+            .map(|r| r * 25.0 as i64)
+            .collect(),
+        difference_counts: (min_bucket..=max_bucket)
+            .into_iter()
+            .map(|r| (counts.get(&r).copied().unwrap_or(0) as f64 / total * 100.0))
+            .collect(),
+    }
 }
 
 #[get("/api/rating_experience?<min_rating>&<max_rating>")]
@@ -2372,212 +4677,639 @@ pub struct FloorRatingDistributions {
     overall: Vec<f64>,
 }
 
-#[get("/api/floor_rating_distribution")]
-pub async fn floor_rating_distribution(conn: RatingsDbConn) -> Json<FloorRatingDistributions> {
-    Json(
-        conn.run(move |conn| {
-            let mut stmt = conn
-                .prepare(
-                    "SELECT floor, value
-                    FROM players NATURAL JOIN player_ratings
-                    WHERE deviation < ?",
-                )
-                .unwrap();
-
-            let mut rows = stmt.query(params![rater::LOW_DEVIATION]).unwrap();
+#[get("/api/floor_rating_distribution")]
+pub async fn floor_rating_distribution(conn: RatingsDbConn) -> Json<FloorRatingDistributions> {
+    Json(
+        conn.run(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT floor, value
+                    FROM players NATURAL JOIN player_ratings
+                    WHERE deviation < ?",
+                )
+                .unwrap();
+
+            let mut rows = stmt.query(params![rater::LOW_DEVIATION]).unwrap();
+
+            let mut totals: FxHashMap<i64, FxHashMap<i64, i64>> = Default::default();
+            let mut overall: FxHashMap<i64, i64> = Default::default();
+
+            while let Some(row) = rows.next().unwrap() {
+                let floor: i64 = row.get(0).unwrap();
+                let value: f64 = row.get(1).unwrap();
+                //let value = value * 173.718 + 1500.0;
+
+                let bucket = ((value + 25.0) / 50.0).floor() as i64;
+
+                *totals.entry(floor).or_default().entry(bucket).or_default() += 1;
+                *overall.entry(bucket).or_default() += 1;
+            }
+
+            let min_bucket = *totals.values().flat_map(|f| f.keys()).min().unwrap();
+            let max_bucket = *totals.values().flat_map(|f| f.keys()).max().unwrap();
+
+            FloorRatingDistributions {
+                ratings: (min_bucket..max_bucket)
+                    .into_iter()
+                    .map(|r| r * 50)
+                    .collect(),
+                floors: totals
+                    .into_iter()
+                    .map(|(f, sums)| {
+                        //let max: i64 = *sums.values().max().unwrap();
+                        (
+                            f,
+                            (min_bucket..max_bucket)
+                                .into_iter()
+                                .map(|r| (sums.get(&r).copied().unwrap_or(0) as f64))
+                                .collect(),
+                        )
+                    })
+                    .collect(),
+                overall: (min_bucket..max_bucket)
+                    .into_iter()
+                    .map(|r| (overall.get(&r).copied().unwrap_or(0) as f64))
+                    .collect(),
+            }
+        })
+        .await,
+    )
+}
+
+#[get("/api/outcomes")]
+pub async fn outcomes(conn: RatingsDbConn) -> Json<(Vec<i64>, Vec<f64>, Vec<f64>)> {
+    Json(
+        conn.run(move |conn| {
+            let mut outcomes = vec![(0.0, 0.0); 101];
+
+            let mut stmt = conn
+                .prepare(
+                    "SELECT
+                value_a, deviation_a, value_b, deviation_b, winner
+                FROM games NATURAL JOIN game_ratings",
+                )
+                .unwrap();
+
+            let mut rows = stmt.query(params![]).unwrap();
+            while let Some(row) = rows.next().unwrap() {
+                let rating_a = Rating::new(row.get(0).unwrap(), row.get(1).unwrap());
+                let rating_b = Rating::new(row.get(2).unwrap(), row.get(3).unwrap());
+                let winner: i64 = row.get(4).unwrap();
+
+                let p = Rating::expected(rating_a, rating_b);
+
+                let o = outcomes.get_mut((p * 100.0).round() as usize).unwrap();
+                if winner == 1 {
+                    o.0 += 1.0;
+                }
+                o.1 += 1.0;
+            }
+
+            (
+                (0..=100).into_iter().collect(),
+                outcomes
+                    .into_iter()
+                    .map(|(wins, total)| wins / total)
+                    .collect(),
+                (0..=100).into_iter().map(|i| i as f64 / 100.0).collect(),
+            )
+        })
+        .await,
+    )
+}
+
+/// How long a verification code can be put in the R-Code comment, in seconds.
+const VERIFICATION_CODE_LIFETIME: i64 = 15 * 60;
+/// Minimum time between two looks at a player's R-Code comment, in seconds. Every poll is a request
+/// to the game servers, so this is per player rather than per verification.
+const VERIFICATION_POLL_INTERVAL: i64 = 10;
+/// Most verifications that can be started for a single player in a day.
+const VERIFICATIONS_PER_DAY: i64 = 10;
+
+/// What a player can confirm with a code in their R-Code comment.
+#[derive(Clone, Copy)]
+enum Verification {
+    Hide,
+    Unhide,
+    Region,
+}
+
+impl Verification {
+    fn name(self) -> &'static str {
+        match self {
+            Verification::Hide => "hide",
+            Verification::Unhide => "unhide",
+            Verification::Region => "region",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct VerificationCode {
+    code: String,
+    expires_in: i64,
+}
+
+#[derive(Serialize)]
+pub struct VerificationPoll {
+    /// One of "verified", "pending", "throttled", "expired" or "none".
+    status: &'static str,
+    retry_in: i64,
+}
+
+/// Only `hidden` and `pending` are filled in for anyone but the player, see `verification_status`.
+#[derive(Serialize)]
+pub struct VerificationStatus {
+    hidden: bool,
+    hidden_since: Option<String>,
+    region: Option<&'static str>,
+    history: Option<Vec<HiddenStatusChange>>,
+    pending: Vec<PendingVerification>,
+}
+
+#[derive(Serialize)]
+pub struct HiddenStatusChange {
+    date: String,
+    action: String,
+}
+
+#[derive(Serialize)]
+pub struct PendingVerification {
+    action: String,
+    expires_in: i64,
+}
+
+/// Seconds until the player's R-Code comment can be looked at again.
+fn verification_throttle(conn: &Connection, id: i64, now: i64) -> i64 {
+    let last_poll: Option<i64> = conn
+        .query_row(
+            "SELECT last_poll FROM verification_polls WHERE id = ?",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()
+        .unwrap();
+    last_poll.map_or(0, |last_poll| {
+        (last_poll + VERIFICATION_POLL_INTERVAL - now).max(0)
+    })
+}
+
+/// Starts a verification. Anyone can start one for any player, so a pending code is never
+/// replaced until it expires, and a player can only have VERIFICATIONS_PER_DAY started a day.
+/// Starting one can't be done while the poll throttle is on either.
+fn start_verification(
+    conn: &mut Connection,
+    id: i64,
+    verification: Verification,
+    payload: Option<&str>,
+    now: i64,
+) -> std::result::Result<VerificationCode, Status> {
+    if verification_throttle(conn, id, now) > 0 {
+        return Err(Status::TooManyRequests);
+    }
+
+    let tx = conn.transaction().unwrap();
+    let pending: bool = tx
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM verifications WHERE id = ? AND action = ? AND expires > ?)",
+            params![id, verification.name(), now],
+            |r| r.get(0),
+        )
+        .unwrap();
+    if pending {
+        return Err(Status::Conflict);
+    }
+
+    let recent: i64 = tx
+        .query_row(
+            "SELECT COUNT(*) FROM verification_starts WHERE id = ? AND timestamp > ?",
+            params![id, now - 24 * 60 * 60],
+            |r| r.get(0),
+        )
+        .unwrap();
+    if recent >= VERIFICATIONS_PER_DAY {
+        return Err(Status::TooManyRequests);
+    }
+
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+    tx.execute(
+        "INSERT OR REPLACE INTO verifications(id, action, code, payload, created, expires)
+        VALUES(?, ?, ?, ?, ?, ?)",
+        params![
+            id,
+            verification.name(),
+            &code,
+            payload,
+            now,
+            now + VERIFICATION_CODE_LIFETIME
+        ],
+    )
+    .unwrap();
+    tx.execute(
+        "INSERT INTO verification_starts(id, timestamp) VALUES(?, ?)",
+        params![id, now],
+    )
+    .unwrap();
+    tx.commit().unwrap();
+
+    Ok(VerificationCode {
+        code,
+        expires_in: VERIFICATION_CODE_LIFETIME,
+    })
+}
+
+/// Takes the code of a pending verification to look for in the R-Code comment, and counts that
+/// as a poll of the player. Expired verifications are removed. The error is the poll status and
+/// how long until the next poll.
+fn claim_verification(
+    conn: &mut Connection,
+    id: i64,
+    verification: Verification,
+    now: i64,
+) -> std::result::Result<(String, Option<String>), (&'static str, i64)> {
+    let tx = conn.transaction().unwrap();
+    let pending: Option<(String, Option<String>, i64)> = tx
+        .query_row(
+            "SELECT code, payload, expires FROM verifications WHERE id = ? AND action = ?",
+            params![id, verification.name()],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )
+        .optional()
+        .unwrap();
+
+    let (code, payload, expires) = match pending {
+        Some(pending) => pending,
+        None => return Err(("none", 0)),
+    };
+
+    if expires <= now {
+        tx.execute(
+            "DELETE FROM verifications WHERE id = ? AND action = ?",
+            params![id, verification.name()],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        return Err(("expired", 0));
+    }
+
+    let retry_in = verification_throttle(&tx, id, now);
+    if retry_in > 0 {
+        return Err(("throttled", retry_in));
+    }
+
+    tx.execute(
+        "INSERT OR REPLACE INTO verification_polls(id, last_poll) VALUES(?, ?)",
+        params![id, now],
+    )
+    .unwrap();
+    tx.commit().unwrap();
+    Ok((code, payload))
+}
+
+/// Checks a pending verification against the player's R-Code comment. The verification is removed
+/// once it is confirmed or expired, a confirmed one returns its payload.
+async fn poll_verification(
+    conn: &RatingsDbConn,
+    id: i64,
+    verification: Verification,
+) -> (VerificationPoll, Option<String>) {
+    let now = Utc::now().timestamp();
+
+    let pending = conn
+        .run(move |conn| claim_verification(conn, id, verification, now))
+        .await;
+
+    let (code, payload) = match pending {
+        Ok(pending) => pending,
+        Err((status, retry_in)) => return (VerificationPoll { status, retry_in }, None),
+    };
+
+    if !public_comment_contains(id, &code).await {
+        return (
+            VerificationPoll {
+                status: "pending",
+                retry_in: VERIFICATION_POLL_INTERVAL,
+            },
+            None,
+        );
+    }
+
+    conn.run(move |conn| {
+        conn.execute(
+            "DELETE FROM verifications WHERE id = ? AND action = ?",
+            params![id, verification.name()],
+        )
+        .unwrap();
+    })
+    .await;
+
+    (
+        VerificationPoll {
+            status: "verified",
+            retry_in: 0,
+        },
+        payload,
+    )
+}
+
+#[post("/api/hide/<player>")]
+pub async fn start_hide_player(
+    conn: RatingsDbConn,
+    player: &str,
+) -> std::result::Result<Json<VerificationCode>, Status> {
+    let id = i64::from_str_radix(&player, 16).map_err(|_| Status::NotFound)?;
+    let now = Utc::now().timestamp();
 
-            let mut totals: FxHashMap<i64, FxHashMap<i64, i64>> = Default::default();
-            let mut overall: FxHashMap<i64, i64> = Default::default();
+    conn.run(move |conn| {
+        if is_hidden(conn, id) {
+            return Err(Status::NotFound);
+        }
+        start_verification(conn, id, Verification::Hide, None, now).map(Json)
+    })
+    .await
+}
 
-            while let Some(row) = rows.next().unwrap() {
-                let floor: i64 = row.get(0).unwrap();
-                let value: f64 = row.get(1).unwrap();
-                //let value = value * 173.718 + 1500.0;
+#[post("/api/hide/poll/<player>")]
+pub async fn poll_hide_player(conn: RatingsDbConn, player: &str) -> Option<Json<VerificationPoll>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
 
-                let bucket = ((value + 25.0) / 50.0).floor() as i64;
+    let (poll, _) = poll_verification(&conn, id, Verification::Hide).await;
+    if poll.status == "verified" {
+        info!("Hiding player {id}");
+        conn.run(move |conn| rater::set_hidden(conn, id, Some("enabled"), "PlayerAutomated"))
+            .await
+            .unwrap();
+    }
 
-                *totals.entry(floor).or_default().entry(bucket).or_default() += 1;
-                *overall.entry(bucket).or_default() += 1;
-            }
+    Some(Json(poll))
+}
 
-            let min_bucket = *totals.values().flat_map(|f| f.keys()).min().unwrap();
-            let max_bucket = *totals.values().flat_map(|f| f.keys()).max().unwrap();
+#[post("/api/unhide/<player>")]
+pub async fn start_unhide_player(
+    conn: RatingsDbConn,
+    player: &str,
+) -> std::result::Result<Json<VerificationCode>, Status> {
+    let id = i64::from_str_radix(&player, 16).map_err(|_| Status::NotFound)?;
+    let now = Utc::now().timestamp();
 
-            FloorRatingDistributions {
-                ratings: (min_bucket..max_bucket)
-                    .into_iter()
-                    .map(|r| r * 50)
-                    .collect(),
-                floors: totals
-                    .into_iter()
-                    .map(|(f, sums)| {
-                        //let max: i64 = *sums.values().max().unwrap();
-                        (
-                            f,
-                            (min_bucket..max_bucket)
-                                .into_iter()
-                                .map(|r| (sums.get(&r).copied().unwrap_or(0) as f64))
-                                .collect(),
-                        )
-                    })
-                    .collect(),
-                overall: (min_bucket..max_bucket)
-                    .into_iter()
-                    .map(|r| (overall.get(&r).copied().unwrap_or(0) as f64))
-                    .collect(),
-            }
-        })
-        .await,
-    )
+    conn.run(move |conn| {
+        if !is_hidden(conn, id) {
+            return Err(Status::NotFound);
+        }
+        start_verification(conn, id, Verification::Unhide, None, now).map(Json)
+    })
+    .await
 }
 
-#[get("/api/outcomes")]
-pub async fn outcomes(conn: RatingsDbConn) -> Json<(Vec<i64>, Vec<f64>, Vec<f64>)> {
-    Json(
-        conn.run(move |conn| {
-            let mut outcomes = vec![(0.0, 0.0); 101];
+#[post("/api/unhide/poll/<player>")]
+pub async fn poll_unhide_player(
+    conn: RatingsDbConn,
+    player: &str,
+) -> Option<Json<VerificationPoll>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+
+    let (poll, _) = poll_verification(&conn, id, Verification::Unhide).await;
+    if poll.status == "verified" {
+        info!("Unhiding player {id}");
+        conn.run(move |conn| rater::set_hidden(conn, id, None, "PlayerAutomated"))
+            .await
+            .unwrap();
+    }
 
-            let mut stmt = conn
-                .prepare(
-                    "SELECT
-                value_a, deviation_a, value_b, deviation_b, winner
-                FROM games NATURAL JOIN game_ratings",
-                )
-                .unwrap();
+    Some(Json(poll))
+}
 
-            let mut rows = stmt.query(params![]).unwrap();
-            while let Some(row) = rows.next().unwrap() {
-                let rating_a = Rating::new(row.get(0).unwrap(), row.get(1).unwrap());
-                let rating_b = Rating::new(row.get(2).unwrap(), row.get(3).unwrap());
-                let winner: i64 = row.get(4).unwrap();
+/// Checks whether the player's R-Code comment contains the given verification code.
+async fn public_comment_contains(id: i64, code: &str) -> bool {
+    info!("Getting player stats");
+    let json = ggst_api::get_player_stats(id.to_string()).await;
+    let lookup = format!("PublicComment\":\"{code}");
+
+    match json {
+        Ok(json) => json.contains(&lookup),
+        Err(er) => {
+            error!("error {}", er);
+            false
+        }
+    }
+}
 
-                let p = Rating::expected(rating_a, rating_b);
+#[post("/api/region/<player>?<region>")]
+pub async fn start_set_region(
+    conn: RatingsDbConn,
+    player: &str,
+    region: &str,
+) -> std::result::Result<Json<VerificationCode>, Status> {
+    let id = i64::from_str_radix(&player, 16).map_err(|_| Status::NotFound)?;
+    let region = get_region(region).ok_or(Status::BadRequest)?;
+    let now = Utc::now().timestamp();
 
-                let o = outcomes.get_mut((p * 100.0).round() as usize).unwrap();
-                if winner == 1 {
-                    o.0 += 1.0;
-                }
-                o.1 += 1.0;
-            }
+    conn.run(move |conn| {
+        start_verification(conn, id, Verification::Region, Some(region), now).map(Json)
+    })
+    .await
+}
 
-            (
-                (0..=100).into_iter().collect(),
-                outcomes
-                    .into_iter()
-                    .map(|(wins, total)| wins / total)
-                    .collect(),
-                (0..=100).into_iter().map(|i| i as f64 / 100.0).collect(),
+#[post("/api/region/poll/<player>")]
+pub async fn poll_set_region(conn: RatingsDbConn, player: &str) -> Option<Json<VerificationPoll>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+
+    let (poll, region) = poll_verification(&conn, id, Verification::Region).await;
+    if let Some(region) = region.as_deref().and_then(get_region) {
+        conn.run(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO player_regions(id, region) VALUES(?, ?)",
+                params![id, region],
             )
+            .unwrap();
         })
-        .await,
-    )
-}
+        .await;
+    }
 
-#[get("/api/hide/<player>")]
-pub async fn start_hide_player(conn: RatingsDbConn, player: &str) -> Json<String> {
-    let id = i64::from_str_radix(&player, 16).unwrap();
-    let player_code = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+    Some(Json(poll))
+}
 
-    let code = conn
-        .run(move |conn| {
-            let tx = conn.transaction().unwrap();
+/// What a player has confirmed about their profile and what is still waiting for a code. Anyone
+/// can see whether the player is hidden and what is pending, the rest only comes with the code
+/// of a pending verification, which only the player has.
+#[get("/api/verification/<player>?<code>")]
+pub async fn verification_status(
+    conn: RatingsDbConn,
+    player: &str,
+    code: Option<String>,
+) -> Option<Json<VerificationStatus>> {
+    let id = i64::from_str_radix(&player, 16).ok()?;
+    let now = Utc::now().timestamp();
+
+    Some(Json(
+        conn.run(move |conn| verification_status_inner(conn, id, code.as_deref(), now))
+            .await,
+    ))
+}
 
-            let exists: i64 = tx
-                .query_row(
-                    "SELECT count(id) FROM hidden_status WHERE id=? and hidden_status is not null",
-                    params![&id],
-                    |r| r.get(0),
-                )
-                .unwrap();
+fn verification_status_inner(
+    conn: &Connection,
+    id: i64,
+    code: Option<&str>,
+    now: i64,
+) -> VerificationStatus {
+    let hidden_since: Option<i64> = conn
+        .query_row(
+            "SELECT timestamp FROM hidden_status WHERE id = ?",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()
+        .unwrap();
 
-            if exists > 0 {
-                tx.execute(
-                    "UPDATE hidden_status SET code=? WHERE id=?",
-                    params![&player_code, &id],
-                )
-                .unwrap();
-            } else {
-                tx.execute(
-                    "INSERT or replace INTO hidden_status(id, hidden_status, code, notes)
-                VALUES(?, NULL, ?, 'PlayerAutomated')",
-                    params![&id, &player_code],
-                )
-                .unwrap();
-            }
-            let _ = tx.commit();
-            player_code
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT action, code, expires FROM verifications
+            WHERE id = ? AND expires > ?
+            ORDER BY action",
+        )
+        .unwrap();
+    let verifications = stmt
+        .query_map(params![id, now], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, String, i64)>>();
+    let authorized = verifications
+        .iter()
+        .any(|(_, pending_code, _)| Some(pending_code.as_str()) == code);
+    let pending = verifications
+        .into_iter()
+        .map(|(action, _, expires)| PendingVerification {
+            action,
+            expires_in: expires - now,
         })
-        .await;
+        .collect();
+
+    if !authorized {
+        return VerificationStatus {
+            hidden: hidden_since.is_some(),
+            hidden_since: None,
+            region: None,
+            history: None,
+            pending,
+        };
+    }
 
-    Json(code)
-}
+    let region: Option<String> = conn
+        .query_row(
+            "SELECT region FROM player_regions WHERE id = ?",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()
+        .unwrap();
 
-#[get("/api/hide/poll/<player>")]
-pub async fn poll_hide_player(conn: RatingsDbConn, player: &str) -> Json<bool> {
-    info!("Starting poll hide player");
-    if let Ok(id) = i64::from_str_radix(&player, 16) {
-        let code: String = conn
-            .run(move |conn| {
-                conn.query_row(
-                    "SELECT code FROM hidden_status WHERE id=?",
-                    params![&id],
-                    |r| r.get(0),
-                )
-                .unwrap_or_else(|_| "".to_owned())
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT timestamp, action FROM hidden_status_history
+            WHERE id = ?
+            ORDER BY timestamp DESC",
+        )
+        .unwrap();
+    let history = stmt
+        .query_map(params![id], |r| {
+            Ok(HiddenStatusChange {
+                date: format_date(r.get(0)?),
+                action: r.get(1)?,
             })
-            .await;
-        info!("Grabbed code {code}");
+        })
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+
+    VerificationStatus {
+        hidden: hidden_since.is_some(),
+        hidden_since: hidden_since.map(format_date),
+        region: region.as_deref().and_then(|r| {
+            website::REGIONS
+                .iter()
+                .find(|(short, _)| *short == r)
+                .map(|(_, name)| *name)
+        }),
+        history: Some(history),
+        pending,
+    }
+}
 
-        if code.is_empty() {
-            info!("No code, returning false");
-            Json(false)
-        } else {
-            info!("Getting player stats");
-            let json = ggst_api::get_player_stats(id.to_string()).await;
-            let lookup = format!("PublicComment\":\"{code}");
-
-            let found = match json {
-                Ok(json) => json.contains(&lookup),
-                Err(er) => {
-                    error!("error {}", er);
-                    false
-                }
-            };
+/// Most cheat reports a single address can submit in a day.
+const REPORTS_PER_DAY: i64 = 5;
+const REPORT_MAX_LINKS: usize = 5;
+const REPORT_MAX_LENGTH: usize = 1000;
 
-            if found {
-                info!("Found code, setting hidden status");
-                conn.run(move |conn| {
-                    let exists: i64 = conn.query_row(
-                        "SELECT count(id) FROM hidden_status WHERE id=? and hidden_status is not null",
-                        params![&id],
-                        |r| r.get(0)
-                    ).unwrap();
+#[derive(Deserialize)]
+pub struct CheatReport {
+    reason: String,
+    #[serde(default)]
+    links: Vec<String>,
+}
 
-                    info!("Does code exist? {exists}");
+/// Queues a cheat report for the moderators, returns the id of the report. Reports are limited by
+/// the peer address of the connection rather than Rocket's `IpAddr` guard, which trusts whatever a
+/// client puts in `X-Real-IP` since the server isn't behind a proxy that sets it.
+#[post("/api/report/<player>", data = "<report>")]
+pub async fn report_player(
+    conn: RatingsDbConn,
+    remote: SocketAddr,
+    player: &str,
+    report: Json<CheatReport>,
+) -> std::result::Result<Json<i64>, Status> {
+    let id = i64::from_str_radix(player, 16).map_err(|_| Status::NotFound)?;
+    let CheatReport { reason, links } = report.into_inner();
+
+    let reason = reason.trim().to_owned();
+    if reason.is_empty()
+        || reason.len() > REPORT_MAX_LENGTH
+        || links.len() > REPORT_MAX_LINKS
+        || links.iter().any(|l| {
+            !(l.starts_with("https://") || l.starts_with("http://"))
+                || l.len() > REPORT_MAX_LENGTH
+                || l.contains(char::is_whitespace)
+        })
+    {
+        return Err(Status::BadRequest);
+    }
 
-                    if exists > 0 {
-                        info!("Setting hidden status to null");
-                        conn.execute(
-                            "UPDATE hidden_status SET hidden_status=NULL, code=NULL WHERE id=?",
-                            params![&id],
-                        ).unwrap();
+    let ip = remote.ip().to_string();
+    let now = Utc::now().timestamp();
 
-                    } else {
-                        info!("Setting hidden status to enabled");
-                        conn.execute(
-                            "UPDATE hidden_status SET hidden_status='enabled', code=NULL WHERE id=?",
-                            params![&id]
-                        ).unwrap();
-                    }
-                }).await;
+    conn.run(move |conn| {
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM players WHERE id = ?)",
+                params![id],
+                |r| r.get(0),
+            )
+            .unwrap();
+        if !exists {
+            return Err(Status::NotFound);
+        }
 
-                return Json(true);
-            }
-            Json(false)
+        let recent: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM cheat_reports WHERE ip = ? AND timestamp > ?",
+                params![&ip, now - 24 * 60 * 60],
+                |r| r.get(0),
+            )
+            .unwrap();
+        if recent >= REPORTS_PER_DAY {
+            return Err(Status::TooManyRequests);
         }
-    } else {
-        Json(false)
-    }
+
+        conn.execute(
+            "INSERT INTO cheat_reports(id, timestamp, reason, links, ip, status)
+            VALUES(?, ?, ?, ?, ?, 'open')",
+            params![id, now, reason, links.join("\n"), ip],
+        )
+        .unwrap();
+
+        Ok(Json(conn.last_insert_rowid()))
+    })
+    .await
 }
 
 #[get("/api/outcomes_delta")]
@@ -2630,3 +5362,252 @@ pub async fn outcomes_delta(conn: RatingsDbConn) -> Json<(Vec<i64>, Vec<f64>, Ve
         .await,
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("../init.sql")).unwrap();
+        conn
+    }
+
+    #[test]
+    fn percentiles() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO rating_percentile_counts VALUES(-1, 1000);
+            INSERT INTO rating_percentiles VALUES(-1, 0, 1000.0);
+            INSERT INTO rating_percentiles VALUES(-1, 500, 1500.0);
+            INSERT INTO rating_percentiles VALUES(-1, 1000, 2000.0);",
+        )
+        .unwrap();
+
+        let p = get_percentile(&conn, None, 1250.0, None).unwrap();
+        assert!((p.percentile - 25.0).abs() < 1e-9);
+        assert_eq!(p.player_count, 1000);
+        assert!(!p.exact);
+
+        let percentile = |value| get_percentile(&conn, None, value, None).unwrap().percentile;
+        assert_eq!(percentile(1500.0), 50.0);
+        assert_eq!(percentile(900.0), 0.0);
+        assert_eq!(percentile(2500.0), 100.0);
+
+        let p = get_percentile(&conn, None, 1250.0, Some(10)).unwrap();
+        assert_eq!(p.percentile, 99.0);
+        assert!(p.exact);
+
+        assert!(get_percentile(&conn, Some(0), 1250.0, None).is_none());
+    }
+
+    #[test]
+    fn name_search() {
+        let conn = test_db();
+        for (id, name) in [
+            (1, "Ｓｏｌ Badguy"),
+            (2, "𝐒𝐨𝐥"),
+            (3, "Ky Kiske"),
+            (4, "★★★"),
+        ] {
+            conn.execute(
+                "INSERT INTO player_names VALUES(?, ?, 0, 0)",
+                params![id, name],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO player_name_search(normalized_name, id, name) VALUES(?, ?, ?)",
+                params![rater::normalize_name(name), id, name],
+            )
+            .unwrap();
+        }
+
+        let kinds = |search: &str, exact: bool| {
+            let mut kinds = name_matches(&conn, search, exact)
+                .unwrap()
+                .into_iter()
+                .map(|(id, (_, name_match))| (id, name_match.kind))
+                .collect::<Vec<_>>();
+            kinds.sort();
+            kinds
+        };
+
+        assert_eq!(kinds("sol", false), [(1, 2), (2, 3)]);
+        assert_eq!(kinds("SOL", false), [(1, 2), (2, 3)]);
+        assert_eq!(kinds("kiske", false), [(3, 1)]);
+        assert_eq!(kinds("ky", false), [(3, 2)]);
+        assert_eq!(kinds("kyklske", false), [(3, 0)]);
+        assert_eq!(kinds("★★★", false), [(4, 3)]);
+        assert_eq!(kinds("Ky Kiske", true), [(3, 3)]);
+        assert!(kinds("sol", true).is_empty());
+    }
+
+    #[test]
+    fn verifications() {
+        let mut conn = test_db();
+        let now = 1_000_000;
+        let region = Verification::Region;
+
+        assert_eq!(
+            claim_verification(&mut conn, 1, region, now),
+            Err(("none", 0))
+        );
+
+        let code = start_verification(&mut conn, 1, region, Some("EU"), now)
+            .unwrap()
+            .code;
+        assert_eq!(
+            claim_verification(&mut conn, 1, region, now),
+            Ok((code.clone(), Some("EU".to_owned())))
+        );
+
+        // Polling and restarting are both throttled per player
+        assert_eq!(
+            claim_verification(&mut conn, 1, region, now + 1),
+            Err(("throttled", VERIFICATION_POLL_INTERVAL - 1))
+        );
+        assert_eq!(
+            start_verification(&mut conn, 1, region, Some("NA"), now + 1).err(),
+            Some(Status::TooManyRequests)
+        );
+        assert!(start_verification(&mut conn, 2, Verification::Hide, None, now + 1).is_ok());
+
+        // A pending code isn't replaced by someone else starting over
+        let later = now + VERIFICATION_POLL_INTERVAL;
+        assert_eq!(
+            start_verification(&mut conn, 1, region, Some("NA"), later).err(),
+            Some(Status::Conflict)
+        );
+        assert_eq!(
+            claim_verification(&mut conn, 1, region, later),
+            Ok((code, Some("EU".to_owned())))
+        );
+
+        let expired = now + 1 + VERIFICATION_CODE_LIFETIME;
+        assert_eq!(
+            claim_verification(&mut conn, 2, Verification::Hide, expired),
+            Err(("expired", 0))
+        );
+        assert_eq!(
+            claim_verification(&mut conn, 2, Verification::Hide, expired),
+            Err(("none", 0))
+        );
+
+        // Restarting once the code expired still counts towards the daily limit
+        for i in 0..VERIFICATIONS_PER_DAY {
+            let start = now + i * VERIFICATION_CODE_LIFETIME;
+            assert!(start_verification(&mut conn, 3, Verification::Hide, None, start).is_ok());
+        }
+        assert_eq!(
+            start_verification(
+                &mut conn,
+                3,
+                Verification::Hide,
+                None,
+                now + VERIFICATIONS_PER_DAY * VERIFICATION_CODE_LIFETIME
+            )
+            .err(),
+            Some(Status::TooManyRequests)
+        );
+    }
+
+    #[test]
+    fn verification_details() {
+        let mut conn = test_db();
+        let now = 1_000_000;
+        conn.execute_batch(
+            "INSERT INTO hidden_status VALUES(1, 'enabled', '', 1000);
+            INSERT INTO hidden_status_history VALUES(1, 1000, 'hide', '');
+            INSERT INTO player_regions(id, region) VALUES(1, 'EU');",
+        )
+        .unwrap();
+        let code = start_verification(&mut conn, 1, Verification::Unhide, None, now)
+            .unwrap()
+            .code;
+
+        // Without the code a hidden player's details stay hidden
+        for guess in [None, Some("wrong")] {
+            let status = verification_status_inner(&conn, 1, guess, now);
+            assert!(status.hidden);
+            assert_eq!(status.pending.len(), 1);
+            assert!(status.hidden_since.is_none() && status.region.is_none());
+            assert!(status.history.is_none());
+        }
+
+        let status = verification_status_inner(&conn, 1, Some(&code), now);
+        assert!(status.hidden_since.is_some() && status.region.is_some());
+        assert_eq!(status.history.unwrap().len(), 1);
+
+        // The code stops working once it expired
+        let expired = now + VERIFICATION_CODE_LIFETIME;
+        let status = verification_status_inner(&conn, 1, Some(&code), expired);
+        assert!(status.pending.is_empty() && status.history.is_none());
+    }
+
+    #[test]
+    fn player_activity() {
+        const DAY: i64 = 24 * 60 * 60;
+        // Monday 2023-01-02 00:00 UTC
+        let monday = 1672617600;
+        let conn = test_db();
+        let days = [
+            (monday - 7 * DAY, 2),
+            (monday, 3),
+            (monday + DAY, 1),
+            (monday + 2 * DAY, 4),
+        ];
+        for (day, game_count) in days {
+            conn.execute(
+                "INSERT INTO player_daily_activity VALUES(1, ?, ?)",
+                params![day, game_count],
+            )
+            .unwrap();
+        }
+
+        let wednesday = monday + 2 * DAY + 3600;
+        let activity = get_player_activity(&conn, 1, 3, wednesday);
+        let weeks: Vec<i64> = activity.weeks.iter().map(|w| w.game_count).collect();
+        assert_eq!(weeks, [0, 2, 8]);
+        assert_eq!(activity.days_played, 4);
+        assert_eq!((activity.current_streak, activity.longest_streak), (3, 3));
+
+        // The same days seen from a later week
+        let activity = get_player_activity(&conn, 1, 3, monday + 7 * DAY);
+        let weeks: Vec<i64> = activity.weeks.iter().map(|w| w.game_count).collect();
+        assert_eq!(weeks, [2, 8, 0]);
+        assert_eq!((activity.current_streak, activity.longest_streak), (0, 3));
+    }
+
+    #[test]
+    fn popularity_changes() {
+        let before = [
+            Some((0.2, 10_000)),
+            Some((0.2, 10_000)),
+            Some((0.2, 100)),
+            None,
+            Some((0.2, 0)),
+            Some((0.3, 10_000)),
+        ];
+        let after = [
+            Some((0.3, 10_000)),
+            Some((0.2, 10_000)),
+            Some((0.3, 100)),
+            Some((0.3, 1_000)),
+            Some((0.3, 1_000)),
+            Some((0.2, 10_000)),
+        ];
+
+        website::load_characters(&test_db()).unwrap();
+        let trends = significant_popularity_changes(&before, &after);
+        assert_eq!(trends.len(), 2);
+
+        assert_eq!(trends[0].character, "Sol");
+        assert!(trends[0].rising);
+        assert_eq!((trends[0].before, trends[0].after), (20.0, 30.0));
+        assert!(trends[0].z > POPULARITY_TREND_Z);
+
+        assert_eq!(trends[1].character, "Potemkin");
+        assert!(!trends[1].rising);
+        assert!(trends[1].z < -POPULARITY_TREND_Z);
+    }
+}
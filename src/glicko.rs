@@ -95,6 +95,17 @@ impl Rating {
                     / 400.0,
             ))
     }
+
+    /// Range of the expected score when the rating difference is off by `z` standard deviations.
+    /// The spread already accounts for the deviations, so unlike `expected` this doesn't discount
+    /// the difference by `g`.
+    pub fn expected_interval(self, other: Rating, z: f64) -> (f64, f64) {
+        let difference = self.value - other.value;
+        let spread =
+            z * (self.deviation * self.deviation + other.deviation * other.deviation).sqrt();
+        let expected = |difference: f64| 1.0 / (1.0 + 10.0f64.powf(-difference / 400.0));
+        (expected(difference - spread), expected(difference + spread))
+    }
 }
 
 const Q: f64 = 0.0057565;
@@ -109,6 +120,24 @@ pub fn e(r: f64, r_j: f64, rd_j: f64) -> f64 {
     1.0 / (1.0 + 10.0f64.powf((1.0 - UNCERTAINTY) * -g(rd_j) * (r - r_j) / 400.0))
 }
 
+/// Wilson score interval of a win rate.
+pub fn wilson_interval(wins: i64, losses: i64, z: f64) -> (f64, f64) {
+    let n = (wins + losses) as f64;
+    if n == 0.0 {
+        return (0.0, 1.0);
+    }
+
+    let p = wins as f64 / n;
+    let center = p + z * z / (2.0 * n);
+    let spread = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    let denominator = 1.0 + z * z / n;
+
+    (
+        (center - spread) / denominator,
+        (center + spread) / denominator,
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -131,4 +160,29 @@ mod test {
 
         assert_eq!(a, b);
     }
+
+    #[test]
+    fn intervals() {
+        let a = Rating::new(1550.0, 20.0);
+        let b = Rating::new(1500.0, 20.0);
+        let (low, high) = a.expected_interval(b, 1.96);
+        assert!(low < a.expected(b) && a.expected(b) < high);
+        assert!(low < 0.5 && high > 0.5);
+
+        let (low, high) = a.expected_interval(b, 0.0);
+        assert!((low - high).abs() < 1e-12);
+
+        let (low, high) =
+            Rating::new(1600.0, 30.0).expected_interval(Rating::new(1500.0, 40.0), 2.0);
+        assert!((low - 0.5).abs() < 1e-12);
+        assert!((high - 0.7597469266479578).abs() < 1e-12);
+
+        let (low, high) = wilson_interval(55, 45, 1.96);
+        assert!(low < 0.5 && high > 0.55);
+
+        let (low, high) = wilson_interval(6000, 4000, 1.96);
+        assert!(low > 0.58 && high < 0.62);
+
+        assert_eq!(wilson_interval(0, 0, 1.96), (0.0, 1.0));
+    }
 }
@@ -3,6 +3,7 @@ extern crate rocket;
 #[macro_use]
 extern crate log;
 
+mod admin;
 mod api;
 mod ggst_api;
 mod glicko;
@@ -35,6 +35,10 @@ async fn main() {
     init_logging();
 
     let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.get(0).map(|r| r.deref()) != Some("init") {
+        rater::migrate_database().unwrap();
+        rater::load_characters().unwrap();
+    }
     match args.get(0).map(|r| r.deref()) {
         Some("init") => {
             rater::init_database().unwrap();
@@ -51,19 +55,98 @@ async fn main() {
         Some("fraud") => {
             rater::update_fraud_once().await;
         }
+        Some("anomalies") => {
+            rater::detect_anomalies_once().unwrap();
+        }
+        Some("smurfs") => {
+            rater::detect_smurfs_once().unwrap();
+        }
+        Some("backtest_floor_priors") => {
+            rater::backtest_floor_priors(args.get(1).map(|r| r.deref())).unwrap();
+        }
+        Some("backtest_seeding") => {
+            rater::backtest_seeding(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+            )
+            .unwrap();
+        }
+        Some("set_seeding") => {
+            rater::set_seeding(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+            )
+            .unwrap();
+        }
+        Some("set_floor_priors") => {
+            rater::set_floor_priors(args.get(1).map(|r| r.deref())).unwrap();
+        }
+        Some("set_smurf_policy") => {
+            rater::set_smurf_policy(args.get(1).map(|r| r.deref())).unwrap();
+        }
+        Some("bracket_matchups") => {
+            rater::calc_bracket_matchups_once();
+        }
+        Some("estimate_matchups") => {
+            rater::estimate_matchups_once();
+        }
         Some("mark_cheater") => {
             rater::mark_cheater(
                 args.get(1).map(|r| r.deref()),
                 args.get(2).map(|r| r.deref()),
                 args.get(3).map(|r| r.deref()),
             )
-            .await;
+            .unwrap();
         }
         Some("mark_vip") => {
-            rater::mark_vip(args.get(1).unwrap(), args.get(2).unwrap());
+            rater::mark_vip(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+            )
+            .unwrap();
         }
         Some("mark_hidden") => {
-            rater::mark_hidden(args.get(1).unwrap(), args.get(2).unwrap());
+            rater::mark_hidden(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+            )
+            .unwrap();
+        }
+        Some("add_moderator") => {
+            rater::add_moderator(args.get(1).map(|r| r.deref())).unwrap();
+        }
+        Some("remove_moderator") => {
+            rater::remove_moderator(args.get(1).map(|r| r.deref())).unwrap();
+        }
+        Some("add_season") => {
+            rater::add_season(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+                args.get(3).map(|r| r.deref()),
+            )
+            .unwrap();
+        }
+        Some("seasons") => {
+            rater::update_seasons_once().unwrap();
+        }
+        Some("add_patch") => {
+            rater::add_patch(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+                args.get(3).map(|r| r.deref()),
+            )
+            .unwrap();
+        }
+        Some("add_character") => {
+            rater::add_character(
+                args.get(1).map(|r| r.deref()),
+                args.get(2).map(|r| r.deref()),
+                args.get(3).map(|r| r.deref()),
+            )
+            .unwrap();
+        }
+        Some("remove_patch") => {
+            rater::remove_patch(args.get(1).map(|r| r.deref())).unwrap();
         }
         Some("print_rankings") => {
             rater::print_rankings();
@@ -77,6 +160,15 @@ async fn main() {
         Some("names") => {
             rater::reset_names().unwrap();
         }
+        Some("rebuild_search") => {
+            rater::rebuild_name_search().unwrap();
+        }
+        Some("rebuild_sessions") => {
+            rater::rebuild_sessions().unwrap();
+        }
+        Some("rebuild_activity") => {
+            rater::rebuild_activity().unwrap();
+        }
         Some("distribution") => {
             rater::reset_distribution().unwrap();
         }
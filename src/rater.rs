@@ -1,12 +1,14 @@
 use crate::{ggst_api, glicko, glicko::Rating, responses, website};
 use anyhow::Context;
-use chrono::{NaiveDateTime, Utc};
+use chrono::{Datelike, NaiveDateTime, Timelike, Utc};
 use fxhash::{FxHashMap, FxHashSet};
 use lazy_static::lazy_static;
+use rand::distributions::{Alphanumeric, DistString};
 use rusqlite::{
-    functions::FunctionFlags, named_params, params, Connection, OptionalExtension, Row, Transaction,
+    functions::FunctionFlags, named_params, params, types::Type, Connection, OptionalExtension,
+    Row, Transaction,
 };
-use std::{sync::Mutex, time::Duration};
+use std::{collections::hash_map::Entry, sync::Mutex, time::Duration};
 use tokio::{time, try_join};
 
 const DECAY_CONSTANT: f64 = 3.1;
@@ -15,13 +17,167 @@ pub const LOW_DEVIATION: f64 = 75.0;
 pub const HIGH_RATING: f64 = 1800.0;
 pub const DB_NAME: &str = "ratings.sqlite";
 
-const CHAR_COUNT: usize = website::CHAR_NAMES.len();
 pub const POP_RATING_BRACKETS: usize = 13;
+/// Rating thresholds the fraud index is calculated above, 0 counts every rating.
+pub const FRAUD_THRESHOLDS: &[i64] = &[0, 1500, 1800];
+pub const BRACKET_MATCHUP_WINDOW: i64 = 60 * 60 * 24 * 28;
+pub const MATCHUP_ESTIMATE_WINDOW: i64 = 60 * 60 * 24 * 28;
+
+/// How far back `detect_anomalies` looks.
+const ANOMALY_WINDOW: i64 = 60 * 60 * 24 * 7;
+/// A win streak is flagged when the expected outcomes give it less than this chance.
+const ANOMALY_STREAK_CHANCE: f64 = 1e-6;
+/// Largest rating gain from an established rating within the window before it is flagged.
+const ANOMALY_MAX_GAIN: f64 = 500.0;
+/// Two players are flagged when they play this many games against each other and the wins either
+/// alternate or go almost all one way.
+const ANOMALY_PAIR_GAMES: i64 = 30;
+const ANOMALY_PAIR_ALTERNATION: f64 = 0.8;
+const ANOMALY_PAIR_ONE_SIDED: f64 = 0.9;
+/// An opponent counts as a new account with at least this deviation and at most this many games.
+const ANOMALY_NEW_ACCOUNT_DEVIATION: f64 = 250.0;
+const ANOMALY_NEW_ACCOUNT_GAMES: i64 = 10;
+/// How many new accounts have to lose to the same player before they are flagged.
+const ANOMALY_NEW_ACCOUNT_VICTIMS: i64 = 5;
+
+/// How a new character is seeded from the average of the player's established characters.
+pub struct SeedingPolicy {
+    /// Share of the average's distance from the default rating that the new character starts with.
+    pub weight: f64,
+    /// Deviation the new character starts with, wider than the ratings it's seeded from.
+    pub deviation: f64,
+}
+
+impl SeedingPolicy {
+    pub fn seed(&self, average: f64) -> Rating {
+        let default = Rating::default();
+        Rating::new(
+            default.value + self.weight * (average - default.value),
+            self.deviation,
+        )
+    }
+}
+
+/// Settings of the rating update, read from `rating_config` at the start of every batch so they can
+/// be changed without a rebuild.
+pub struct RatingConfig {
+    /// `None` starts every new character at the default rating. Check changes with
+    /// backtest_seeding, set with set_seeding.
+    pub seeding: Option<SeedingPolicy>,
+    /// Whether new players start at the rating prior of their floor, see `calc_floor_priors`. Check
+    /// changes with backtest_floor_priors, set with set_floor_priors.
+    pub floor_priors: bool,
+    /// What happens to the ratings of the characters `detect_smurfs` flags, set with
+    /// set_smurf_policy.
+    pub smurf_policy: SmurfPolicy,
+}
+
+pub fn load_rating_config(conn: &Connection) -> rusqlite::Result<RatingConfig> {
+    conn.query_row(
+        "SELECT seeding_weight, seeding_deviation, floor_priors, smurf_policy FROM rating_config",
+        [],
+        |r| {
+            let weight: Option<f64> = r.get(0)?;
+            let deviation: f64 = r.get(1)?;
+            let smurf_policy: String = r.get(3)?;
+            Ok(RatingConfig {
+                seeding: weight.map(|weight| SeedingPolicy { weight, deviation }),
+                floor_priors: r.get(2)?,
+                smurf_policy: SmurfPolicy::from_name(&smurf_policy).ok_or_else(|| {
+                    rusqlite::Error::InvalidColumnType(3, smurf_policy, Type::Text)
+                })?,
+            })
+        },
+    )
+}
+
+/// How many of a new character's games the backtests score.
+const BACKTEST_GAMES: i64 = 30;
+/// Floors with fewer established players than this have no prior.
+const FLOOR_PRIOR_MIN_PLAYERS: i64 = 100;
+
+/// How far back `detect_smurfs` looks for new characters and the games to compare alts by.
+const SMURF_WINDOW: i64 = 60 * 60 * 24 * 14;
+/// A new character is judged on its first SMURF_GAMES games, once it has at least SMURF_MIN_GAMES.
+const SMURF_GAMES: i64 = 30;
+const SMURF_MIN_GAMES: i64 = 10;
+/// Standard deviations the wins have to be above the expected wins to flag the character.
+const SMURF_Z: f64 = 3.0;
+/// Weights of name similarity, shared opponents and active hours in the alt link score.
+const ALT_NAME_WEIGHT: f64 = 0.4;
+const ALT_OPPONENT_WEIGHT: f64 = 0.3;
+const ALT_HOUR_WEIGHT: f64 = 0.3;
+/// Lowest score that links two players, and the most links kept per player.
+const ALT_LINK_SCORE: f64 = 0.5;
+const ALT_MAX_LINKS: usize = 5;
+/// Least name similarity or shared opponents that makes another player worth comparing.
+const ALT_NAME_SIMILARITY: f64 = 0.5;
+const ALT_MIN_SHARED_OPPONENTS: i64 = 3;
+
+/// What happens to the ratings of flagged smurfs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SmurfPolicy {
+    /// They are only reported to moderators.
+    ReportOnly,
+    /// Their deviation stays at least SMURF_MIN_DEVIATION for their first SMURF_FAST_GAMES games,
+    /// so they climb to their real rating faster.
+    FastConvergence,
+    /// Their new characters start at the best established rating of their likely alts.
+    AltStartingRating,
+}
+
+impl SmurfPolicy {
+    const ALL: [SmurfPolicy; 3] = [
+        SmurfPolicy::ReportOnly,
+        SmurfPolicy::FastConvergence,
+        SmurfPolicy::AltStartingRating,
+    ];
+
+    /// The name the policy is stored and set by.
+    pub fn name(self) -> &'static str {
+        match self {
+            SmurfPolicy::ReportOnly => "report_only",
+            SmurfPolicy::FastConvergence => "fast_convergence",
+            SmurfPolicy::AltStartingRating => "alt_starting_rating",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+const SMURF_MIN_DEVIATION: f64 = 150.0;
+const SMURF_FAST_GAMES: i64 = 50;
+/// A player idle for longer than this, in seconds, starts a new session with their next game.
+pub const SESSION_GAP: i64 = 30 * 60;
+/// Games of a session after this many count as late, to compare how players do once they've been
+/// playing for a while.
+pub const SESSION_LATE_GAMES: i64 = 10;
+/// Standard deviation in rating points of the prior on each matchup offset.
+const MATCHUP_OFFSET_PRIOR: f64 = 100.0;
+
+/// Weights of the components of the tier list score, see `calc_tier_list`.
+const TIER_MATCHUP_WEIGHT: f64 = 0.5;
+const TIER_FRAUD_WEIGHT: f64 = 0.25;
+const TIER_REPRESENTATION_WEIGHT: f64 = 0.25;
+/// Rating points per doubling of a character's over-representation at high level.
+const TIER_REPRESENTATION_SCALE: f64 = 50.0;
+/// Tiers and the minimum z-score of the tier list score to reach them.
+pub const TIERS: &[(&str, f64)] = &[
+    ("S", 1.0),
+    ("A", 0.33),
+    ("B", -0.33),
+    ("C", -1.0),
+    ("D", f64::NEG_INFINITY),
+];
 
 pub const RATING_PERIOD: i64 = 60 * 60;
 pub const RANKING_PERIOD: i64 = 1 * 60 * 60;
 pub const STATISTICS_PERIOD: i64 = 6 * 60 * 60;
 
+pub const RANKING_SNAPSHOT_SIZE: i64 = 100;
+
 lazy_static! {
     pub static ref RUNTIME_DATA: Mutex<RuntimeData> = Mutex::new(RuntimeData {});
 }
@@ -32,13 +188,165 @@ type Result<T> = std::result::Result<T, anyhow::Error>;
 
 pub fn init_database() -> Result<()> {
     info!("Intializing database");
+    migrate_database()
+}
+
+/// Brings a database created by an earlier version up to date, this has to happen before anything
+/// else touches it.
+pub fn migrate_database() -> Result<()> {
+    let mut conn = Connection::open(DB_NAME)?;
+    migrate(&mut conn)?;
+    Ok(())
+}
+
+/// Changes the tables that already exist to their current shape, then creates the missing ones
+/// from init.sql. Every step checks whether it is still needed, so this is safe to run every start.
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let then = Utc::now();
+    let tx = conn.transaction()?;
+
+    // Tables whose key changed can't be altered in place, the old ones are moved aside for
+    // init.sql to create the current ones and their rows are copied over after
+    let old_popularity = has_table(&tx, "character_popularity_global")?
+        && !has_column(&tx, "character_popularity_global", "patch_id")?;
+    if old_popularity {
+        tx.execute_batch(
+            "ALTER TABLE character_popularity_global RENAME TO character_popularity_global_old;
+            ALTER TABLE character_popularity_rating RENAME TO character_popularity_rating_old;",
+        )?;
+    }
+
+    let old_fraud = has_table(&tx, "fraud_index_higher_rated")?;
+    if old_fraud {
+        tx.execute_batch("ALTER TABLE fraud_index RENAME TO fraud_index_old;")?;
+    }
+
+    if has_table(&tx, "player_names")? && !has_column(&tx, "player_names", "first_seen")? {
+        // Names are dated by the games played under them
+        tx.execute_batch(
+            "ALTER TABLE player_names ADD COLUMN first_seen INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE player_names ADD COLUMN last_seen INTEGER NOT NULL DEFAULT 0;
+            CREATE TEMP TABLE name_times AS
+                SELECT id, name, MIN(timestamp) AS first_seen, MAX(timestamp) AS last_seen FROM (
+                    SELECT id_a AS id, name_a AS name, timestamp FROM games
+                    UNION ALL
+                    SELECT id_b AS id, name_b AS name, timestamp FROM games
+                )
+                GROUP BY id, name;
+            UPDATE player_names
+                SET first_seen = name_times.first_seen, last_seen = name_times.last_seen
+                FROM name_times
+                WHERE name_times.id = player_names.id AND name_times.name = player_names.name;
+            DROP TABLE name_times;",
+        )?;
+    }
+
+    let new_name_search = !has_table(&tx, "player_name_search")?;
+    let old_hidden = has_column(&tx, "hidden_status", "code")?;
+    if old_hidden {
+        tx.execute_batch("ALTER TABLE hidden_status RENAME TO hidden_status_old;")?;
+    }
+
+    tx.execute_batch(include_str!("../init.sql"))?;
+
+    if new_name_search {
+        fill_name_search(&tx)?;
+    }
+    if old_hidden {
+        // Rows without a status were hides waiting for their code, those players have to start
+        // over. There's no record of when the others were hidden, so they count from now.
+        tx.execute(
+            "INSERT INTO hidden_status(id, hidden_status, notes, timestamp)
+            SELECT id, hidden_status, notes, ? FROM hidden_status_old
+            WHERE hidden_status IS NOT NULL",
+            params![then.timestamp()],
+        )?;
+        tx.execute("DROP TABLE hidden_status_old", [])?;
+    }
+
+    if old_fraud {
+        // The three fraud tables were the latest index above each of the rating thresholds
+        let timestamp = then.timestamp();
+        for (table, threshold) in [
+            ("fraud_index_old", FRAUD_THRESHOLDS[0]),
+            ("fraud_index_higher_rated", FRAUD_THRESHOLDS[1]),
+            ("fraud_index_highest_rated", FRAUD_THRESHOLDS[2]),
+        ] {
+            tx.execute(
+                &format!(
+                    "INSERT INTO fraud_index(timestamp, rating_threshold, char_id, player_count, avg_delta)
+                    SELECT ?, ?, char_id, player_count, avg_delta FROM {table}"
+                ),
+                params![timestamp, threshold],
+            )?;
+            tx.execute(&format!("DROP TABLE {table}"), [])?;
+        }
+    }
+
+    if old_popularity {
+        // The popularity used to only cover the rolling window, which is patch 0 now
+        tx.execute_batch(
+            "INSERT INTO character_popularity_global(patch_id, char_id, popularity, sample_size)
+            SELECT 0, char_id, popularity, 0 FROM character_popularity_global_old;
+            INSERT INTO character_popularity_rating(
+                patch_id, char_id, rating_bracket, popularity, sample_size
+            )
+            SELECT 0, char_id, rating_bracket, popularity, 0 FROM character_popularity_rating_old;
+            DROP TABLE character_popularity_global_old;
+            DROP TABLE character_popularity_rating_old;",
+        )?;
+    }
 
+    tx.commit()?;
+    info!(
+        "Migrated database - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+/// Loads the roster, this has to happen before anything looks up a character.
+pub fn load_characters() -> Result<()> {
     let conn = Connection::open(DB_NAME)?;
-    conn.execute_batch(include_str!("../init.sql"))?;
+    website::load_characters(&conn)?;
+    Ok(())
+}
+
+/// Adds a character to the roster, or renames it. Takes effect on the next start.
+pub fn add_character(char_id: Option<&str>, short: Option<&str>, name: Option<&str>) -> Result<()> {
+    let char_id: i64 = char_id.context("Missing character id")?.parse()?;
+    let short = short.context("Missing short name")?;
+    let name = name.context("Missing name")?;
 
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute(
+        "REPLACE INTO characters(char_id, short, name) VALUES(?, ?, ?)",
+        params![char_id, short, name],
+    )?;
+    conn.execute(
+        "DELETE FROM unknown_characters WHERE char_id = ?",
+        params![char_id],
+    )?;
+    info!("Added character {} ({}) as id {}", name, short, char_id);
     Ok(())
 }
 
+fn has_table(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+        params![table],
+        |r| r.get(0),
+    )
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?) WHERE name = ?)",
+        params![table, column],
+        |r| r.get(0),
+    )
+}
+
 pub fn reset_database() -> Result<()> {
     info!("Resetting database");
     let conn = Connection::open(DB_NAME)?;
@@ -66,8 +374,22 @@ pub fn reset_names() -> Result<()> {
     };
 
     for g in games {
-        update_player(&tx, g.id_a, &g.name_a, g.game_floor, g.platform_a);
-        update_player(&tx, g.id_b, &g.name_b, g.game_floor, g.platform_b);
+        update_player(
+            &tx,
+            g.id_a,
+            &g.name_a,
+            g.game_floor,
+            g.platform_a,
+            g.timestamp,
+        );
+        update_player(
+            &tx,
+            g.id_b,
+            &g.name_b,
+            g.game_floor,
+            g.platform_b,
+            g.timestamp,
+        );
     }
 
     tx.commit()?;
@@ -128,15 +450,36 @@ pub async fn update_statistics(
         info!("New statistics period, updating statistics.");
         *last_statistics_update = *last_ranking_update;
         update_player_distribution(conn);
+        if let Err(e) = calc_floor_priors(conn) {
+            error!("calc_floor_priors failed: {}", e);
+        }
         //if let Err(e) = calc_versus_matchups(&mut conn) {
         //    error!("calc_versus_matchups failed: {}", e);
         //}
-        if let Err(e) = calc_fraud_index(conn) {
+        if let Err(e) = calc_fraud_index(conn, *last_ranking_update) {
             error!("calc_fraud_index failed: {}", e);
         }
         if let Err(e) = calc_character_popularity(conn, *last_ranking_update) {
             error!("calc_character_popularity failed: {}", e);
         }
+        if let Err(e) = update_seasons(conn, now) {
+            error!("update_seasons failed: {}", e);
+        }
+        if let Err(e) = calc_bracket_matchups(conn, *last_ranking_update) {
+            error!("calc_bracket_matchups failed: {}", e);
+        }
+        if let Err(e) = estimate_matchups(conn, *last_ranking_update) {
+            error!("estimate_matchups failed: {}", e);
+        }
+        if let Err(e) = calc_tier_list(conn, *last_ranking_update) {
+            error!("calc_tier_list failed: {}", e);
+        }
+        if let Err(e) = detect_anomalies(conn, *last_ranking_update) {
+            error!("detect_anomalies failed: {}", e);
+        }
+        if let Err(e) = detect_smurfs(conn, *last_ranking_update) {
+            error!("detect_smurfs failed: {}", e);
+        }
     }
 
     if let Err(e) = update_decay(conn, Utc::now().timestamp()) {
@@ -148,6 +491,12 @@ pub async fn update_statistics(
     if let Err(e) = update_rankings(conn) {
         error!("update_rankings failed: {}", e);
     }
+    if let Err(e) = snapshot_rankings(conn, now) {
+        error!("snapshot_rankings failed: {}", e);
+    }
+    if let Err(e) = update_rating_percentiles(conn) {
+        error!("update_rating_percentiles failed: {}", e);
+    }
 
     while now - *last_ranking_update > RANKING_PERIOD {
         *last_ranking_update += RANKING_PERIOD;
@@ -181,7 +530,7 @@ pub async fn update_once() {
     //if let Err(e) = calc_versus_matchups(&mut conn) {
     //    error!("calc_versus_matchups failed: {}", e);
     //}
-    if let Err(e) = calc_fraud_index(&mut conn) {
+    if let Err(e) = calc_fraud_index(&mut conn, Utc::now().timestamp()) {
         error!("calc_fraud_index failed: {}", e);
     }
 
@@ -218,7 +567,7 @@ pub fn print_rankings() {
 
     while let Some(row) = rows.next().unwrap() {
         let name: String = row.get(0).unwrap();
-        let char_name = website::CHAR_NAMES[row.get::<_, usize>(1).unwrap()].1;
+        let char_name = website::character(row.get::<_, usize>(1).unwrap()).1;
         let value: f64 = row.get(2).unwrap();
         let deviation: f64 = row.get(3).unwrap();
         let games: i64 = row.get(4).unwrap();
@@ -232,39 +581,138 @@ pub fn print_rankings() {
     }
 }
 
-pub fn mark_vip(vip_id: &str, notes: &str) {
-    let vip_id = i64::from_str_radix(vip_id, 16).unwrap();
+/// Returns the table of a moderation status, which is also the name of its status column.
+pub fn moderation_table(kind: &str) -> Option<&'static str> {
+    match kind {
+        "cheater" => Some("cheater_status"),
+        "vip" => Some("vip_status"),
+        "hidden" => Some("hidden_status"),
+        _ => None,
+    }
+}
+
+/// Creates a moderator and prints the token they authenticate to the admin API with.
+pub fn add_moderator(name: Option<&str>) -> Result<()> {
+    let name = name.context("Missing moderator name")?;
+    let token = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
 
-    let conn = Connection::open(DB_NAME).unwrap();
+    let conn = Connection::open(DB_NAME)?;
     conn.execute(
-        "INSERT INTO vip_status
-            VALUES(?, 'VIP', ?)",
-        params![vip_id, notes],
-    )
-    .unwrap();
+        "REPLACE INTO moderators(name, token, created) VALUES(?, ?, ?)",
+        params![name, &token, Utc::now().timestamp()],
+    )?;
+    println!("Token for {}: {}", name, token);
+    Ok(())
 }
 
-pub fn mark_hidden(hidden_id: &str, notes: &str) {
-    let hidden_id = i64::from_str_radix(hidden_id, 16).unwrap();
+pub fn remove_moderator(name: Option<&str>) -> Result<()> {
+    let name = name.context("Missing moderator name")?;
+
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute("DELETE FROM moderators WHERE name = ?", params![name])?;
+    Ok(())
+}
+
+pub fn mark_vip(vip_id: Option<&str>, notes: Option<&str>) -> Result<()> {
+    let vip_id = i64::from_str_radix(vip_id.context("Missing player id")?, 16)?;
+
+    let mut conn = Connection::open(DB_NAME)?;
+    let tx = conn.transaction()?;
+    set_status(&tx, "cli", "vip", vip_id, "VIP", notes.unwrap_or(""))?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn mark_hidden(hidden_id: Option<&str>, notes: Option<&str>) -> Result<()> {
+    let hidden_id = i64::from_str_radix(hidden_id.context("Missing player id")?, 16)?;
+
+    let mut conn = Connection::open(DB_NAME)?;
+    let tx = conn.transaction()?;
+    set_status(
+        &tx,
+        "cli",
+        "hidden",
+        hidden_id,
+        "hidden",
+        notes.unwrap_or(""),
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Hides a player with the given status, or unhides them with `None`. Every change is logged in
+/// hidden_status_history.
+pub fn set_hidden(
+    conn: &Connection,
+    id: i64,
+    hidden_status: Option<&str>,
+    notes: &str,
+) -> rusqlite::Result<()> {
+    let timestamp = Utc::now().timestamp();
+
+    if let Some(hidden_status) = hidden_status {
+        conn.execute(
+            "INSERT OR REPLACE INTO hidden_status(id, hidden_status, notes, timestamp)
+            VALUES(?, ?, ?, ?)",
+            params![id, hidden_status, notes, timestamp],
+        )?;
+    } else {
+        conn.execute("DELETE FROM hidden_status WHERE id = ?", params![id])?;
+    }
 
-    let conn = Connection::open(DB_NAME).unwrap();
     conn.execute(
-        "INSERT INTO hidden_status
-            VALUES(?, 'hidden', ?)",
-        params![hidden_id, notes],
-    )
-    .unwrap();
+        "INSERT INTO hidden_status_history(id, timestamp, action, notes)
+        VALUES(?, ?, ?, ?)",
+        params![
+            id,
+            timestamp,
+            if hidden_status.is_some() {
+                "hide"
+            } else {
+                "unhide"
+            },
+            notes
+        ],
+    )?;
+
+    Ok(())
 }
 
-pub async fn mark_cheater(
+/// Marks a cheater. Without a cheater type this only prints how much each opponent would get back.
+pub fn mark_cheater(
     cheater_id: Option<&str>,
     cheater_type: Option<&str>,
     notes: Option<&str>,
-) {
-    let cheater_id = i64::from_str_radix(cheater_id.unwrap(), 16).unwrap();
+) -> Result<()> {
+    let cheater_id = i64::from_str_radix(cheater_id.context("Missing player id")?, 16)?;
 
-    let conn = Connection::open(DB_NAME).unwrap();
+    let mut conn = Connection::open(DB_NAME)?;
+
+    for (key, value) in cheater_rating_offsets(&conn, cheater_id)? {
+        println!("{:?}: {:.1}", key, value);
+    }
+
+    if let Some(cheater_type) = cheater_type {
+        let tx = conn.transaction()?;
+        set_status(
+            &tx,
+            "cli",
+            "cheater",
+            cheater_id,
+            cheater_type,
+            notes.unwrap_or(""),
+        )?;
+        tx.commit()?;
+    }
 
+    Ok(())
+}
+
+/// The rating each (player, character) lost or gained in games against the cheater.
+fn cheater_rating_offsets(
+    conn: &Connection,
+    cheater_id: i64,
+) -> rusqlite::Result<FxHashMap<(i64, i64), f64>> {
     struct Game {
         id_a: i64,
         char_a: i64,
@@ -282,22 +730,23 @@ pub async fn mark_cheater(
             "SELECT id_a, char_a, value_a, deviation_a, id_b, char_b, value_b, deviation_b, winner
             FROM game_ratings
             NATURAL JOIN games
-            WHERE id_a = ? OR id_b = ?").unwrap();
+            WHERE id_a = ? OR id_b = ?",
+        )?;
 
         let mut games = Vec::new();
-        let mut rows = stmt.query(params![cheater_id, cheater_id]).unwrap();
+        let mut rows = stmt.query(params![cheater_id, cheater_id])?;
 
-        while let Some(row) = rows.next().unwrap() {
+        while let Some(row) = rows.next()? {
             games.push(Game {
-                id_a: row.get(0).unwrap(),
-                char_a: row.get(1).unwrap(),
-                value_a: row.get(2).unwrap(),
-                deviation_a: row.get(3).unwrap(),
-                id_b: row.get(4).unwrap(),
-                char_b: row.get(5).unwrap(),
-                value_b: row.get(6).unwrap(),
-                deviation_b: row.get(7).unwrap(),
-                winner: row.get(8).unwrap(),
+                id_a: row.get(0)?,
+                char_a: row.get(1)?,
+                value_a: row.get(2)?,
+                deviation_a: row.get(3)?,
+                id_b: row.get(4)?,
+                char_b: row.get(5)?,
+                value_b: row.get(6)?,
+                deviation_b: row.get(7)?,
+                winner: row.get(8)?,
             });
         }
 
@@ -324,34 +773,180 @@ pub async fn mark_cheater(
         }
     }
 
-    for (key, value) in &player_offsets {
-        println!("{:?}: {:.1}", key, value);
-    }
+    Ok(player_offsets)
+}
 
-    if cheater_type.is_some() {
-        for ((id, char_id), offset) in player_offsets {
-            conn.execute(
-                "UPDATE player_ratings 
-            SET value = value + ?
-            WHERE id= ? AND char_id = ?",
-                params![offset, id, char_id],
-            )
-            .unwrap();
+/// Sets a cheater, VIP or hidden status, replacing the current one. Opponents of a newly marked
+/// cheater get back the rating they lost or gained against them. The caller commits the
+/// transaction.
+pub fn set_status(
+    tx: &Transaction,
+    moderator: &str,
+    kind: &str,
+    id: i64,
+    status: &str,
+    notes: &str,
+) -> Result<()> {
+    let table = moderation_table(kind).context("Unknown status")?;
+
+    match kind {
+        "hidden" => set_hidden(tx, id, Some(status), notes)?,
+        _ => {
+            if kind == "cheater" && !has_status(tx, table, id)? {
+                for ((id, char_id), offset) in cheater_rating_offsets(tx, id)? {
+                    tx.execute(
+                        "UPDATE player_ratings
+                        SET value = value + ?
+                        WHERE id= ? AND char_id = ?",
+                        params![offset, id, char_id],
+                    )?;
+                }
+            }
+
+            tx.execute(
+                &format!("INSERT OR REPLACE INTO {table}(id, {table}, notes) VALUES(?, ?, ?)"),
+                params![id, status, notes],
+            )?;
         }
+    }
 
-        conn.execute(
-            "INSERT INTO cheater_status
-            VALUES(?, ?, ?)",
-            params![cheater_id, cheater_type, notes.unwrap_or("")],
-        )
-        .unwrap();
+    log_moderation(tx, moderator, "mark", kind, id, Some(status), notes)?;
+
+    info!("{} marked {:X} as {} ({})", moderator, id, kind, status);
+    Ok(())
+}
+
+/// Removes a cheater, VIP or hidden status. Ratings given back when a cheater was marked stay as
+/// they are. Returns false if the player didn't have the status.
+pub fn clear_status(
+    conn: &mut Connection,
+    moderator: &str,
+    kind: &str,
+    id: i64,
+    notes: &str,
+) -> Result<bool> {
+    let table = moderation_table(kind).context("Unknown status")?;
+    let tx = conn.transaction()?;
+
+    if !has_status(&tx, table, id)? {
+        return Ok(false);
+    }
+
+    if kind == "hidden" {
+        set_hidden(&tx, id, None, notes)?;
+    } else {
+        tx.execute(&format!("DELETE FROM {table} WHERE id = ?"), params![id])?;
+    }
+
+    log_moderation(&tx, moderator, "unmark", kind, id, None, notes)?;
+    tx.commit()?;
+
+    info!("{} unmarked {:X} as {}", moderator, id, kind);
+    Ok(true)
+}
+
+/// Replaces the notes of a cheater, VIP or hidden status. Returns false if the player didn't have
+/// the status.
+pub fn set_status_notes(
+    conn: &mut Connection,
+    moderator: &str,
+    kind: &str,
+    id: i64,
+    notes: &str,
+) -> Result<bool> {
+    let table = moderation_table(kind).context("Unknown status")?;
+    let tx = conn.transaction()?;
+
+    let changed = tx.execute(
+        &format!("UPDATE {table} SET notes = ? WHERE id = ?"),
+        params![notes, id],
+    )?;
+    if changed == 0 {
+        return Ok(false);
+    }
+
+    log_moderation(&tx, moderator, "notes", kind, id, None, notes)?;
+    tx.commit()?;
+
+    Ok(true)
+}
+
+/// Closes the open cheat reports against a player. With a cheater status they are confirmed and the
+/// player is marked, otherwise they are dismissed, both in the same transaction. Returns how many
+/// reports were closed.
+pub fn resolve_reports(
+    conn: &mut Connection,
+    moderator: &str,
+    id: i64,
+    cheater_status: Option<&str>,
+    notes: &str,
+) -> Result<usize> {
+    let tx = conn.transaction()?;
+
+    if let Some(cheater_status) = cheater_status {
+        set_status(&tx, moderator, "cheater", id, cheater_status, notes)?;
     }
+
+    let resolved = tx.execute(
+        "UPDATE cheat_reports
+        SET status = ?, resolved_by = ?, resolved_at = ?
+        WHERE id = ? AND status = 'open'",
+        params![
+            if cheater_status.is_some() {
+                "confirmed"
+            } else {
+                "dismissed"
+            },
+            moderator,
+            Utc::now().timestamp(),
+            id
+        ],
+    )?;
+    if cheater_status.is_none() {
+        log_moderation(&tx, moderator, "dismiss", "cheater", id, None, notes)?;
+    }
+    tx.commit()?;
+
+    Ok(resolved)
+}
+
+fn has_status(conn: &Connection, table: &str, id: i64) -> rusqlite::Result<bool> {
+    conn.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM {table} WHERE id = ?)"),
+        params![id],
+        |r| r.get(0),
+    )
+}
+
+fn log_moderation(
+    conn: &Connection,
+    moderator: &str,
+    action: &str,
+    kind: &str,
+    id: i64,
+    status: Option<&str>,
+    notes: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO moderation_log(timestamp, moderator, action, kind, id, status, notes)
+        VALUES(?, ?, ?, ?, ?, ?, ?)",
+        params![
+            Utc::now().timestamp(),
+            moderator,
+            action,
+            kind,
+            id,
+            status,
+            notes
+        ],
+    )?;
+    Ok(())
 }
 
 pub async fn update_fraud_once() {
     let mut conn = Connection::open(DB_NAME).unwrap();
 
-    if let Err(e) = calc_fraud_index(&mut conn) {
+    if let Err(e) = calc_fraud_index(&mut conn, Utc::now().timestamp()) {
         error!("calc_fraud_index failed: {}", e);
     }
 }
@@ -362,14 +957,14 @@ pub async fn update_decay_once() {
     update_decay(&mut conn, Utc::now().timestamp()).unwrap();
 }
 
-pub fn get_average_rating(conn: &Transaction, id: i64) -> f64 {
+/// The average rating of the player's characters with a deviation below `max_deviation`.
+pub fn get_average_rating(conn: &Transaction, id: i64, max_deviation: f64) -> Option<f64> {
     conn.query_row(
-        "select avg(value) from player_ratings where id = ?",
-        params![id],
+        "select avg(value) from player_ratings where id = ? and deviation < ?",
+        params![id, max_deviation],
         |r| r.get::<_, Option<f64>>(0),
     )
     .unwrap()
-    .unwrap_or_default()
 }
 
 pub async fn pull() {
@@ -393,6 +988,7 @@ async fn grab_games(conn: &mut Connection, _pages: usize) -> Result<()> {
 
     let old_count: i64 = conn.query_row("SELECT COUNT(*) FROM games", [], |r| r.get(0))?;
 
+    let roster_size = website::char_names().len();
     let tx = conn.transaction()?;
 
     let mut new_games = Vec::new();
@@ -403,6 +999,13 @@ async fn grab_games(conn: &mut Connection, _pages: usize) -> Result<()> {
     }
     tx.commit()?;
 
+    if new_games
+        .iter()
+        .any(|g| g.char_a as usize >= roster_size || g.char_b as usize >= roster_size)
+    {
+        website::load_characters(conn)?;
+    }
+
     let count: i64 = conn.query_row("SELECT COUNT(*) FROM games", [], |r| r.get(0))?;
 
     let elapsed = (Utc::now() - then).num_milliseconds();
@@ -446,6 +1049,17 @@ fn add_game(conn: &Transaction, game: responses::Replay) -> Option<Game> {
     } = game;
     let timestamp = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S").unwrap();
 
+    for char_id in [player1_character, player2_character] {
+        if website::char_names().get(char_id as usize).is_none() {
+            warn!("Unknown character id {}", char_id);
+            conn.execute(
+                "INSERT OR IGNORE INTO unknown_characters(char_id, first_seen) VALUES(?, ?)",
+                params![char_id, timestamp.timestamp()],
+            )
+            .unwrap();
+        }
+    }
+
     let count = conn
         .execute(
             "INSERT OR IGNORE INTO games (
@@ -501,7 +1115,14 @@ fn add_game(conn: &Transaction, game: responses::Replay) -> Option<Game> {
     //sort the list by date
 }
 
-fn update_player(conn: &Transaction, id: i64, name: &str, floor: i64, platform: i64) {
+fn update_player(
+    conn: &Transaction,
+    id: i64,
+    name: &str,
+    floor: i64,
+    platform: i64,
+    timestamp: i64,
+) {
     if let Err(e) = conn.execute(
         "REPLACE INTO players(id, name, floor, platform) VALUES(?, ?, ?, ?)",
         params![id, name, floor, platform],
@@ -509,24 +1130,229 @@ fn update_player(conn: &Transaction, id: i64, name: &str, floor: i64, platform:
         warn!("{}", e);
     }
 
-    if let Err(e) = conn.execute(
-        "INSERT OR IGNORE INTO player_names(id, name) VALUES(?, ?)",
-        params![id, name],
+    match conn.execute(
+        "INSERT OR IGNORE INTO player_names(id, name, first_seen, last_seen) VALUES(?, ?, ?, ?)",
+        params![id, name, timestamp, timestamp],
     ) {
-        warn!("{}", e);
+        Ok(1) => {
+            if let Err(e) = conn.execute(
+                "INSERT INTO player_name_search(normalized_name, id, name) VALUES(?, ?, ?)",
+                params![normalize_name(name), id, name],
+            ) {
+                warn!("{}", e);
+            }
+        }
+        Ok(_) => {
+            // Games aren't always processed in order, so the range only ever widens
+            if let Err(e) = conn.execute(
+                "UPDATE player_names SET
+                    first_seen = MIN(first_seen, ?),
+                    last_seen = MAX(last_seen, ?)
+                WHERE id = ? AND name = ?",
+                params![timestamp, timestamp, id, name],
+            ) {
+                warn!("{}", e);
+            }
+        }
+        Err(e) => warn!("{}", e),
     }
 }
 
-fn update_player_distribution(conn: &mut Connection) {
-    let then = Utc::now();
-    let tx = conn.transaction().unwrap();
+/// The form of a name that's indexed for search: full-width characters, styled letters and
+/// circled letters become plain ones, everything is lowercased and whatever isn't a letter or a
+/// digit is dropped. Names that are nothing but symbols are kept as they are, lowercased.
+pub fn normalize_name(name: &str) -> String {
+    let normalized: String = name
+        .chars()
+        .map(|c| match c as u32 {
+            // Full-width ASCII
+            0xFF01..=0xFF5E => char::from_u32(c as u32 - 0xFEE0).unwrap(),
+            // Mathematical bold, italic, script, fraktur, double-struck, sans-serif and monospace
+            cp @ 0x1D400..=0x1D6A3 => {
+                let i = ((cp - 0x1D400) % 52) as u8;
+                if i < 26 {
+                    (b'A' + i) as char
+                } else {
+                    (b'a' + i - 26) as char
+                }
+            }
+            cp @ 0x1D7CE..=0x1D7FF => (b'0' + ((cp - 0x1D7CE) % 10) as u8) as char,
+            // Circled letters
+            cp @ 0x24B6..=0x24CF => (b'A' + (cp - 0x24B6) as u8) as char,
+            cp @ 0x24D0..=0x24E9 => (b'a' + (cp - 0x24D0) as u8) as char,
+            _ => c,
+        })
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect();
 
-    let two_weeks_ago = then.timestamp() - 60 * 60 * 24 * 14;
+    if normalized.is_empty() {
+        name.to_lowercase()
+    } else {
+        normalized
+    }
+}
 
-    tx.execute("DELETE FROM player_floor_distribution", [])
-        .unwrap();
-    tx.execute("DELETE FROM player_rating_distribution", [])
-        .unwrap();
+/// The distinct sequences of three characters in `s`, sorted.
+pub fn trigrams(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut res: Vec<String> = chars.windows(3).map(|w| w.iter().collect()).collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// Dice coefficient of two sets of trigrams, 1.0 when they're the same.
+pub fn trigram_similarity(a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+    let common = a.iter().filter(|t| b.contains(t)).count();
+    2.0 * common as f64 / (a.len() + b.len()) as f64
+}
+
+/// Refills the search index from `player_names`.
+pub fn rebuild_name_search() -> Result<()> {
+    let then = Utc::now();
+    let mut conn = Connection::open(DB_NAME)?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM player_name_search", [])?;
+    fill_name_search(&tx)?;
+    tx.commit()?;
+    info!(
+        "Rebuilt the name search index - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+/// Refills the session tables by replaying every rated game. Rating changes are recomputed from
+/// the ratings stored with each game, so they can differ a little from the live ones for players
+/// under the smurf deviation floor.
+pub fn rebuild_sessions() -> Result<()> {
+    let then = Utc::now();
+    let mut conn = Connection::open(DB_NAME)?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM player_sessions", [])?;
+    tx.execute("DELETE FROM player_session_opponents", [])?;
+
+    let mut sessions = SessionBatch::default();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT timestamp, id_a, value_a, deviation_a, id_b, value_b, deviation_b, winner, valid
+            FROM game_ratings
+            ORDER BY timestamp",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut counter = 0;
+        while let Some(row) = rows.next()? {
+            let timestamp: i64 = row.get(0)?;
+            let id_a: i64 = row.get(1)?;
+            let rating_a = Rating::new(row.get(2)?, row.get(3)?);
+            let id_b: i64 = row.get(4)?;
+            let rating_b = Rating::new(row.get(5)?, row.get(6)?);
+            let a_won = row.get::<_, i64>(7)? == 1;
+            let valid: bool = row.get(8)?;
+
+            let result_a = if a_won { 1.0 } else { 0.0 };
+            let (change_a, change_b) = if valid {
+                (
+                    rating_a.rating_change(rating_b, result_a),
+                    rating_b.rating_change(rating_a, 1.0 - result_a),
+                )
+            } else {
+                (0.0, 0.0)
+            };
+            let expected_a = rating_a.expected(rating_b);
+
+            sessions.add_game(&tx, id_a, id_b, timestamp, a_won, expected_a, change_a)?;
+            sessions.add_game(
+                &tx,
+                id_b,
+                id_a,
+                timestamp,
+                !a_won,
+                1.0 - expected_a,
+                change_b,
+            )?;
+
+            counter += 1;
+            if counter % 250_000 == 0 {
+                std::mem::take(&mut sessions).write(&tx)?;
+                info!("On game {}...", counter);
+            }
+        }
+    }
+    sessions.write(&tx)?;
+
+    tx.commit()?;
+    info!(
+        "Rebuilt the sessions - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+/// Refills the activity tables from every rated game.
+pub fn rebuild_activity() -> Result<()> {
+    let then = Utc::now();
+    let mut conn = Connection::open(DB_NAME)?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "DELETE FROM player_daily_activity;
+        DELETE FROM player_hourly_activity;
+        DELETE FROM daily_activity;
+        DELETE FROM hourly_activity;",
+    )?;
+
+    let mut activity = ActivityBatch::default();
+    {
+        let mut stmt = tx.prepare("SELECT timestamp, id_a, id_b FROM game_ratings")?;
+        let mut rows = stmt.query([])?;
+        let mut counter = 0;
+        while let Some(row) = rows.next()? {
+            activity.add_game(row.get(1)?, row.get(2)?, row.get(0)?);
+
+            counter += 1;
+            if counter % 250_000 == 0 {
+                std::mem::take(&mut activity).write(&tx)?;
+                info!("On game {}...", counter);
+            }
+        }
+    }
+    activity.write(&tx)?;
+
+    tx.commit()?;
+    info!(
+        "Rebuilt the activity - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+fn fill_name_search(tx: &Transaction) -> Result<()> {
+    let mut stmt = tx.prepare("SELECT id, name FROM player_names")?;
+    let mut insert =
+        tx.prepare("INSERT INTO player_name_search(normalized_name, id, name) VALUES(?, ?, ?)")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: String = row.get(1)?;
+        insert.execute(params![normalize_name(&name), id, name])?;
+    }
+    Ok(())
+}
+
+fn update_player_distribution(conn: &mut Connection) {
+    let then = Utc::now();
+    let tx = conn.transaction().unwrap();
+
+    let two_weeks_ago = then.timestamp() - 60 * 60 * 24 * 14;
+
+    tx.execute("DELETE FROM player_floor_distribution", [])
+        .unwrap();
+    tx.execute("DELETE FROM player_rating_distribution", [])
+        .unwrap();
 
     for f in (1..=10).chain(std::iter::once(99)) {
         let player_count: i64 = tx
@@ -600,6 +1426,436 @@ fn update_player_distribution(conn: &mut Connection) {
     );
 }
 
+/// Stores the rating found at each tenth of a percent of the established players, overall and
+/// per character, so the percentile of any rating can be interpolated. Cheaters and hidden players
+/// are left out like in the rankings.
+fn update_rating_percentiles(conn: &mut Connection) -> Result<()> {
+    let then = Utc::now();
+    let char_count = website::char_names().len();
+    let tx = conn.transaction()?;
+
+    let mut overall = Vec::new();
+    let mut characters = vec![Vec::new(); char_count];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT char_id, value
+            FROM player_ratings
+                LEFT JOIN cheater_status on player_ratings.id = cheater_status.id
+                LEFT JOIN hidden_status on player_ratings.id = hidden_status.id
+            WHERE deviation < ? AND cheater_status IS NULL AND hidden_status IS NULL",
+        )?;
+        let mut rows = stmt.query(params![LOW_DEVIATION])?;
+        while let Some(row) = rows.next()? {
+            let char_id: usize = row.get(0)?;
+            let value: f64 = row.get(1)?;
+            overall.push(value);
+            if let Some(values) = characters.get_mut(char_id) {
+                values.push(value);
+            }
+        }
+    }
+
+    tx.execute("DELETE FROM rating_percentiles", [])?;
+    tx.execute("DELETE FROM rating_percentile_counts", [])?;
+
+    for (char_id, mut values) in std::iter::once((-1, overall)).chain(
+        characters
+            .into_iter()
+            .enumerate()
+            .map(|(c, v)| (c as i64, v)),
+    ) {
+        if values.is_empty() {
+            continue;
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        tx.execute(
+            "INSERT INTO rating_percentile_counts(char_id, player_count) VALUES(?, ?)",
+            params![char_id, values.len()],
+        )?;
+
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO rating_percentiles(char_id, permille, value) VALUES(?, ?, ?)",
+        )?;
+        for permille in 0..=1000 {
+            let i = permille * (values.len() - 1) / 1000;
+            stmt.execute(params![char_id, permille, values[i]])?;
+        }
+    }
+
+    tx.commit()?;
+
+    info!(
+        "Updated rating percentiles - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
+
+/// A character's first rating, given when it first plays. Under SmurfPolicy::AltStartingRating a
+/// flagged player starts at the best established rating of their likely alts, otherwise the
+/// character is seeded from the player's other characters under the configured seeding policy. The
+/// other characters are rated as they are at this point of the batch: `players` holds the ratings
+/// so far, and `batch_characters` the player's characters that aren't in player_ratings yet. A new
+/// player starts at the prior of the floor they're playing on.
+#[allow(clippy::too_many_arguments)]
+fn new_rated_player(
+    tx: &Transaction,
+    config: &RatingConfig,
+    floor_priors: &FxHashMap<i64, Rating>,
+    players: &FxHashMap<(i64, i64), RatedPlayer>,
+    batch_characters: &[i64],
+    id: i64,
+    char_id: i64,
+    floor: i64,
+    timestamp: i64,
+) -> RatedPlayer {
+    if config.smurf_policy == SmurfPolicy::AltStartingRating {
+        let alt_rating: Option<f64> = tx
+            .query_row(
+                "SELECT MAX(value)
+                FROM alt_links
+                JOIN player_ratings ON player_ratings.id = alt_links.alt_id
+                WHERE alt_links.id = ? AND deviation < ?",
+                params![id, LOW_DEVIATION],
+                |r| r.get(0),
+            )
+            .unwrap();
+        if let Some(rating) = alt_rating {
+            return RatedPlayer::new_from_rating(
+                id,
+                char_id,
+                timestamp,
+                Rating {
+                    value: rating,
+                    ..Rating::default()
+                },
+            );
+        }
+    }
+
+    let mut other_ratings = batch_characters
+        .iter()
+        .map(|other_char| players[&(id, *other_char)].rating)
+        .collect::<Vec<_>>();
+
+    let mut stmt = tx
+        .prepare_cached("SELECT char_id, value, deviation FROM player_ratings WHERE id = ?")
+        .unwrap();
+    let mut rows = stmt.query(params![id]).unwrap();
+    while let Some(row) = rows.next().unwrap() {
+        other_ratings.push(match players.get(&(id, row.get(0).unwrap())) {
+            Some(player) => player.rating,
+            None => Rating::new(row.get(1).unwrap(), row.get(2).unwrap()),
+        });
+    }
+
+    if let Some(policy) = &config.seeding {
+        let established = other_ratings
+            .iter()
+            .filter(|r| r.deviation < LOW_DEVIATION)
+            .map(|r| r.value)
+            .collect::<Vec<_>>();
+
+        if !established.is_empty() {
+            let average = established.iter().sum::<f64>() / established.len() as f64;
+            return RatedPlayer::new_from_rating(id, char_id, timestamp, policy.seed(average));
+        }
+    }
+
+    if other_ratings.is_empty() {
+        if let Some(prior) = floor_priors.get(&floor) {
+            return RatedPlayer::new_from_rating(id, char_id, timestamp, *prior);
+        }
+    }
+
+    RatedPlayer::new(id, char_id, timestamp)
+}
+
+/// The rating after a game. Under SmurfPolicy::FastConvergence flagged characters keep a high
+/// deviation for their first games.
+fn update_rating(
+    policy: SmurfPolicy,
+    smurfs: &FxHashSet<(i64, i64)>,
+    player: &RatedPlayer,
+    other: Rating,
+    result: f64,
+) -> Rating {
+    if policy == SmurfPolicy::FastConvergence
+        && smurfs.contains(&(player.id, player.char_id))
+        && player.win_count + player.loss_count < SMURF_FAST_GAMES
+    {
+        player
+            .rating
+            .update_with_min_dev(other, result, SMURF_MIN_DEVIATION)
+    } else {
+        player.rating.update(other, result)
+    }
+}
+
+/// A player's latest session while a batch of games is rated.
+struct Session {
+    start: i64,
+    end: i64,
+    game_count: i64,
+    wins: i64,
+    expected_wins: f64,
+    rating_change: f64,
+    late_games: i64,
+    late_wins: i64,
+    late_expected_wins: f64,
+    loss_streak: i64,
+    longest_loss_streak: i64,
+    opponents: FxHashSet<i64>,
+}
+
+impl Session {
+    fn new(opponent_id: i64, timestamp: i64, won: bool, expected: f64, rating_change: f64) -> Self {
+        Self {
+            start: timestamp,
+            end: timestamp,
+            game_count: 1,
+            wins: won as i64,
+            expected_wins: expected,
+            rating_change,
+            late_games: 0,
+            late_wins: 0,
+            late_expected_wins: 0.0,
+            loss_streak: !won as i64,
+            longest_loss_streak: !won as i64,
+            opponents: [opponent_id].into_iter().collect(),
+        }
+    }
+
+    fn add_game(
+        &mut self,
+        opponent_id: i64,
+        timestamp: i64,
+        won: bool,
+        expected: f64,
+        rating_change: f64,
+    ) {
+        if self.game_count >= SESSION_LATE_GAMES {
+            self.late_games += 1;
+            self.late_wins += won as i64;
+            self.late_expected_wins += expected;
+        }
+
+        self.end = self.end.max(timestamp);
+        self.game_count += 1;
+        self.wins += won as i64;
+        self.expected_wins += expected;
+        self.rating_change += rating_change;
+        self.loss_streak = if won { 0 } else { self.loss_streak + 1 };
+        self.longest_loss_streak = self.longest_loss_streak.max(self.loss_streak);
+        self.opponents.insert(opponent_id);
+    }
+}
+
+/// The sessions of the players in a batch of games. A player's latest session is read the first
+/// time they show up, and everything is written back at once by `write`.
+#[derive(Default)]
+struct SessionBatch {
+    latest: FxHashMap<i64, Session>,
+    finished: Vec<(i64, Session)>,
+}
+
+impl SessionBatch {
+    /// Adds a game to the player's latest session, or starts a new one if they've been idle for
+    /// longer than the session gap.
+    #[allow(clippy::too_many_arguments)]
+    fn add_game(
+        &mut self,
+        tx: &Transaction,
+        id: i64,
+        opponent_id: i64,
+        timestamp: i64,
+        won: bool,
+        expected: f64,
+        rating_change: f64,
+    ) -> rusqlite::Result<()> {
+        if let Entry::Vacant(entry) = self.latest.entry(id) {
+            if let Some(session) = load_latest_session(tx, id)? {
+                entry.insert(session);
+            }
+        }
+
+        match self.latest.get_mut(&id) {
+            Some(session) if timestamp - session.end <= SESSION_GAP => {
+                session.add_game(opponent_id, timestamp, won, expected, rating_change);
+            }
+            _ => {
+                let session = Session::new(opponent_id, timestamp, won, expected, rating_change);
+                if let Some(previous) = self.latest.insert(id, session) {
+                    self.finished.push((id, previous));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(self, tx: &Transaction) -> rusqlite::Result<()> {
+        let mut insert_session = tx.prepare_cached(
+            "REPLACE INTO player_sessions VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        let mut delete_opponents =
+            tx.prepare_cached("DELETE FROM player_session_opponents WHERE id = ?")?;
+        let mut insert_opponent =
+            tx.prepare_cached("INSERT INTO player_session_opponents VALUES(?, ?)")?;
+
+        let latest = self.latest.iter().map(|(id, s)| (*id, s));
+        for (id, s) in self.finished.iter().map(|(id, s)| (*id, s)).chain(latest) {
+            insert_session.execute(params![
+                id,
+                s.start,
+                s.end,
+                s.game_count,
+                s.wins,
+                s.expected_wins,
+                s.rating_change,
+                s.opponents.len() as i64,
+                s.late_games,
+                s.late_wins,
+                s.late_expected_wins,
+                s.loss_streak,
+                s.longest_loss_streak,
+            ])?;
+        }
+
+        for (id, s) in &self.latest {
+            delete_opponents.execute(params![id])?;
+            for opponent_id in &s.opponents {
+                insert_opponent.execute(params![id, opponent_id])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_latest_session(tx: &Transaction, id: i64) -> rusqlite::Result<Option<Session>> {
+    let session = tx
+        .query_row(
+            "SELECT start, end, game_count, wins, expected_wins, rating_change, late_games,
+                late_wins, late_expected_wins, loss_streak, longest_loss_streak
+            FROM player_sessions
+            WHERE id = ?
+            ORDER BY start DESC LIMIT 1",
+            params![id],
+            |r| {
+                Ok(Session {
+                    start: r.get(0)?,
+                    end: r.get(1)?,
+                    game_count: r.get(2)?,
+                    wins: r.get(3)?,
+                    expected_wins: r.get(4)?,
+                    rating_change: r.get(5)?,
+                    late_games: r.get(6)?,
+                    late_wins: r.get(7)?,
+                    late_expected_wins: r.get(8)?,
+                    loss_streak: r.get(9)?,
+                    longest_loss_streak: r.get(10)?,
+                    opponents: FxHashSet::default(),
+                })
+            },
+        )
+        .optional()?;
+
+    let mut session = match session {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+
+    let mut stmt =
+        tx.prepare_cached("SELECT opponent_id FROM player_session_opponents WHERE id = ?")?;
+    session.opponents = stmt
+        .query_map(params![id], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(Some(session))
+}
+
+/// Game counts of a batch of games, added to the activity tables at once by `write`.
+#[derive(Default)]
+struct ActivityBatch {
+    player_daily: FxHashMap<(i64, i64), i64>,
+    player_hourly: FxHashMap<(i64, u32, u32), i64>,
+    daily: FxHashMap<i64, i64>,
+    hourly: FxHashMap<(u32, u32), i64>,
+}
+
+impl ActivityBatch {
+    /// Counts a game in the daily and hour of the week activity of both players and of everyone.
+    fn add_game(&mut self, id_a: i64, id_b: i64, timestamp: i64) {
+        let time = NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap();
+        let day = time.date().and_hms_opt(0, 0, 0).unwrap().timestamp();
+        let weekday = time.weekday().num_days_from_monday();
+        let hour = time.hour();
+
+        for id in [id_a, id_b] {
+            *self.player_daily.entry((id, day)).or_default() += 1;
+            *self.player_hourly.entry((id, weekday, hour)).or_default() += 1;
+        }
+        *self.daily.entry(day).or_default() += 1;
+        *self.hourly.entry((weekday, hour)).or_default() += 1;
+    }
+
+    fn write(self, tx: &Transaction) -> rusqlite::Result<()> {
+        //A player counts towards a day the first time they have a game on it
+        let mut new_players = FxHashMap::<i64, i64>::default();
+        for ((id, day), game_count) in self.player_daily {
+            *new_players.entry(day).or_default() +=
+                tx.prepare_cached("INSERT OR IGNORE INTO player_daily_activity VALUES(?, ?, 0)")?
+                    .execute(params![id, day])? as i64;
+            tx.prepare_cached(
+                "UPDATE player_daily_activity SET game_count = game_count + ?
+                WHERE id = ? AND day = ?",
+            )?
+            .execute(params![game_count, id, day])?;
+        }
+
+        for ((id, weekday, hour), game_count) in self.player_hourly {
+            tx.prepare_cached("INSERT OR IGNORE INTO player_hourly_activity VALUES(?, ?, ?, 0)")?
+                .execute(params![id, weekday, hour])?;
+            tx.prepare_cached(
+                "UPDATE player_hourly_activity SET game_count = game_count + ?
+                WHERE id = ? AND weekday = ? AND hour = ?",
+            )?
+            .execute(params![game_count, id, weekday, hour])?;
+        }
+
+        for (day, game_count) in self.daily {
+            tx.prepare_cached("INSERT OR IGNORE INTO daily_activity VALUES(?, 0, 0)")?
+                .execute(params![day])?;
+            tx.prepare_cached(
+                "UPDATE daily_activity SET
+                    game_count = game_count + ?,
+                    player_count = player_count + ?
+                WHERE day = ?",
+            )?
+            .execute(params![
+                game_count,
+                new_players.get(&day).copied().unwrap_or(0),
+                day
+            ])?;
+        }
+
+        for ((weekday, hour), game_count) in self.hourly {
+            tx.prepare_cached("INSERT OR IGNORE INTO hourly_activity VALUES(?, ?, 0)")?
+                .execute(params![weekday, hour])?;
+            tx.prepare_cached(
+                "UPDATE hourly_activity SET game_count = game_count + ?
+                WHERE weekday = ? AND hour = ?",
+            )?
+            .execute(params![game_count, weekday, hour])?;
+        }
+
+        Ok(())
+    }
+}
+
 fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
     info!("Updating ratings");
     let then = Utc::now();
@@ -666,36 +1922,24 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
 
     //let popularities =
 
-    //Fetch all the players in the games
+    let config = load_rating_config(&tx).unwrap();
+    let floor_priors = if config.floor_priors {
+        load_floor_priors(&tx).unwrap()
+    } else {
+        FxHashMap::default()
+    };
+
+    //Fetch all the players in the games, new characters are rated when they first play
     let mut players = FxHashMap::default();
+    let mut new_characters = FxHashSet::default();
     for g in &games {
-        if !players.contains_key(&(g.id_a, g.char_a)) {
-            players.insert(
-                (g.id_a, g.char_a),
-                tx.query_row(
-                    "SELECT 
-                        player_ratings.id, player_ratings.char_id, wins, losses, value, deviation, last_decay,
-                        top_rating_value, top_rating_deviation, top_rating_timestamp,
-                        top_defeated_id, top_defeated_char_id, top_defeated_name,
-                        top_defeated_value, top_defeated_deviation, top_defeated_floor,
-                        top_defeated_timestamp, character_rank
-                    FROM player_ratings LEFT JOIN ranking_character 
-                    ON 
-                        player_ratings.id = ranking_character.id AND 
-                        player_ratings.char_id = ranking_character.char_id
-                    WHERE player_ratings.id = ? AND player_ratings.char_id = ?",
-                    params![g.id_a, g.char_a],
-                    |r| Ok(RatedPlayer::from_row(r)),
-                )
-                .optional()
-                .unwrap()
-                .unwrap_or(RatedPlayer::new(g.id_a, g.char_a, g.timestamp)),
-            );
-        }
-        if !players.contains_key(&(g.id_b, g.char_b)) {
-            players.insert(
-                (g.id_b, g.char_b),
-                tx.query_row(
+        for key in [(g.id_a, g.char_a), (g.id_b, g.char_b)] {
+            if players.contains_key(&key) || new_characters.contains(&key) {
+                continue;
+            }
+
+            let player = tx
+                .query_row(
                     "SELECT 
                         player_ratings.id, player_ratings.char_id, wins, losses, value, deviation, last_decay,
                         top_rating_value, top_rating_deviation, top_rating_timestamp,
@@ -707,15 +1951,23 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
                         player_ratings.id = ranking_character.id AND 
                         player_ratings.char_id = ranking_character.char_id
                     WHERE player_ratings.id = ? AND player_ratings.char_id = ?",
-                    params![g.id_b, g.char_b],
+                    params![key.0, key.1],
                     |r| Ok(RatedPlayer::from_row(r)),
                 )
                 .optional()
-                .unwrap()
-                .unwrap_or(RatedPlayer::new(g.id_b, g.char_b, g.timestamp)),
-            );
+                .unwrap();
+            match player {
+                Some(player) => {
+                    players.insert(key, player);
+                }
+                None => {
+                    new_characters.insert(key);
+                }
+            }
         }
     }
+    // Characters rated for the first time in this batch so far, by player
+    let mut batch_characters = FxHashMap::<i64, Vec<i64>>::default();
 
     info!("Fetched {} players", players.len());
 
@@ -758,13 +2010,27 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
         hidden
     };
 
+    let smurfs = {
+        let mut stmt = tx.prepare("SELECT id, char_id FROM smurf_flags").unwrap();
+        let smurfs = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<FxHashSet<(i64, i64)>>();
+        smurfs
+    };
+
     let mut counter = 0;
+    let mut sessions = SessionBatch::default();
+    let mut activity = ActivityBatch::default();
 
     //let mut last_timestamp = 0;
 
     let popularities = {
         let mut stmt = tx
-            .prepare("SELECT char_id, popularity FROM character_popularity_global")
+            .prepare(
+                "SELECT char_id, popularity FROM character_popularity_global WHERE patch_id = 0",
+            )
             .unwrap();
 
         let mut rows = stmt.query([]).unwrap();
@@ -788,8 +2054,40 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
             info!("On game {}...", counter);
         }
 
-        update_player(&tx, g.id_a, &g.name_a, g.game_floor, g.platform_a);
-        update_player(&tx, g.id_b, &g.name_b, g.game_floor, g.platform_b);
+        for key in [(g.id_a, g.char_a), (g.id_b, g.char_b)] {
+            if !players.contains_key(&key) {
+                let player = new_rated_player(
+                    &tx,
+                    &config,
+                    &floor_priors,
+                    &players,
+                    batch_characters.get(&key.0).map_or(&[], Vec::as_slice),
+                    key.0,
+                    key.1,
+                    g.game_floor,
+                    g.timestamp,
+                );
+                players.insert(key, player);
+                batch_characters.entry(key.0).or_default().push(key.1);
+            }
+        }
+
+        update_player(
+            &tx,
+            g.id_a,
+            &g.name_a,
+            g.game_floor,
+            g.platform_a,
+            g.timestamp,
+        );
+        update_player(
+            &tx,
+            g.id_b,
+            &g.name_b,
+            g.game_floor,
+            g.platform_b,
+            g.timestamp,
+        );
 
         let has_cheater = cheaters.contains(&g.id_a) || cheaters.contains(&g.id_b);
         let has_hidden = hidden.contains(&g.id_a) || hidden.contains(&g.id_b);
@@ -862,10 +2160,25 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
 
         if valid {
             //Update ratings
-            players.get_mut(&winner).unwrap().rating = winner_rating.update(loser_rating, 1.0);
+            let winner_new_rating = update_rating(
+                config.smurf_policy,
+                &smurfs,
+                players.get(&winner).unwrap(),
+                loser_rating,
+                1.0,
+            );
+            let loser_new_rating = update_rating(
+                config.smurf_policy,
+                &smurfs,
+                players.get(&loser).unwrap(),
+                winner_rating,
+                0.0,
+            );
+
+            players.get_mut(&winner).unwrap().rating = winner_new_rating;
             players.get_mut(&winner).unwrap().win_count += 1;
 
-            players.get_mut(&loser).unwrap().rating = loser_rating.update(winner_rating, 0.0);
+            players.get_mut(&loser).unwrap().rating = loser_new_rating;
             players.get_mut(&loser).unwrap().loss_count += 1;
 
             //Update player matchups
@@ -1151,16 +2464,44 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
             ],
         )
         .unwrap();
-    }
 
-    for (_, player) in players.into_iter() {
-        if player.rating.deviation < 0.0 {
-            error!("Negative rating deviation???");
-        }
-
-        tx.execute(
-            "REPLACE INTO player_ratings VALUES(
-                ?, ?, ?, ?, ?, ?, ?,
+        let expected_a = old_rating_a.expected(old_rating_b);
+        sessions
+            .add_game(
+                &tx,
+                g.id_a,
+                g.id_b,
+                g.timestamp,
+                g.winner == 1,
+                expected_a,
+                players.get(&(g.id_a, g.char_a)).unwrap().rating.value - old_rating_a.value,
+            )
+            .unwrap();
+        sessions
+            .add_game(
+                &tx,
+                g.id_b,
+                g.id_a,
+                g.timestamp,
+                g.winner == 2,
+                1.0 - expected_a,
+                players.get(&(g.id_b, g.char_b)).unwrap().rating.value - old_rating_b.value,
+            )
+            .unwrap();
+        activity.add_game(g.id_a, g.id_b, g.timestamp);
+    }
+
+    sessions.write(&tx).unwrap();
+    activity.write(&tx).unwrap();
+
+    for (_, player) in players.into_iter() {
+        if player.rating.deviation < 0.0 {
+            error!("Negative rating deviation???");
+        }
+
+        tx.execute(
+            "REPLACE INTO player_ratings VALUES(
+                ?, ?, ?, ?, ?, ?, ?,
                 ?, ?, ?, 
                 ?, ?, ?, ?, ?, ?, ?)",
             params![
@@ -1199,9 +2540,46 @@ fn update_ratings(conn: &mut Connection, games: Option<Vec<Game>>) -> i64 {
 }
 
 pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) -> Result<()> {
+    calc_character_popularity_since(conn, last_timestamp - 60 * 60 * 24 * 7, i64::MAX, 0)?;
+    snapshot_character_popularity(conn, last_timestamp)?;
+    if let Some((patch_id, from, until)) = latest_patch(conn, last_timestamp)? {
+        calc_character_popularity_since(conn, from, until, patch_id)?;
+    }
+    Ok(())
+}
+
+/// Copies the rolling window popularity into the history tables, so trends can be followed.
+fn snapshot_character_popularity(conn: &mut Connection, timestamp: i64) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT OR REPLACE INTO character_popularity_global_history
+        SELECT ?, char_id, popularity, sample_size
+        FROM character_popularity_global
+        WHERE patch_id = 0",
+        params![timestamp],
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO character_popularity_rating_history
+        SELECT ?, char_id, rating_bracket, popularity, sample_size
+        FROM character_popularity_rating
+        WHERE patch_id = 0",
+        params![timestamp],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Character popularity of the games between `since` and `until`, stored under `patch_id`.
+/// Patch 0 is the rolling window.
+fn calc_character_popularity_since(
+    conn: &mut Connection,
+    since: i64,
+    until: i64,
+    patch_id: i64,
+) -> Result<()> {
     let then = Utc::now();
+    let char_count = website::char_names().len();
     info!("Calculating character popularity stats..");
-    let one_week_ago = last_timestamp - 60 * 60 * 24 * 7;
 
     let tx = conn.transaction()?;
     info!("making temp table");
@@ -1217,8 +2595,8 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
             deviation_b
         FROM
             games NATURAL JOIN game_ratings
-        WHERE timestamp > ? AND (deviation_a < ? OR deviation_b < ?)",
-        params![one_week_ago, LOW_DEVIATION, LOW_DEVIATION],
+        WHERE timestamp > ? AND timestamp < ? AND (deviation_a < ? OR deviation_b < ?)",
+        params![since, until, LOW_DEVIATION, LOW_DEVIATION],
     )?;
     info!("making indices");
     tx.execute("CREATE INDEX temp.i_char_a ON recent_games(char_a)", [])?;
@@ -1228,8 +2606,14 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
 
     let tx = conn.transaction()?;
 
-    tx.execute("DELETE FROM character_popularity_global", [])?;
-    tx.execute("DELETE FROM character_popularity_rating", [])?;
+    tx.execute(
+        "DELETE FROM character_popularity_global WHERE patch_id = ?",
+        params![patch_id],
+    )?;
+    tx.execute(
+        "DELETE FROM character_popularity_rating WHERE patch_id = ?",
+        params![patch_id],
+    )?;
 
     let global_game_count: f64 =
         tx.query_row("SELECT COUNT(*) FROM  temp.recent_games", params![], |r| {
@@ -1241,7 +2625,7 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
         return Ok(());
     }
 
-    for c in 0..CHAR_COUNT {
+    for c in 0..char_count {
         let char_count: f64 = tx.query_row(
             "SELECT
                     (SELECT COUNT(*) FROM temp.recent_games
@@ -1254,8 +2638,13 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
         )?;
 
         tx.execute(
-            "INSERT INTO character_popularity_global VALUES(?, ?)",
-            params![c, char_count / global_game_count],
+            "INSERT INTO character_popularity_global VALUES(?, ?, ?, ?)",
+            params![
+                patch_id,
+                c,
+                char_count / global_game_count,
+                2.0 * global_game_count
+            ],
         )?;
     }
 
@@ -1286,7 +2675,7 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
             |r| r.get(0),
         )?;
 
-        for c in 0..CHAR_COUNT {
+        for c in 0..char_count {
             let char_count: f64 = tx.query_row(
                 "SELECT
                     (SELECT COUNT(*) FROM temp.recent_games
@@ -1314,8 +2703,14 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
             )?;
 
             tx.execute(
-                "INSERT INTO character_popularity_rating VALUES(?, ?, ?)",
-                params![c, r, 2.0 * char_count / rating_game_count.max(1.0)],
+                "INSERT INTO character_popularity_rating VALUES(?, ?, ?, ?, ?)",
+                params![
+                    patch_id,
+                    c,
+                    r,
+                    2.0 * char_count / rating_game_count.max(1.0),
+                    rating_game_count
+                ],
             )?;
         }
     }
@@ -1330,6 +2725,435 @@ pub fn calc_character_popularity(conn: &mut Connection, last_timestamp: i64) ->
     Ok(())
 }
 
+/// The rating bracket a rating falls in: below 1100, then 100 wide up to 2200 and above.
+pub fn rating_bracket(value: f64) -> usize {
+    (((value - 1000.0) / 100.0).floor().max(0.0) as usize).min(POP_RATING_BRACKETS - 1)
+}
+
+/// Matchup results of the last weeks and of the current patch per rating bracket, using the
+/// ratings from before each game so the expected result can be taken out of the win rate.
+pub fn calc_bracket_matchups(conn: &mut Connection, last_timestamp: i64) -> Result<()> {
+    calc_bracket_matchups_since(conn, last_timestamp - BRACKET_MATCHUP_WINDOW, i64::MAX, 0)?;
+    if let Some((patch_id, from, until)) = latest_patch(conn, last_timestamp)? {
+        calc_bracket_matchups_since(conn, from, until, patch_id)?;
+    }
+    Ok(())
+}
+
+/// Bracket matchups of the games between `since` and `until`, stored under `patch_id`.
+fn calc_bracket_matchups_since(
+    conn: &mut Connection,
+    since: i64,
+    until: i64,
+    patch_id: i64,
+) -> Result<()> {
+    let then = Utc::now();
+    let char_count = website::char_names().len();
+
+    #[derive(Clone, Copy, Default)]
+    struct BracketMatchup {
+        wins: i64,
+        losses: i64,
+        expected_wins: f64,
+        rating_delta_sum: f64,
+    }
+
+    let mut matchups =
+        vec![BracketMatchup::default(); POP_RATING_BRACKETS * char_count * char_count];
+    let index = |bracket: usize, c: usize, o: usize| (bracket * char_count + c) * char_count + o;
+
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "SELECT
+                char_a, value_a, deviation_a,
+                char_b, value_b, deviation_b,
+                winner
+            FROM games NATURAL JOIN game_ratings
+            WHERE timestamp > ? AND timestamp < ?
+                AND valid AND char_a != char_b AND deviation_a < ? AND deviation_b < ?",
+        )?;
+        let mut rows = stmt.query(params![since, until, LOW_DEVIATION, LOW_DEVIATION])?;
+
+        while let Some(row) = rows.next()? {
+            let char_a: usize = row.get(0)?;
+            let rating_a = Rating::new(row.get(1)?, row.get(2)?);
+            let char_b: usize = row.get(3)?;
+            let rating_b = Rating::new(row.get(4)?, row.get(5)?);
+            let winner: i64 = row.get(6)?;
+
+            let bracket = rating_bracket((rating_a.value + rating_b.value) / 2.0);
+            let expected = rating_a.expected(rating_b);
+
+            let a = &mut matchups[index(bracket, char_a, char_b)];
+            if winner == 1 {
+                a.wins += 1;
+            } else {
+                a.losses += 1;
+            }
+            a.expected_wins += expected;
+            a.rating_delta_sum += rating_a.value - rating_b.value;
+
+            let b = &mut matchups[index(bracket, char_b, char_a)];
+            if winner == 1 {
+                b.losses += 1;
+            } else {
+                b.wins += 1;
+            }
+            b.expected_wins += 1.0 - expected;
+            b.rating_delta_sum += rating_b.value - rating_a.value;
+        }
+    }
+
+    tx.execute(
+        "DELETE FROM bracket_matchups WHERE patch_id = ?",
+        params![patch_id],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO bracket_matchups
+            (patch_id, rating_bracket, char_id, opp_char_id,
+                wins, losses, expected_wins, rating_delta_sum)
+            VALUES(?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for bracket in 0..POP_RATING_BRACKETS {
+            for c in 0..char_count {
+                for o in 0..char_count {
+                    let m = matchups[index(bracket, c, o)];
+                    if m.wins + m.losses == 0 {
+                        continue;
+                    }
+                    stmt.execute(params![
+                        patch_id,
+                        bracket,
+                        c,
+                        o,
+                        m.wins,
+                        m.losses,
+                        m.expected_wins,
+                        m.rating_delta_sum
+                    ])?;
+                }
+            }
+        }
+    }
+    tx.commit()?;
+
+    info!(
+        "Updated bracket matchups - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+pub fn calc_bracket_matchups_once() {
+    let mut conn = Connection::open(DB_NAME).unwrap();
+    calc_bracket_matchups(&mut conn, Utc::now().timestamp()).unwrap();
+}
+
+/// Fits a rating offset per character pair so that the expected result of each game, from the
+/// players' pre-game ratings plus the offset, best matches the actual results of the last weeks
+/// and of the current patch.
+/// Every game only depends on the offset of its own pair, so each pair is fitted on its own.
+pub fn estimate_matchups(conn: &mut Connection, last_timestamp: i64) -> Result<()> {
+    estimate_matchups_since(conn, last_timestamp - MATCHUP_ESTIMATE_WINDOW, i64::MAX, 0)?;
+    if let Some((patch_id, from, until)) = latest_patch(conn, last_timestamp)? {
+        estimate_matchups_since(conn, from, until, patch_id)?;
+    }
+    Ok(())
+}
+
+/// Estimated matchups of the games between `since` and `until`, stored under `patch_id`.
+fn estimate_matchups_since(
+    conn: &mut Connection,
+    since: i64,
+    until: i64,
+    patch_id: i64,
+) -> Result<()> {
+    let then = Utc::now();
+    let char_count = website::char_names().len();
+
+    // Per pair with char_a < char_b: rating difference, g of the combined deviation, and whether
+    // char_a won, all from char_a's side.
+    let mut games: Vec<Vec<(f64, f64, bool)>> = vec![Vec::new(); char_count * char_count];
+
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "SELECT
+                char_a, value_a, deviation_a,
+                char_b, value_b, deviation_b,
+                winner
+            FROM games NATURAL JOIN game_ratings
+            WHERE timestamp > ? AND timestamp < ?
+                AND valid AND char_a != char_b AND deviation_a < ? AND deviation_b < ?",
+        )?;
+        let mut rows = stmt.query(params![since, until, LOW_DEVIATION, LOW_DEVIATION])?;
+
+        while let Some(row) = rows.next()? {
+            let char_a: usize = row.get(0)?;
+            let value_a: f64 = row.get(1)?;
+            let deviation_a: f64 = row.get(2)?;
+            let char_b: usize = row.get(3)?;
+            let value_b: f64 = row.get(4)?;
+            let deviation_b: f64 = row.get(5)?;
+            let a_won = row.get::<_, i64>(6)? == 1;
+
+            let g = glicko::g((deviation_a * deviation_a + deviation_b * deviation_b).sqrt());
+            if char_a < char_b {
+                games[char_a * char_count + char_b].push((value_a - value_b, g, a_won));
+            } else {
+                games[char_b * char_count + char_a].push((value_b - value_a, g, !a_won));
+            }
+        }
+    }
+
+    tx.execute(
+        "DELETE FROM estimated_matchups WHERE patch_id = ?",
+        params![patch_id],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO estimated_matchups
+            (patch_id, char_id, opp_char_id, wins, losses, rating_offset, rating_offset_deviation)
+            VALUES(?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        let k = std::f64::consts::LN_10 / 400.0;
+
+        for c in 0..char_count {
+            for o in c + 1..char_count {
+                let games = &games[c * char_count + o];
+                if games.is_empty() {
+                    continue;
+                }
+
+                let mut offset = 0.0;
+                let mut information = 0.0;
+                for _ in 0..20 {
+                    let mut gradient = -offset / (MATCHUP_OFFSET_PRIOR * MATCHUP_OFFSET_PRIOR);
+                    information = 1.0 / (MATCHUP_OFFSET_PRIOR * MATCHUP_OFFSET_PRIOR);
+                    for &(delta, g, won) in games {
+                        let p = 1.0 / (1.0 + 10f64.powf(-g * (delta + offset) / 400.0));
+                        let result = if won { 1.0 } else { 0.0 };
+                        gradient += k * g * (result - p);
+                        information += k * k * g * g * p * (1.0 - p);
+                    }
+
+                    let step = gradient / information;
+                    offset += step;
+                    if step.abs() < 0.01 {
+                        break;
+                    }
+                }
+                let offset_deviation = 1.0 / information.sqrt();
+
+                let wins = games.iter().filter(|(_, _, won)| *won).count();
+                let losses = games.len() - wins;
+                stmt.execute(params![
+                    patch_id,
+                    c,
+                    o,
+                    wins,
+                    losses,
+                    offset,
+                    offset_deviation
+                ])?;
+                stmt.execute(params![
+                    patch_id,
+                    o,
+                    c,
+                    losses,
+                    wins,
+                    -offset,
+                    offset_deviation
+                ])?;
+            }
+        }
+    }
+    tx.commit()?;
+
+    info!(
+        "Estimated matchups - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+pub fn estimate_matchups_once() {
+    let mut conn = Connection::open(DB_NAME).unwrap();
+    estimate_matchups(&mut conn, Utc::now().timestamp()).unwrap();
+}
+
+/// Scores every character by combining, all in rating points:
+///
+/// - matchups: the rating offset from `estimated_matchups` against the field, weighted by the
+///   popularity of each opponent,
+/// - fraud: the fraud index, how much higher players rate with this character than with their
+///   other characters,
+/// - representation: `TIER_REPRESENTATION_SCALE` points per doubling of the character's share of
+///   the global top 1000 and of the top 3 rating brackets compared to its overall popularity.
+///
+/// The score is the weighted sum of these, and the tiers are cut at the z-scores in `TIERS`.
+pub fn calc_tier_list(conn: &mut Connection, timestamp: i64) -> Result<()> {
+    let then = Utc::now();
+    let char_count = website::char_names().len();
+    let tx = conn.transaction()?;
+
+    let mut popularity = vec![0.0; char_count];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT char_id, popularity FROM character_popularity_global WHERE patch_id = 0",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            popularity[row.get::<_, usize>(0)?] = row.get(1)?;
+        }
+    }
+    let total: f64 = popularity.iter().sum();
+    if total == 0.0 {
+        info!("No character popularity yet, unable to calculate the tier list");
+        return Ok(());
+    }
+    popularity.iter_mut().for_each(|p| *p /= total);
+
+    let mut matchup = vec![0.0; char_count];
+    {
+        let mut weights = vec![0.0; char_count];
+        let mut stmt = tx.prepare(
+            "SELECT char_id, opp_char_id, rating_offset
+                FROM estimated_matchups
+                WHERE patch_id = 0",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let c: usize = row.get(0)?;
+            let o: usize = row.get(1)?;
+            let offset: f64 = row.get(2)?;
+            matchup[c] += popularity[o] * offset;
+            weights[c] += popularity[o];
+        }
+        for c in 0..char_count {
+            if weights[c] > 0.0 {
+                matchup[c] /= weights[c];
+            }
+        }
+    }
+
+    let mut fraud = vec![0.0; char_count];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT char_id, avg_delta FROM fraud_index
+            WHERE rating_threshold = 0
+                AND timestamp = (SELECT MAX(timestamp) FROM fraud_index)",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            fraud[row.get::<_, usize>(0)?] = row.get(1)?;
+        }
+    }
+
+    let mut top_share = vec![0.0; char_count];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT char_id, COUNT(*) FROM ranking_global WHERE global_rank <= 1000 GROUP BY char_id",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut total = 0.0;
+        while let Some(row) = rows.next()? {
+            let count: f64 = row.get(1)?;
+            top_share[row.get::<_, usize>(0)?] = count;
+            total += count;
+        }
+        top_share.iter_mut().for_each(|s| *s /= total.max(1.0));
+    }
+
+    let mut bracket_share = vec![0.0; char_count];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT char_id, AVG(popularity) FROM character_popularity_rating
+            WHERE patch_id = 0 AND rating_bracket >= ? GROUP BY char_id",
+        )?;
+        let mut rows = stmt.query(params![POP_RATING_BRACKETS - 3])?;
+        while let Some(row) = rows.next()? {
+            bracket_share[row.get::<_, usize>(0)?] = row.get(1)?;
+        }
+        let total: f64 = bracket_share.iter().sum();
+        bracket_share
+            .iter_mut()
+            .for_each(|s| *s /= total.max(f64::EPSILON));
+    }
+
+    let representation: Vec<f64> = (0..char_count)
+        .map(|c| {
+            let ratio = |share: f64| {
+                if popularity[c] > 0.0 && share > 0.0 {
+                    (share / popularity[c]).log2()
+                } else {
+                    0.0
+                }
+            };
+            TIER_REPRESENTATION_SCALE * (ratio(top_share[c]) + ratio(bracket_share[c])) / 2.0
+        })
+        .collect();
+
+    let scores: Vec<f64> = (0..char_count)
+        .map(|c| {
+            TIER_MATCHUP_WEIGHT * matchup[c]
+                + TIER_FRAUD_WEIGHT * fraud[c]
+                + TIER_REPRESENTATION_WEIGHT * representation[c]
+        })
+        .collect();
+
+    let tiers = assign_tiers(&scores);
+
+    tx.execute(
+        "DELETE FROM tier_list WHERE timestamp = ?",
+        params![timestamp],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO tier_list
+            (timestamp, char_id, matchup_score, fraud_score, representation_score, score, tier)
+            VALUES(?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for c in 0..char_count {
+            stmt.execute(params![
+                timestamp,
+                c,
+                matchup[c],
+                fraud[c],
+                representation[c],
+                scores[c],
+                tiers[c]
+            ])?;
+        }
+    }
+    tx.commit()?;
+
+    info!(
+        "Calculated tier list - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
+/// The tier of each score, from how many standard deviations it is from the mean.
+fn assign_tiers(scores: &[f64]) -> Vec<&'static str> {
+    let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+    let std_dev = (scores.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>()
+        / scores.len() as f64)
+        .sqrt()
+        .max(f64::EPSILON);
+
+    scores
+        .iter()
+        .map(|s| {
+            let z = (s - mean) / std_dev;
+            TIERS.iter().find(|(_, min)| z >= *min).unwrap().0
+        })
+        .collect()
+}
+
 pub fn update_rankings_once() {
     let mut conn = Connection::open(DB_NAME).unwrap();
     update_rankings(&mut conn).unwrap();
@@ -1338,6 +3162,7 @@ pub fn update_rankings_once() {
 pub fn update_rankings(conn: &mut Connection) -> Result<()> {
     info!("Updating rankings");
     let then = Utc::now();
+    let char_count = website::char_names().len();
     let tx = conn.transaction()?;
     tx.execute("DELETE FROM ranking_global", [])?;
     tx.execute("DELETE FROM ranking_character", [])?;
@@ -1355,7 +3180,7 @@ pub fn update_rankings(conn: &mut Connection) -> Result<()> {
         params![LOW_DEVIATION],
     )?;
 
-    for c in 0..CHAR_COUNT {
+    for c in 0..char_count {
         tx.execute(
             "INSERT INTO ranking_character (character_rank, id, char_id)
              SELECT ROW_NUMBER() 
@@ -1370,6 +3195,39 @@ pub fn update_rankings(conn: &mut Connection) -> Result<()> {
         )?;
     }
 
+    tx.execute("DELETE FROM ranking_global_platform", [])?;
+    tx.execute("DELETE FROM ranking_character_platform", [])?;
+
+    tx.execute(
+        "INSERT INTO ranking_global_platform (platform, global_rank, id, char_id)
+         SELECT platform, global_rank, id, char_id FROM (
+            SELECT players.platform AS platform, ROW_NUMBER()
+            OVER (PARTITION BY players.platform ORDER BY value DESC) as global_rank,
+            player_ratings.id AS id, char_id
+            FROM player_ratings
+               JOIN players on player_ratings.id = players.id
+               LEFT JOIN cheater_status on player_ratings.id = cheater_status.id
+               LEFT JOIN hidden_status on player_ratings.id = hidden_status.id
+            WHERE deviation < ? AND cheater_status IS NULL AND hidden_status IS NULL)
+         WHERE global_rank <= 1000",
+        params![LOW_DEVIATION],
+    )?;
+
+    tx.execute(
+        "INSERT INTO ranking_character_platform (platform, character_rank, char_id, id)
+         SELECT platform, character_rank, char_id, id FROM (
+            SELECT players.platform AS platform, ROW_NUMBER()
+            OVER (PARTITION BY players.platform, char_id ORDER BY value DESC) as character_rank,
+            player_ratings.id AS id, char_id
+            FROM player_ratings
+               JOIN players on player_ratings.id = players.id
+               LEFT JOIN cheater_status on player_ratings.id = cheater_status.id
+               LEFT JOIN hidden_status on player_ratings.id = hidden_status.id
+            WHERE deviation < ? AND cheater_status IS NULL AND hidden_status IS NULL)
+         WHERE character_rank <= 1000",
+        params![LOW_DEVIATION],
+    )?;
+
     tx.commit()?;
     info!(
         "Updated rankings - {}ms",
@@ -1378,6 +3236,52 @@ pub fn update_rankings(conn: &mut Connection) -> Result<()> {
     Ok(())
 }
 
+/// Stores the top of the current rankings as the snapshot for the day `timestamp` falls on.
+/// Later calls on the same day overwrite the snapshot, so each day ends up holding the last
+/// rankings calculated that day.
+pub fn snapshot_rankings(conn: &mut Connection, timestamp: i64) -> Result<()> {
+    info!("Snapshotting rankings");
+    let then = Utc::now();
+    let day_timestamp = NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .unwrap()
+        .date()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .timestamp();
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM ranking_global_history WHERE timestamp = ?",
+        params![day_timestamp],
+    )?;
+    tx.execute(
+        "DELETE FROM ranking_character_history WHERE timestamp = ?",
+        params![day_timestamp],
+    )?;
+
+    tx.execute(
+        "INSERT INTO ranking_global_history
+        SELECT ?, global_rank, id, char_id, wins, losses, value, deviation
+        FROM ranking_global NATURAL JOIN player_ratings
+        WHERE global_rank <= ?",
+        params![day_timestamp, RANKING_SNAPSHOT_SIZE],
+    )?;
+    tx.execute(
+        "INSERT INTO ranking_character_history
+        SELECT ?, character_rank, char_id, id, wins, losses, value, deviation
+        FROM ranking_character NATURAL JOIN player_ratings
+        WHERE character_rank <= ?",
+        params![day_timestamp, RANKING_SNAPSHOT_SIZE],
+    )?;
+
+    tx.commit()?;
+    info!(
+        "Snapshotted rankings - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+    Ok(())
+}
+
 pub fn update_decay(conn: &mut Connection, timestamp: i64) -> Result<()> {
     info!("Updating decay");
     let then = Utc::now();
@@ -1522,181 +3426,1252 @@ fn decay_matchups(conn: &mut Connection, _timestamp: i64) -> Result<()> {
     Ok(())
 }
 
-pub fn calc_fraud_index(conn: &mut Connection) -> Result<()> {
+pub fn calc_fraud_index(conn: &mut Connection, timestamp: i64) -> Result<()> {
     let then = Utc::now();
     info!("Calculating fraud index");
     let tx = conn.transaction()?;
-    tx.execute("DELETE FROM fraud_index", [])?;
-    tx.execute("DELETE FROM fraud_index_higher_rated", [])?;
-    tx.execute("DELETE FROM fraud_index_highest_rated", [])?;
+    tx.execute(
+        "DELETE FROM fraud_index WHERE timestamp = ?",
+        params![timestamp],
+    )?;
 
-    {
-        let mut stmt = tx
-            .prepare(
-                "select 
-                    char_id, 
-                    count(*), 
-                    avg(value - 
-                        (avg_value - (1.0 / char_count) * value)
-                        * char_count
-                        / (char_count - 1.0))
-            from
-                (
-                    select id, avg_value, char_count from
-                    (
-                        select 
-                            id, 
-                            avg(value) as avg_value, 
-                            count(char_id) as char_count
-                        from player_ratings
-                        where deviation < ? and wins + losses >= 200
-                        group by id
-                    ) as averages
-                    where char_count > 1
-                ) as filtered_averages
-
-                join
+    for threshold in FRAUD_THRESHOLDS {
+        calc_fraud_index_above(&tx, timestamp, *threshold)?;
+    }
+
+    tx.commit()?;
+
+    info!(
+        "Updated fraud index - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
 
+/// How much higher players rate with each character than with their other characters, counting
+/// only the characters rated above `threshold` and above the player's average.
+fn calc_fraud_index_above(tx: &Transaction, timestamp: i64, threshold: i64) -> Result<()> {
+    let mut stmt = tx.prepare(
+        "select 
+                char_id, 
+                count(*), 
+                avg(value - 
+                    (avg_value - (1.0 / char_count) * value)
+                    * char_count
+                    / (char_count - 1.0))
+        from
+            (
+                select id, avg_value, char_count from
                 (
-                    select id, char_id, value
+                    select 
+                        id, 
+                        avg(value) as avg_value, 
+                        count(char_id) as char_count
                     from player_ratings
                     where deviation < ? and wins + losses >= 200
-                ) as char_ratings
+                    group by id
+                ) as averages
+                where char_count > 1
+            ) as filtered_averages
 
-                on filtered_averages.id = char_ratings.id
-                
-                where char_ratings.value > filtered_averages.avg_value
+            join
 
-            group by char_id;",
-            )
-            .unwrap();
+            (
+                select id, char_id, value
+                from player_ratings
+                where deviation < ? and wins + losses >= 200
+            ) as char_ratings
 
-        let mut rows = stmt.query(params![LOW_DEVIATION, LOW_DEVIATION]).unwrap();
+            on filtered_averages.id = char_ratings.id
 
-        while let Some(row) = rows.next().unwrap() {
-            let char_id: i64 = row.get(0).unwrap();
-            let player_count: i64 = row.get(1).unwrap();
-            let avg_delta: f64 = row.get(2).unwrap();
-            tx.execute(
-                "INSERT INTO fraud_index VALUES(?, ?, ?)",
-                params![char_id, player_count, avg_delta],
-            )
-            .unwrap();
-        }
+            where char_ratings.value > filtered_averages.avg_value
+                and char_ratings.value > ?
 
-        let mut stmt = tx
-            .prepare(
-                "select 
-                    char_id, 
-                    count(*), 
-                    avg(value - 
-                        (avg_value - (1.0 / char_count) * value)
-                        * char_count
-                        / (char_count - 1.0))
-            from
-                (
-                    select id, avg_value, char_count from
-                    (
-                        select 
-                            id, 
-                            avg(value) as avg_value, 
-                            count(char_id) as char_count
-                        from player_ratings
-                        where deviation < ? and wins + losses >= 200
-                        group by id
-                    ) as averages
-                    where char_count > 1
-                ) as filtered_averages
-
-                join
+        group by char_id;",
+    )?;
 
-                (
-                    select id, char_id, value
-                    from player_ratings
-                    where deviation < ? and wins + losses >= 200
-                ) as char_ratings
+    let mut rows = stmt.query(params![LOW_DEVIATION, LOW_DEVIATION, threshold])?;
 
-                on filtered_averages.id = char_ratings.id
+    while let Some(row) = rows.next()? {
+        let char_id: i64 = row.get(0)?;
+        let player_count: i64 = row.get(1)?;
+        let avg_delta: f64 = row.get(2)?;
+        tx.execute(
+            "INSERT INTO fraud_index
+            (timestamp, rating_threshold, char_id, player_count, avg_delta)
+            VALUES(?, ?, ?, ?, ?)",
+            params![timestamp, threshold, char_id, player_count, avg_delta],
+        )?;
+    }
 
-                where char_ratings.value > filtered_averages.avg_value
-                    and char_ratings.value > 1500
+    Ok(())
+}
 
-            group by char_id;",
-            )
-            .unwrap();
+pub fn detect_anomalies_once() -> Result<()> {
+    let mut conn = Connection::open(DB_NAME)?;
+    detect_anomalies(&mut conn, Utc::now().timestamp())
+}
 
-        let mut rows = stmt.query(params![LOW_DEVIATION, LOW_DEVIATION]).unwrap();
+/// Flags players whose games in the last ANOMALY_WINDOW look improbable, for moderators to review.
+/// Nobody is marked automatically. Flags of the same run share the timestamp.
+pub fn detect_anomalies(conn: &mut Connection, timestamp: i64) -> Result<()> {
+    let then = Utc::now();
+    info!("Detecting anomalies");
+
+    struct Streak {
+        length: i64,
+        log_chance: f64,
+        best_length: i64,
+        best_log_chance: f64,
+    }
+
+    struct Pair {
+        games: i64,
+        alternations: i64,
+        wins_low: i64,
+        last_winner: i64,
+    }
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM anomalies WHERE timestamp = ?",
+        params![timestamp],
+    )?;
+
+    let cheaters = {
+        let mut stmt = tx.prepare("SELECT id FROM cheater_status")?;
+        let cheaters = stmt
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<FxHashSet<i64>>>()?;
+        cheaters
+    };
+
+    let mut streaks = FxHashMap::<(i64, i64), Streak>::default();
+    let mut gains = FxHashMap::<(i64, i64), (Rating, f64, i64)>::default();
+    let mut pairs = FxHashMap::<(i64, i64), Pair>::default();
+    let mut fresh_losses = FxHashMap::<i64, FxHashSet<i64>>::default();
+
+    {
+        let mut stmt = tx.prepare(
+            "SELECT timestamp, id_a, char_a, value_a, deviation_a, id_b, char_b, value_b, deviation_b, winner
+            FROM game_ratings
+            NATURAL JOIN games
+            WHERE timestamp > ?
+            ORDER BY timestamp",
+        )?;
+        let mut rows = stmt.query(params![timestamp - ANOMALY_WINDOW])?;
+
+        while let Some(row) = rows.next()? {
+            let game_timestamp: i64 = row.get(0)?;
+            let a = (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?);
+            let rating_a = Rating::new(row.get(3)?, row.get(4)?);
+            let b = (row.get::<_, i64>(5)?, row.get::<_, i64>(6)?);
+            let rating_b = Rating::new(row.get(7)?, row.get(8)?);
+            let winner: i64 = row.get(9)?;
+
+            let (winner, winner_rating, loser, loser_rating) = match winner {
+                1 => (a, rating_a, b, rating_b),
+                2 => (b, rating_b, a, rating_a),
+                _ => continue,
+            };
+
+            // The chance of the winner's streak is the product of the expected outcomes
+            let streak = streaks.entry(winner).or_insert(Streak {
+                length: 0,
+                log_chance: 0.0,
+                best_length: 0,
+                best_log_chance: 0.0,
+            });
+            streak.length += 1;
+            streak.log_chance += winner_rating.expected(loser_rating).ln();
+            if streak.log_chance < streak.best_log_chance {
+                streak.best_length = streak.length;
+                streak.best_log_chance = streak.log_chance;
+            }
+            if let Some(streak) = streaks.get_mut(&loser) {
+                streak.length = 0;
+                streak.log_chance = 0.0;
+            }
+
+            for (player, rating) in [(winner, winner_rating), (loser, loser_rating)] {
+                let gain = gains
+                    .entry(player)
+                    .or_insert((rating, rating.value, game_timestamp));
+                gain.1 = rating.value;
+                gain.2 = game_timestamp;
+            }
+
+            let key = (winner.0.min(loser.0), winner.0.max(loser.0));
+            let pair = pairs.entry(key).or_insert(Pair {
+                games: 0,
+                alternations: 0,
+                wins_low: 0,
+                last_winner: winner.0,
+            });
+            pair.games += 1;
+            if pair.last_winner != winner.0 {
+                pair.alternations += 1;
+            }
+            pair.last_winner = winner.0;
+            if winner.0 == key.0 {
+                pair.wins_low += 1;
+            }
+
+            if loser_rating.deviation >= ANOMALY_NEW_ACCOUNT_DEVIATION {
+                fresh_losses.entry(winner.0).or_default().insert(loser.0);
+            }
+        }
+    }
+
+    let mut anomalies = FxHashMap::<(i64, &str), (f64, String)>::default();
+    let mut flag = |id: i64, kind: &'static str, score: f64, explanation: String| {
+        if cheaters.contains(&id) {
+            return;
+        }
+        let current = anomalies
+            .entry((id, kind))
+            .or_insert((score, explanation.clone()));
+        if score > current.0 {
+            *current = (score, explanation);
+        }
+    };
+
+    for ((id, char_id), streak) in &streaks {
+        let chance = streak.best_log_chance.exp();
+        if chance < ANOMALY_STREAK_CHANCE {
+            flag(
+                *id,
+                "streak",
+                -chance.log10(),
+                format!(
+                    "{} wins in a row as {} with a chance of 1 in {:.0}",
+                    streak.best_length,
+                    website::character(*char_id as usize).1,
+                    1.0 / chance
+                ),
+            );
+        }
+    }
+
+    for ((id, char_id), (first, last, _)) in &gains {
+        let gain = last - first.value;
+        if first.deviation < LOW_DEVIATION && gain > ANOMALY_MAX_GAIN {
+            flag(
+                *id,
+                "gain",
+                gain / 100.0,
+                format!(
+                    "Gained {:.0} rating as {} from an established {:.0}",
+                    gain,
+                    website::character(*char_id as usize).1,
+                    first.value
+                ),
+            );
+        }
+    }
+
+    for ((low, high), pair) in &pairs {
+        if pair.games < ANOMALY_PAIR_GAMES {
+            continue;
+        }
+        let alternation = pair.alternations as f64 / (pair.games - 1) as f64;
+        let one_sided = pair.wins_low.max(pair.games - pair.wins_low) as f64 / pair.games as f64;
+        if alternation < ANOMALY_PAIR_ALTERNATION && one_sided < ANOMALY_PAIR_ONE_SIDED {
+            continue;
+        }
+        for (id, other) in [(*low, *high), (*high, *low)] {
+            flag(
+                id,
+                "pair",
+                pair.games as f64 / ANOMALY_PAIR_GAMES as f64,
+                format!(
+                    "{} games against {:X}, {:.0}% alternating wins, {:.0}% won by one side",
+                    pair.games,
+                    other,
+                    alternation * 100.0,
+                    one_sided * 100.0
+                ),
+            );
+        }
+    }
+
+    {
+        let mut stmt = tx.prepare_cached(
+            "SELECT (SELECT COUNT(*) FROM games WHERE id_a = ?1)
+                + (SELECT COUNT(*) FROM games WHERE id_b = ?1)",
+        )?;
+        let mut game_counts = FxHashMap::<i64, i64>::default();
+
+        for (id, losers) in &fresh_losses {
+            if (losers.len() as i64) < ANOMALY_NEW_ACCOUNT_VICTIMS {
+                continue;
+            }
+            let mut new_accounts = 0;
+            for loser in losers {
+                let games = match game_counts.get(loser) {
+                    Some(games) => *games,
+                    None => {
+                        let games: i64 = stmt.query_row(params![loser], |r| r.get(0))?;
+                        game_counts.insert(*loser, games);
+                        games
+                    }
+                };
+                if games <= ANOMALY_NEW_ACCOUNT_GAMES {
+                    new_accounts += 1;
+                }
+            }
+            if new_accounts >= ANOMALY_NEW_ACCOUNT_VICTIMS {
+                flag(
+                    *id,
+                    "new_accounts",
+                    new_accounts as f64 / ANOMALY_NEW_ACCOUNT_VICTIMS as f64,
+                    format!(
+                        "Beat {} accounts with at most {} games",
+                        new_accounts, ANOMALY_NEW_ACCOUNT_GAMES
+                    ),
+                );
+            }
+        }
+    }
+
+    let flagged = anomalies.len();
+    for ((id, kind), (score, explanation)) in anomalies {
+        tx.execute(
+            "INSERT INTO anomalies(timestamp, id, kind, score, explanation)
+            VALUES(?, ?, ?, ?, ?)",
+            params![timestamp, id, kind, score, explanation],
+        )?;
+    }
+
+    tx.commit()?;
+
+    info!(
+        "Flagged {} anomalies - {}ms",
+        flagged,
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
+
+pub fn detect_smurfs_once() -> Result<()> {
+    let mut conn = Connection::open(DB_NAME)?;
+    detect_smurfs(&mut conn, Utc::now().timestamp())
+}
+
+/// How many standard deviations the wins of a new character's first games are above what their
+/// ratings expected, `None` before SMURF_MIN_GAMES games.
+fn smurf_z(games: i64, wins: i64, expected_wins: f64, variance: f64) -> Option<f64> {
+    if games < SMURF_MIN_GAMES || variance <= 0.0 {
+        return None;
+    }
+    Some((wins as f64 - expected_wins) / variance.sqrt())
+}
+
+/// Whether the character has a game before `timestamp`. Seeding and floor priors mean a new
+/// character doesn't necessarily start at the default rating, so this is what tells them apart.
+fn played_before(
+    tx: &Transaction,
+    id: i64,
+    char_id: i64,
+    timestamp: i64,
+) -> rusqlite::Result<bool> {
+    tx.prepare_cached(
+        "SELECT EXISTS(SELECT 1 FROM games WHERE id_a = ? AND char_a = ? AND timestamp < ?)
+            OR EXISTS(SELECT 1 FROM games WHERE id_b = ? AND char_b = ? AND timestamp < ?)",
+    )?
+    .query_row(
+        params![id, char_id, timestamp, id, char_id, timestamp],
+        |r| r.get(0),
+    )
+}
+
+/// Flags characters whose first games went far better than their rating expected, then links each
+/// flagged player to the players they're most likely an alt of, by name, opponents and active hours.
+pub fn detect_smurfs(conn: &mut Connection, timestamp: i64) -> Result<()> {
+    let then = Utc::now();
+    info!("Detecting smurfs");
+
+    struct Start {
+        games: i64,
+        wins: i64,
+        expected_wins: f64,
+        variance: f64,
+    }
+
+    let tx = conn.transaction()?;
+
+    let mut starts = FxHashMap::<(i64, i64), Start>::default();
+    let mut established = FxHashSet::<(i64, i64)>::default();
+    let mut opponents = FxHashMap::<i64, FxHashSet<i64>>::default();
+    let mut hours = FxHashMap::<i64, [f64; 24]>::default();
+
+    {
+        let mut stmt = tx.prepare(
+            "SELECT timestamp, id_a, char_a, value_a, deviation_a, id_b, char_b, value_b, deviation_b, winner
+            FROM game_ratings
+            NATURAL JOIN games
+            WHERE timestamp > ?
+            ORDER BY timestamp",
+        )?;
+        let mut rows = stmt.query(params![timestamp - SMURF_WINDOW])?;
+
+        while let Some(row) = rows.next()? {
+            let game_timestamp: i64 = row.get(0)?;
+            let a = (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?);
+            let rating_a = Rating::new(row.get(3)?, row.get(4)?);
+            let b = (row.get::<_, i64>(5)?, row.get::<_, i64>(6)?);
+            let rating_b = Rating::new(row.get(7)?, row.get(8)?);
+            let winner: i64 = row.get(9)?;
+
+            for (player, rating, opponent, opponent_rating, won) in [
+                (a, rating_a, b, rating_b, winner == 1),
+                (b, rating_b, a, rating_a, winner == 2),
+            ] {
+                opponents.entry(player.0).or_default().insert(opponent.0);
+                hours.entry(player.0).or_insert([0.0; 24])
+                    [((game_timestamp / 3600) % 24) as usize] += 1.0;
+
+                if established.contains(&player) {
+                    continue;
+                }
+                // Only characters whose first game is in the window are new
+                if !starts.contains_key(&player)
+                    && played_before(&tx, player.0, player.1, timestamp - SMURF_WINDOW + 1)?
+                {
+                    established.insert(player);
+                    continue;
+                }
+
+                let start = starts.entry(player).or_insert(Start {
+                    games: 0,
+                    wins: 0,
+                    expected_wins: 0.0,
+                    variance: 0.0,
+                });
+                if start.games >= SMURF_GAMES {
+                    continue;
+                }
+                let expected = rating.expected(opponent_rating);
+                start.games += 1;
+                start.expected_wins += expected;
+                start.variance += expected * (1.0 - expected);
+                if won {
+                    start.wins += 1;
+                }
+            }
+        }
+    }
+
+    let mut flagged = FxHashSet::<i64>::default();
+    for ((id, char_id), start) in &starts {
+        let z = match smurf_z(start.games, start.wins, start.expected_wins, start.variance) {
+            Some(z) if z >= SMURF_Z => z,
+            _ => continue,
+        };
+
+        tx.execute(
+            "INSERT OR REPLACE INTO smurf_flags
+            (id, char_id, timestamp, game_count, wins, expected_wins, z)
+            VALUES(?, ?, ?, ?, ?, ?, ?)",
+            params![
+                id,
+                char_id,
+                timestamp,
+                start.games,
+                start.wins,
+                start.expected_wins,
+                z
+            ],
+        )?;
+        flagged.insert(*id);
+    }
+
+    let hour_share = |id: &i64| {
+        hours.get(id).map(|h| {
+            let total: f64 = h.iter().sum();
+            h.map(|c| c / total)
+        })
+    };
+
+    let mut link_count = 0;
+    for id in &flagged {
+        let mut candidates = FxHashMap::<i64, (f64, i64)>::default();
+
+        // Players with a similar name
+        let names = {
+            let mut stmt = tx.prepare_cached("SELECT name FROM player_names WHERE id = ?")?;
+            let names = stmt
+                .query_map(params![id], |r| r.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            names
+        };
+        for name in names {
+            let name_trigrams = trigrams(&normalize_name(&name));
+            if name_trigrams.is_empty() {
+                continue;
+            }
+            let any_trigram = name_trigrams
+                .iter()
+                .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            let mut stmt = tx.prepare_cached(
+                "SELECT id, normalized_name FROM player_name_search
+                WHERE player_name_search MATCH ?
+                ORDER BY rank
+                LIMIT 1000",
+            )?;
+            let mut rows = stmt.query(params![any_trigram])?;
+            while let Some(row) = rows.next()? {
+                let other: i64 = row.get(0)?;
+                let similarity =
+                    trigram_similarity(&name_trigrams, &trigrams(&row.get::<_, String>(1)?));
+                if other != *id && similarity >= ALT_NAME_SIMILARITY {
+                    let candidate = candidates.entry(other).or_default();
+                    candidate.0 = candidate.0.max(similarity);
+                }
+            }
+        }
+
+        // Players who met the same opponents
+        let own_opponents = opponents.get(id).cloned().unwrap_or_default();
+        let mut shared = FxHashMap::<i64, i64>::default();
+        for opponent in &own_opponents {
+            for other in opponents.get(opponent).into_iter().flatten() {
+                if other != id {
+                    *shared.entry(*other).or_default() += 1;
+                }
+            }
+        }
+        for (other, count) in shared {
+            if count >= ALT_MIN_SHARED_OPPONENTS {
+                candidates.entry(other).or_default().1 = count;
+            }
+        }
+
+        let own_hours = hour_share(id);
+        let mut links = candidates
+            .into_iter()
+            .filter(|(other, _)| !flagged.contains(other))
+            .map(|(other, (name_similarity, shared))| {
+                let shared_opponents = match opponents.get(&other) {
+                    Some(other_opponents) => {
+                        shared as f64 / own_opponents.union(other_opponents).count().max(1) as f64
+                    }
+                    None => 0.0,
+                };
+                let hour_overlap = match (own_hours, hour_share(&other)) {
+                    (Some(a), Some(b)) => a.iter().zip(b.iter()).map(|(a, b)| a.min(*b)).sum(),
+                    _ => 0.0,
+                };
+                let score = ALT_NAME_WEIGHT * name_similarity
+                    + ALT_OPPONENT_WEIGHT * shared_opponents
+                    + ALT_HOUR_WEIGHT * hour_overlap;
+                (
+                    other,
+                    score,
+                    name_similarity,
+                    shared_opponents,
+                    hour_overlap,
+                )
+            })
+            .filter(|l| l.1 >= ALT_LINK_SCORE)
+            .collect::<Vec<_>>();
+        links.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        links.truncate(ALT_MAX_LINKS);
+
+        tx.execute("DELETE FROM alt_links WHERE id = ?", params![id])?;
+        for (other, score, name_similarity, shared_opponents, hour_overlap) in links {
+            tx.execute(
+                "INSERT INTO alt_links
+                (id, alt_id, timestamp, score, name_similarity, shared_opponents, hour_overlap)
+                VALUES(?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    id,
+                    other,
+                    timestamp,
+                    score,
+                    name_similarity,
+                    shared_opponents,
+                    hour_overlap
+                ],
+            )?;
+            link_count += 1;
+        }
+    }
+
+    tx.commit()?;
+
+    info!(
+        "Flagged {} smurfs with {} alt links - {}ms",
+        flagged.len(),
+        link_count,
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
+
+/// How well a replayed rating predicted its games.
+#[derive(Default)]
+struct BacktestScore {
+    log_loss: f64,
+    brier: f64,
+    points: f64,
+}
+
+impl BacktestScore {
+    /// Scores a game of the replayed rating `own` and updates it with the result.
+    fn add(&mut self, own: &mut Rating, opponent: Rating, result: f64) {
+        let expected = own.expected(opponent);
+        let chance = if result == 1.0 {
+            expected
+        } else {
+            1.0 - expected
+        };
+        self.log_loss -= chance.max(1e-9).ln();
+        self.brier += (result - expected).powi(2);
+        self.points -= opponent.rating_change(*own, 1.0 - result);
+        *own = own.update(opponent, result);
+    }
+}
+
+fn print_backtest(scores: &[(&str, &BacktestScore)], games: i64) {
+    let games = games.max(1) as f64;
+    println!("| Start | Log loss | Brier | Points taken per game |");
+    println!("|-------|----------|-------|-----------------------|");
+    for (label, score) in scores {
+        println!(
+            "| {} | {:.4} | {:.4} | {:.1} |",
+            label,
+            score.log_loss / games,
+            score.brier / games,
+            score.points / games
+        );
+    }
+}
+
+/// Learns the rating prior of each floor from the established players who were first seen before
+/// `first_seen_before`: the mean of their ratings, with their spread as the deviation.
+fn learn_floor_priors(
+    conn: &Connection,
+    first_seen_before: i64,
+) -> Result<FxHashMap<i64, (i64, Rating)>> {
+    let mut stmt = conn.prepare(
+        "SELECT floor, COUNT(*), AVG(value), AVG(value * value)
+        FROM players NATURAL JOIN player_ratings
+        WHERE deviation < ?
+            AND id IN (SELECT id FROM player_names GROUP BY id HAVING MIN(first_seen) < ?)
+        GROUP BY floor",
+    )?;
+    let mut rows = stmt.query(params![LOW_DEVIATION, first_seen_before])?;
+
+    let mut priors = FxHashMap::default();
+    while let Some(row) = rows.next()? {
+        let floor: i64 = row.get(0)?;
+        let player_count: i64 = row.get(1)?;
+        let mean: f64 = row.get(2)?;
+        let mean_square: f64 = row.get(3)?;
+        if player_count < FLOOR_PRIOR_MIN_PLAYERS {
+            continue;
+        }
+
+        let deviation = (mean_square - mean * mean)
+            .max(0.0)
+            .sqrt()
+            .clamp(LOW_DEVIATION, glicko::INITIAL_DEVIATION);
+        priors.insert(floor, (player_count, Rating::new(mean, deviation)));
+    }
+
+    Ok(priors)
+}
+
+/// Updates the rating priors new players start at from the floor of their first game.
+pub fn calc_floor_priors(conn: &mut Connection) -> Result<()> {
+    let then = Utc::now();
+    let priors = learn_floor_priors(conn, i64::MAX)?;
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM floor_rating_priors", [])?;
+    for (floor, (player_count, prior)) in &priors {
+        tx.execute(
+            "INSERT INTO floor_rating_priors(floor, player_count, value, deviation)
+            VALUES(?, ?, ?, ?)",
+            params![floor, player_count, prior.value, prior.deviation],
+        )?;
+    }
+    tx.commit()?;
+
+    info!(
+        "Updated {} floor priors - {}ms",
+        priors.len(),
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
+
+fn load_floor_priors(conn: &Connection) -> Result<FxHashMap<i64, Rating>> {
+    let mut stmt = conn.prepare("SELECT floor, value, deviation FROM floor_rating_priors")?;
+    let priors = stmt
+        .query_map([], |r| Ok((r.get(0)?, Rating::new(r.get(1)?, r.get(2)?))))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(priors)
+}
+
+/// Replays the first games of the players first seen in the last `days` days (28 by default), once
+/// from the default rating and once from the prior of the floor of their first game. The priors are
+/// learned only from players seen before then.
+pub fn backtest_floor_priors(days: Option<&str>) -> Result<()> {
+    let then = Utc::now();
+    let days: i64 = days.map(|d| d.parse()).transpose()?.unwrap_or(28);
+    let cutoff = then.timestamp() - days * 24 * 60 * 60;
+
+    let conn = Connection::open(DB_NAME)?;
+    let priors = learn_floor_priors(&conn, cutoff)?;
+
+    let new_players = {
+        let mut stmt =
+            conn.prepare("SELECT id FROM player_names GROUP BY id HAVING MIN(first_seen) >= ?")?;
+        let new_players = stmt
+            .query_map(params![cutoff], |r| r.get(0))?
+            .collect::<rusqlite::Result<FxHashSet<i64>>>()?;
+        new_players
+    };
+
+    struct Replay {
+        games: i64,
+        default: Rating,
+        prior: Rating,
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id_a, char_a, value_a, deviation_a, id_b, char_b, value_b, deviation_b, winner, game_floor
+        FROM game_ratings
+        NATURAL JOIN games
+        WHERE timestamp >= ?
+        ORDER BY timestamp",
+    )?;
+    let mut rows = stmt.query(params![cutoff])?;
+
+    let mut started = FxHashSet::<i64>::default();
+    let mut replays = FxHashMap::<(i64, i64), Replay>::default();
+    let mut default_score = BacktestScore::default();
+    let mut prior_score = BacktestScore::default();
+    let mut games = 0;
+
+    while let Some(row) = rows.next()? {
+        let a = (row.get::<_, i64>(0)?, row.get::<_, i64>(1)?);
+        let rating_a = Rating::new(row.get(2)?, row.get(3)?);
+        let b = (row.get::<_, i64>(4)?, row.get::<_, i64>(5)?);
+        let rating_b = Rating::new(row.get(6)?, row.get(7)?);
+        let winner: i64 = row.get(8)?;
+        let floor: i64 = row.get(9)?;
+
+        for (player, opponent_rating, result) in [
+            (a, rating_b, if winner == 1 { 1.0 } else { 0.0 }),
+            (b, rating_a, if winner == 2 { 1.0 } else { 0.0 }),
+        ] {
+            if new_players.contains(&player.0) && started.insert(player.0) {
+                if let Some((_, prior)) = priors.get(&floor) {
+                    replays.insert(
+                        player,
+                        Replay {
+                            games: 0,
+                            default: Rating::default(),
+                            prior: *prior,
+                        },
+                    );
+                }
+            }
+
+            if let Some(replay) = replays.get_mut(&player) {
+                default_score.add(&mut replay.default, opponent_rating, result);
+                prior_score.add(&mut replay.prior, opponent_rating, result);
+                games += 1;
+                replay.games += 1;
+                if replay.games >= BACKTEST_GAMES {
+                    replays.remove(&player);
+                }
+            }
+        }
+    }
+
+    println!(
+        "Floor priors for {} floors: {} new players, {} games",
+        priors.len(),
+        started.len(),
+        games
+    );
+    print_backtest(
+        &[("Default", &default_score), ("Floor", &prior_score)],
+        games,
+    );
+
+    info!(
+        "Backtested floor priors - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
+
+/// Replays the first games of every character that was picked up by a player with an established
+/// character, once from the default rating and once seeded, against the opponents' recorded
+/// ratings. Prints how well each predicted the results and how many points the new characters took.
+pub fn backtest_seeding(weight: Option<&str>, deviation: Option<&str>) -> Result<()> {
+    let then = Utc::now();
+    let conn = Connection::open(DB_NAME)?;
+    let config = load_rating_config(&conn)?;
+    let policy = SeedingPolicy {
+        weight: match weight {
+            Some(weight) => weight.parse()?,
+            None => config.seeding.as_ref().map(|p| p.weight).unwrap_or(0.5),
+        },
+        deviation: match deviation {
+            Some(deviation) => deviation.parse()?,
+            None => config
+                .seeding
+                .as_ref()
+                .map(|p| p.deviation)
+                .unwrap_or(250.0),
+        },
+    };
+
+    let (characters, games, default_score, seeded_score) = replay_seeding(&conn, &policy)?;
+
+    println!(
+        "Seeding weight {} deviation {}: {} characters, {} games",
+        policy.weight, policy.deviation, characters, games
+    );
+    print_backtest(
+        &[("Default", &default_score), ("Seeded", &seeded_score)],
+        games,
+    );
+
+    info!(
+        "Backtested seeding - {}ms",
+        (Utc::now() - then).num_milliseconds()
+    );
+
+    Ok(())
+}
+
+/// The replays of `backtest_seeding`: how many characters and games were replayed, and the scores
+/// from the default rating and seeded. A character is new at its first game, whatever rating it was
+/// actually given then.
+fn replay_seeding(
+    conn: &Connection,
+    policy: &SeedingPolicy,
+) -> Result<(i64, i64, BacktestScore, BacktestScore)> {
+    struct Replay {
+        games: i64,
+        default: Rating,
+        seeded: Rating,
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id_a, char_a, value_a, deviation_a, id_b, char_b, value_b, deviation_b, winner
+        FROM game_ratings
+        NATURAL JOIN games
+        ORDER BY timestamp",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    // The latest recorded rating of every character, by player
+    let mut ratings = FxHashMap::<i64, FxHashMap<i64, Rating>>::default();
+    let mut replays = FxHashMap::<(i64, i64), Replay>::default();
+    let mut default_score = BacktestScore::default();
+    let mut seeded_score = BacktestScore::default();
+    let mut characters = 0;
+    let mut games = 0;
+
+    while let Some(row) = rows.next()? {
+        let a = (row.get::<_, i64>(0)?, row.get::<_, i64>(1)?);
+        let rating_a = Rating::new(row.get(2)?, row.get(3)?);
+        let b = (row.get::<_, i64>(4)?, row.get::<_, i64>(5)?);
+        let rating_b = Rating::new(row.get(6)?, row.get(7)?);
+        let winner: i64 = row.get(8)?;
+
+        for (player, rating, opponent_rating, result) in [
+            (a, rating_a, rating_b, if winner == 1 { 1.0 } else { 0.0 }),
+            (b, rating_b, rating_a, if winner == 2 { 1.0 } else { 0.0 }),
+        ] {
+            let other_characters = ratings.entry(player.0).or_default();
+            let is_new = !other_characters.contains_key(&player.1);
+
+            if is_new {
+                let established = other_characters
+                    .values()
+                    .filter(|r| r.deviation < LOW_DEVIATION)
+                    .map(|r| r.value)
+                    .collect::<Vec<_>>();
+                if !established.is_empty() {
+                    let average = established.iter().sum::<f64>() / established.len() as f64;
+                    replays.insert(
+                        player,
+                        Replay {
+                            games: 0,
+                            default: Rating::default(),
+                            seeded: policy.seed(average),
+                        },
+                    );
+                    characters += 1;
+                }
+            }
+            other_characters.insert(player.1, rating);
+
+            if let Some(replay) = replays.get_mut(&player) {
+                default_score.add(&mut replay.default, opponent_rating, result);
+                seeded_score.add(&mut replay.seeded, opponent_rating, result);
+                games += 1;
+                replay.games += 1;
+                if replay.games >= BACKTEST_GAMES {
+                    replays.remove(&player);
+                }
+            }
+        }
+    }
+
+    Ok((characters, games, default_score, seeded_score))
+}
+
+/// Sets how new characters are seeded from the next rating update on, "off" starts them at the
+/// default rating. The deviation stays as it is when it isn't given.
+pub fn set_seeding(weight: Option<&str>, deviation: Option<&str>) -> Result<()> {
+    let weight: Option<f64> = match weight.context("Missing seeding weight")? {
+        "off" => None,
+        weight => Some(weight.parse()?),
+    };
+    let deviation: Option<f64> = deviation.map(|d| d.parse()).transpose()?;
+
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute(
+        "UPDATE rating_config SET
+            seeding_weight = ?,
+            seeding_deviation = COALESCE(?, seeding_deviation)",
+        params![weight, deviation],
+    )?;
+
+    info!("Set seeding weight {:?} deviation {:?}", weight, deviation);
+    Ok(())
+}
+
+/// Turns the floor priors of new players on or off from the next rating update on.
+pub fn set_floor_priors(enabled: Option<&str>) -> Result<()> {
+    let enabled = match enabled.context("Missing on or off")? {
+        "on" => true,
+        "off" => false,
+        enabled => return Err(anyhow::anyhow!("Expected on or off, got {}", enabled)),
+    };
+
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute(
+        "UPDATE rating_config SET floor_priors = ?",
+        params![enabled],
+    )?;
+
+    info!("Set floor priors {}", if enabled { "on" } else { "off" });
+    Ok(())
+}
+
+/// Sets what happens to the ratings of flagged smurfs from the next rating update on.
+pub fn set_smurf_policy(policy: Option<&str>) -> Result<()> {
+    let name = policy.context("Missing smurf policy")?;
+    let policy = SmurfPolicy::from_name(name).with_context(|| {
+        format!(
+            "Expected one of {}, got {}",
+            SmurfPolicy::ALL.map(SmurfPolicy::name).join(", "),
+            name
+        )
+    })?;
+
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute(
+        "UPDATE rating_config SET smurf_policy = ?",
+        params![policy.name()],
+    )?;
+
+    info!("Set smurf policy {}", policy.name());
+    Ok(())
+}
+
+pub fn add_season(name: Option<&str>, start: Option<&str>, end: Option<&str>) -> Result<()> {
+    let name = name.context("Missing season name")?;
+    let start = NaiveDateTime::parse_from_str(
+        &format!("{} 00:00:00", start.context("Missing season start date")?),
+        "%Y-%m-%d %H:%M:%S",
+    )
+    .context("Season start should be formatted as YYYY-MM-DD")?;
+    let end = NaiveDateTime::parse_from_str(
+        &format!("{} 00:00:00", end.context("Missing season end date")?),
+        "%Y-%m-%d %H:%M:%S",
+    )
+    .context("Season end should be formatted as YYYY-MM-DD")?;
 
-        while let Some(row) = rows.next().unwrap() {
-            let char_id: i64 = row.get(0).unwrap();
-            let player_count: i64 = row.get(1).unwrap();
-            let avg_delta: f64 = row.get(2).unwrap();
-            tx.execute(
-                "INSERT INTO fraud_index_higher_rated VALUES(?, ?, ?)",
-                params![char_id, player_count, avg_delta],
-            )
-            .unwrap();
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute(
+        "INSERT INTO seasons(name, start_timestamp, end_timestamp, archived) VALUES(?, ?, ?, 0)",
+        params![name, start.timestamp(), end.timestamp()],
+    )?;
+
+    info!("Added season {} ({} - {})", name, start, end);
+    Ok(())
+}
+
+pub fn add_patch(date: Option<&str>, label: Option<&str>, notes: Option<&str>) -> Result<()> {
+    let date = NaiveDateTime::parse_from_str(
+        &format!("{} 00:00:00", date.context("Missing patch date")?),
+        "%Y-%m-%d %H:%M:%S",
+    )
+    .context("Patch date should be formatted as YYYY-MM-DD")?;
+    let label = label.context("Missing patch label")?;
+
+    let conn = Connection::open(DB_NAME)?;
+    conn.execute(
+        "INSERT INTO patches(timestamp, label, notes) VALUES(?, ?, ?)",
+        params![date.timestamp(), label, notes],
+    )?;
+
+    info!("Added patch {} ({})", label, date);
+    Ok(())
+}
+
+pub fn remove_patch(label: Option<&str>) -> Result<()> {
+    let label = label.context("Missing patch label")?;
+
+    let mut conn = Connection::open(DB_NAME)?;
+    let tx = conn.transaction()?;
+    let patch_id: i64 = tx
+        .query_row(
+            "SELECT patch_id FROM patches WHERE label = ?",
+            params![label],
+            |r| r.get(0),
+        )
+        .optional()?
+        .context("No such patch")?;
+    for table in [
+        "character_popularity_global",
+        "character_popularity_rating",
+        "bracket_matchups",
+        "estimated_matchups",
+    ] {
+        tx.execute(
+            &format!("DELETE FROM {} WHERE patch_id = ?", table),
+            params![patch_id],
+        )?;
+    }
+    tx.execute("DELETE FROM patches WHERE patch_id = ?", params![patch_id])?;
+    tx.commit()?;
+
+    info!("Removed patch {}", label);
+    Ok(())
+}
+
+/// The most recent patch released at or before `timestamp`, with its release and the release of
+/// the patch after it, if there is one yet.
+fn latest_patch(conn: &Connection, timestamp: i64) -> Result<Option<(i64, i64, i64)>> {
+    Ok(conn
+        .query_row(
+            "SELECT
+                patch_id,
+                timestamp,
+                (SELECT MIN(next.timestamp) FROM patches AS next
+                    WHERE next.timestamp > patches.timestamp)
+            FROM patches
+            WHERE timestamp <= ?
+            ORDER BY timestamp DESC LIMIT 1",
+            params![timestamp],
+            |r| {
+                Ok((
+                    r.get(0)?,
+                    r.get(1)?,
+                    r.get::<_, Option<i64>>(2)?.unwrap_or(i64::MAX),
+                ))
+            },
+        )
+        .optional()?)
+}
+
+pub fn update_seasons_once() -> Result<()> {
+    let mut conn = Connection::open(DB_NAME)?;
+    update_seasons(&mut conn, Utc::now().timestamp())
+}
+
+/// Recalculates the standings of every season that has started but isn't archived yet. Seasons
+/// that have ended are archived after their final standings have been calculated.
+pub fn update_seasons(conn: &mut Connection, now: i64) -> Result<()> {
+    let seasons = {
+        let mut stmt = conn.prepare(
+            "SELECT season_id, start_timestamp, end_timestamp
+            FROM seasons
+            WHERE archived = 0 AND start_timestamp <= ?",
+        )?;
+        let mut rows = stmt.query(params![now])?;
+        let mut seasons = Vec::<(i64, i64, i64)>::new();
+        while let Some(row) = rows.next()? {
+            seasons.push((row.get(0)?, row.get(1)?, row.get(2)?));
         }
+        seasons
+    };
 
-        let mut stmt = tx
-            .prepare(
-                "select 
-                    char_id, 
-                    count(*), 
-                    avg(value - 
-                        (avg_value - (1.0 / char_count) * value)
-                        * char_count
-                        / (char_count - 1.0))
-            from
-                (
-                    select id, avg_value, char_count from
-                    (
-                        select
-                            id,
-                            avg(value) as avg_value, 
-                            count(char_id) as char_count
-                        from player_ratings
-                        where deviation < ? and wins + losses >= 200
-                        group by id
-                    ) as averages
-                    where char_count > 1
-                ) as filtered_averages
-
-                join
+    for (season_id, start, end) in seasons {
+        calc_season(conn, season_id, start, end.min(now))?;
 
-                (
-                    select id, char_id, value
-                    from player_ratings
-                    where deviation < ? and wins + losses >= 200
-                ) as char_ratings
+        if end <= now {
+            info!("Season {} has ended, archiving it", season_id);
+            conn.execute(
+                "UPDATE seasons SET archived = 1 WHERE season_id = ?",
+                params![season_id],
+            )?;
+        }
+    }
+
+    Ok(())
+}
 
-                on filtered_averages.id = char_ratings.id
+/// Replays the rated games of a season from the ratings stored in `game_ratings`, giving
+/// everyone's final rating, peak rating and record for that season, then ranks them.
+fn calc_season(conn: &mut Connection, season_id: i64, start: i64, end: i64) -> Result<()> {
+    info!("Calculating season {}", season_id);
+    let then = Utc::now();
 
-                where char_ratings.value > filtered_averages.avg_value 
-                    and char_ratings.value > 1800
+    struct SeasonPlayer {
+        wins: i64,
+        losses: i64,
+        rating: Rating,
+        last_game: i64,
+        peak: Option<(Rating, i64)>,
+    }
 
-            group by char_id;",
-            )
-            .unwrap();
+    let tx = conn.transaction()?;
 
-        let mut rows = stmt.query(params![LOW_DEVIATION, LOW_DEVIATION]).unwrap();
+    let mut players = FxHashMap::<(i64, i64), SeasonPlayer>::default();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT
+                timestamp,
+                id_a, char_a, value_a, deviation_a,
+                id_b, char_b, value_b, deviation_b,
+                winner
+            FROM games NATURAL JOIN game_ratings
+            WHERE valid AND timestamp >= ? AND timestamp < ?
+            ORDER BY timestamp ASC",
+        )?;
+        let mut rows = stmt.query(params![start, end])?;
+
+        while let Some(row) = rows.next()? {
+            let timestamp: i64 = row.get(0)?;
+            let a = (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?);
+            let rating_a = Rating::new(row.get(3)?, row.get(4)?);
+            let b = (row.get::<_, i64>(5)?, row.get::<_, i64>(6)?);
+            let rating_b = Rating::new(row.get(7)?, row.get(8)?);
+            let winner: i64 = row.get(9)?;
+
+            for (key, own, opp, result) in [
+                (a, rating_a, rating_b, if winner == 1 { 1.0 } else { 0.0 }),
+                (b, rating_b, rating_a, if winner == 2 { 1.0 } else { 0.0 }),
+            ] {
+                let rating = own.update(opp, result);
+                let player = players.entry(key).or_insert(SeasonPlayer {
+                    wins: 0,
+                    losses: 0,
+                    rating,
+                    last_game: timestamp,
+                    peak: None,
+                });
 
-        while let Some(row) = rows.next().unwrap() {
-            let char_id: i64 = row.get(0).unwrap();
-            let player_count: i64 = row.get(1).unwrap();
-            let avg_delta: f64 = row.get(2).unwrap();
-            tx.execute(
-                "INSERT INTO fraud_index_highest_rated VALUES(?, ?, ?)",
-                params![char_id, player_count, avg_delta],
-            )
-            .unwrap();
+                if result == 1.0 {
+                    player.wins += 1;
+                } else {
+                    player.losses += 1;
+                }
+                player.rating = rating;
+                player.last_game = timestamp;
+
+                if rating.deviation < LOW_DEVIATION
+                    && player
+                        .peak
+                        .map(|(peak, _)| rating.value > peak.value)
+                        .unwrap_or(true)
+                {
+                    player.peak = Some((rating, timestamp));
+                }
+            }
         }
     }
 
-    tx.commit()?;
+    let excluded = {
+        let mut excluded = FxHashSet::<i64>::default();
+        let mut stmt = tx.prepare(
+            "SELECT id FROM cheater_status
+            UNION
+            SELECT id FROM hidden_status WHERE hidden_status IS NOT NULL",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            excluded.insert(row.get(0)?);
+        }
+        excluded
+    };
+
+    for player in players.values_mut() {
+        let periods = (end - player.last_game) / RATING_PERIOD;
+        player.rating.decay_deviation(periods, DECAY_CONSTANT);
+    }
+
+    let mut ranked: Vec<_> = players
+        .iter()
+        .filter(|((id, _), p)| p.rating.deviation < LOW_DEVIATION && !excluded.contains(id))
+        .map(|(key, p)| (*key, p.rating.value))
+        .collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    let mut global_ranks = FxHashMap::<(i64, i64), i64>::default();
+    let mut character_ranks = FxHashMap::<(i64, i64), i64>::default();
+    let mut character_counts = FxHashMap::<i64, i64>::default();
+    for (i, (key, _)) in ranked.iter().enumerate() {
+        if i < 1000 {
+            global_ranks.insert(*key, i as i64 + 1);
+        }
+        let count = character_counts.entry(key.1).or_default();
+        if *count < 1000 {
+            *count += 1;
+            character_ranks.insert(*key, *count);
+        }
+    }
+
+    tx.execute(
+        "DELETE FROM season_ratings WHERE season_id = ?",
+        params![season_id],
+    )?;
+    for ((id, char_id), player) in &players {
+        tx.execute(
+            "INSERT INTO season_ratings VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                season_id,
+                id,
+                char_id,
+                player.wins,
+                player.losses,
+                player.rating.value,
+                player.rating.deviation,
+                player.peak.map(|(r, _)| r.value),
+                player.peak.map(|(r, _)| r.deviation),
+                player.peak.map(|(_, t)| t),
+                global_ranks.get(&(*id, *char_id)),
+                character_ranks.get(&(*id, *char_id)),
+            ],
+        )?;
+    }
 
+    tx.commit()?;
     info!(
-        "Updated fraud index - {}ms",
+        "Calculated season {} ({} players) - {}ms",
+        season_id,
+        players.len(),
         (Utc::now() - then).num_milliseconds()
     );
 
@@ -1786,16 +4761,13 @@ impl RatedPlayer {
             character_rank: None,
         }
     }
-    pub fn new_from_rating(id: i64, char_id: i64, timestamp: i64, rating: f64) -> Self {
+    pub fn new_from_rating(id: i64, char_id: i64, timestamp: i64, rating: Rating) -> Self {
         Self {
             id,
             char_id,
             win_count: 0,
             loss_count: 0,
-            rating: Rating {
-                value: rating,
-                ..Rating::default()
-            },
+            rating,
             last_decay: timestamp,
             top_rating: None,
             top_defeated: None,
@@ -1904,3 +4876,510 @@ impl RatedPlayer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("../init.sql")).unwrap();
+        conn
+    }
+
+    fn game(timestamp: i64, a: (i64, i64), b: (i64, i64), winner: i64) -> Game {
+        Game {
+            timestamp,
+            id_a: a.0,
+            name_a: format!("{:X}", a.0),
+            char_a: a.1,
+            platform_a: 3,
+            id_b: b.0,
+            name_b: format!("{:X}", b.0),
+            char_b: b.1,
+            platform_b: 3,
+            winner,
+            game_floor: 99,
+        }
+    }
+
+    fn insert_games(conn: &Connection, games: &[Game]) {
+        for g in games {
+            conn.execute(
+                "INSERT INTO games VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    g.timestamp,
+                    g.id_a,
+                    g.name_a,
+                    g.char_a,
+                    g.platform_a,
+                    g.id_b,
+                    g.name_b,
+                    g.char_b,
+                    g.platform_b,
+                    g.winner,
+                    g.game_floor
+                ],
+            )
+            .unwrap();
+        }
+    }
+
+    /// The rating player a had going into the game at `timestamp`.
+    fn rating_before(conn: &Connection, timestamp: i64) -> Rating {
+        conn.query_row(
+            "SELECT value_a, deviation_a FROM game_ratings WHERE timestamp = ?",
+            params![timestamp],
+            |r| Ok(Rating::new(r.get(0)?, r.get(1)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rating_brackets() {
+        assert_eq!(rating_bracket(500.0), 0);
+        assert_eq!(rating_bracket(1099.9), 0);
+        assert_eq!(rating_bracket(1100.0), 1);
+        assert_eq!(rating_bracket(1550.0), 5);
+        assert_eq!(rating_bracket(2199.9), POP_RATING_BRACKETS - 2);
+        assert_eq!(rating_bracket(2200.0), POP_RATING_BRACKETS - 1);
+        assert_eq!(rating_bracket(3500.0), POP_RATING_BRACKETS - 1);
+
+        for bracket in 0..POP_RATING_BRACKETS {
+            let (low, high) = crate::api::rating_bracket_bounds(bracket);
+            assert_eq!(rating_bracket(low as f64), bracket);
+            assert_eq!(rating_bracket(high as f64 - 0.1), bracket);
+        }
+    }
+
+    #[test]
+    fn normalized_names() {
+        assert_eq!(normalize_name("Ｓｏｌ　Ｂａｄｇｕｙ"), "solbadguy");
+        assert_eq!(normalize_name("𝐒𝐨𝐥 𝑆𝑜𝑙 𝟏𝟐"), "solsol12");
+        assert_eq!(normalize_name("ⓈⓄⓁ ⓢⓞⓛ"), "solsol");
+        assert_eq!(normalize_name("-=[Sol]=-"), "sol");
+        assert_eq!(normalize_name("ÉCLAIR"), "éclair");
+        assert_eq!(normalize_name("★☆★"), "★☆★");
+        assert_eq!(normalize_name(""), "");
+    }
+
+    #[test]
+    fn trigram_matching() {
+        assert_eq!(trigrams("abcab"), ["abc", "bca", "cab"]);
+        assert!(trigrams("ab").is_empty());
+
+        let a = trigrams("kykiske");
+        let b = trigrams("kyklske");
+        assert_eq!(trigram_similarity(&a, &a), 1.0);
+        assert_eq!(trigram_similarity(&a, &b), 0.4);
+        assert_eq!(trigram_similarity(&a, &trigrams("solbadguy")), 0.0);
+        assert_eq!(trigram_similarity(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn moderation() {
+        let mut conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO games VALUES(100, 1, 'cheater', 0, 1, 2, 'victim', 0, 1, 1, 99);
+            INSERT INTO game_ratings VALUES(100, 1, 1500.0, 100.0, 2, 1500.0, 100.0, 1, 1);
+            INSERT INTO player_ratings(id, char_id, wins, losses, value, deviation, last_decay)
+            VALUES(2, 0, 0, 1, 1480.0, 100.0, 0);
+            INSERT INTO cheat_reports(id, timestamp, reason, links, ip, status)
+            VALUES(1, 50, 'aimbot', '', 'a', 'open'), (1, 60, 'aimbot', '', 'b', 'open'),
+                (3, 70, 'lag', '', 'a', 'open'), (4, 80, 'lag', '', 'a', 'open');",
+        )
+        .unwrap();
+
+        let victim_rating = |conn: &Connection| -> f64 {
+            conn.query_row("SELECT value FROM player_ratings WHERE id = 2", [], |r| {
+                r.get(0)
+            })
+            .unwrap()
+        };
+        let count = |conn: &Connection, sql: &str| -> i64 {
+            conn.query_row(sql, [], |r| r.get(0)).unwrap()
+        };
+
+        assert_eq!(
+            resolve_reports(&mut conn, "mod", 1, Some("cheater"), "").unwrap(),
+            2
+        );
+        let lost = Rating::new(1500.0, 100.0).rating_change(Rating::new(1500.0, 100.0), 0.0);
+        assert_eq!(victim_rating(&conn), 1480.0 - lost);
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM cheat_reports
+                WHERE id = 1 AND status = 'confirmed' AND resolved_by = 'mod'"
+            ),
+            2
+        );
+
+        // Marking an existing cheater again doesn't give the rating back twice
+        let tx = conn.transaction().unwrap();
+        set_status(&tx, "mod", "cheater", 1, "cheater", "").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(victim_rating(&conn), 1480.0 - lost);
+
+        assert_eq!(resolve_reports(&mut conn, "mod", 3, None, "").unwrap(), 1);
+        assert_eq!(
+            count(&conn, "SELECT COUNT(*) FROM cheater_status WHERE id = 3"),
+            0
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM cheat_reports WHERE id = 3 AND status = 'dismissed'"
+            ),
+            1
+        );
+        let actions = conn
+            .prepare("SELECT action FROM moderation_log ORDER BY rowid")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .unwrap();
+        assert_eq!(actions, ["mark", "mark", "dismiss"]);
+
+        // The status isn't set when the reports can't be closed
+        conn.execute_batch(
+            "CREATE TRIGGER fail BEFORE UPDATE ON cheat_reports
+            BEGIN SELECT RAISE(ABORT, 'fail'); END;",
+        )
+        .unwrap();
+        assert!(resolve_reports(&mut conn, "mod", 4, Some("cheater"), "").is_err());
+        assert_eq!(
+            count(&conn, "SELECT COUNT(*) FROM cheater_status WHERE id = 4"),
+            0
+        );
+    }
+
+    #[test]
+    fn smurf_scores() {
+        // Even games, each expected to be won half the time
+        let even =
+            |games: i64, wins: i64| smurf_z(games, wins, games as f64 * 0.5, games as f64 * 0.25);
+
+        assert!(even(SMURF_MIN_GAMES - 1, SMURF_MIN_GAMES - 1).is_none());
+        assert_eq!(even(20, 10), Some(0.0));
+        assert!(even(20, 20).unwrap() >= SMURF_Z);
+        assert!(even(20, 13).unwrap() < SMURF_Z);
+        assert!(even(20, 2).unwrap() < 0.0);
+        assert!(smurf_z(20, 20, 20.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn seeding() {
+        let policy = SeedingPolicy {
+            weight: 0.5,
+            deviation: 250.0,
+        };
+        assert_eq!(policy.seed(2000.0), Rating::new(1750.0, 250.0));
+        assert_eq!(policy.seed(1300.0), Rating::new(1400.0, 250.0));
+
+        // The second character is picked up after the first one played in the same batch
+        let mut conn = test_db();
+        conn.execute_batch(
+            "UPDATE rating_config SET seeding_weight = 0.5, seeding_deviation = 250.0;
+            INSERT INTO player_ratings(id, char_id, wins, losses, value, deviation, last_decay)
+            VALUES(1, 0, 10, 10, 2000.0, 60.0, 0);",
+        )
+        .unwrap();
+        update_ratings(
+            &mut conn,
+            Some(vec![
+                game(100, (1, 0), (2, 0), 1),
+                game(200, (1, 1), (2, 0), 2),
+            ]),
+        );
+
+        let first: f64 = conn
+            .query_row(
+                "SELECT value FROM player_ratings WHERE id = 1 AND char_id = 0",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert!(first > 2000.0);
+        assert_eq!(rating_before(&conn, 200), policy.seed(first));
+    }
+
+    #[test]
+    fn seeding_backtest() {
+        let policy = SeedingPolicy {
+            weight: 0.5,
+            deviation: 250.0,
+        };
+        let mut conn = test_db();
+        conn.execute_batch(
+            "UPDATE rating_config SET seeding_weight = 0.5, seeding_deviation = 250.0;
+            INSERT INTO player_ratings(id, char_id, wins, losses, value, deviation, last_decay)
+            VALUES(1, 0, 10, 10, 2000.0, 60.0, 0);",
+        )
+        .unwrap();
+        let mut games = vec![game(100, (1, 0), (2, 0), 1)];
+        games.extend((0..5).map(|i| game(200 + i, (1, 1), (3 + i, 0), 1)));
+        insert_games(&conn, &games);
+        update_ratings(&mut conn, None);
+
+        // The new character was seeded, it is replayed all the same
+        assert_eq!(rating_before(&conn, 200).deviation, policy.deviation);
+        let (characters, games, _, _) = replay_seeding(&conn, &policy).unwrap();
+        assert_eq!((characters, games), (1, 5));
+    }
+
+    #[test]
+    fn floor_priors() {
+        let prior = Rating::new(1400.0, 200.0);
+        let mut conn = test_db();
+        let default_config = load_rating_config(&conn).unwrap();
+        assert!(default_config.seeding.is_none() && !default_config.floor_priors);
+
+        conn.execute(
+            "INSERT INTO floor_rating_priors(floor, player_count, value, deviation)
+            VALUES(99, 100, ?, ?)",
+            params![prior.value, prior.deviation],
+        )
+        .unwrap();
+        conn.execute("UPDATE rating_config SET floor_priors = 1", [])
+            .unwrap();
+        update_ratings(
+            &mut conn,
+            Some(vec![
+                game(100, (1, 0), (2, 0), 1),
+                game(200, (1, 1), (2, 0), 1),
+            ]),
+        );
+
+        // Only a player without any other character starts at the prior
+        assert_eq!(rating_before(&conn, 100), prior);
+        assert_eq!(rating_before(&conn, 200), Rating::default());
+
+        conn.execute("UPDATE rating_config SET floor_priors = 0", [])
+            .unwrap();
+        update_ratings(&mut conn, Some(vec![game(300, (3, 0), (2, 0), 1)]));
+        assert_eq!(rating_before(&conn, 300), Rating::default());
+    }
+
+    #[test]
+    fn sessions() {
+        let mut conn = test_db();
+        let tx = conn.transaction().unwrap();
+        let mut batch = SessionBatch::default();
+        for (opponent_id, timestamp, won) in [(2, 0, false), (3, 600, false), (2, 1200, true)] {
+            batch
+                .add_game(&tx, 1, opponent_id, timestamp, won, 0.5, 0.0)
+                .unwrap();
+        }
+        batch.write(&tx).unwrap();
+
+        // The session carries over into the next batch until the gap
+        let mut batch = SessionBatch::default();
+        batch.add_game(&tx, 1, 4, 1800, false, 0.5, 0.0).unwrap();
+        batch
+            .add_game(&tx, 1, 2, 1800 + SESSION_GAP + 1, true, 0.5, 0.0)
+            .unwrap();
+        batch.write(&tx).unwrap();
+
+        let sessions = tx
+            .prepare(
+                "SELECT start, game_count, wins, opponent_count, loss_streak, longest_loss_streak
+                FROM player_sessions WHERE id = 1 ORDER BY start",
+            )
+            .unwrap()
+            .query_map([], |r| {
+                Ok((
+                    r.get(0)?,
+                    r.get(1)?,
+                    r.get(2)?,
+                    r.get(3)?,
+                    r.get(4)?,
+                    r.get(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(i64, i64, i64, i64, i64, i64)>>>()
+            .unwrap();
+        assert_eq!(
+            sessions,
+            [(0, 4, 1, 3, 1, 2), (1800 + SESSION_GAP + 1, 1, 1, 1, 0, 0)]
+        );
+
+        let opponents: Vec<i64> = tx
+            .prepare("SELECT opponent_id FROM player_session_opponents WHERE id = 1")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(opponents, [2]);
+    }
+
+    #[test]
+    fn activity() {
+        // Monday 2023-01-02 00:00 UTC
+        let day = 1672617600;
+        let mut conn = test_db();
+        let tx = conn.transaction().unwrap();
+        let mut batch = ActivityBatch::default();
+        batch.add_game(1, 2, day + 3600);
+        batch.add_game(1, 3, day + 3660);
+        batch.write(&tx).unwrap();
+
+        // Players already counted on a day aren't counted again by a later batch
+        let mut batch = ActivityBatch::default();
+        batch.add_game(1, 2, day + 7200);
+        batch.add_game(1, 2, day + 86400 + 7200);
+        batch.write(&tx).unwrap();
+
+        let daily = tx
+            .prepare("SELECT day, game_count, player_count FROM daily_activity ORDER BY day")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(i64, i64, i64)>>>()
+            .unwrap();
+        assert_eq!(daily, [(day, 3, 3), (day + 86400, 1, 2)]);
+
+        let hourly = tx
+            .prepare("SELECT weekday, hour, game_count FROM hourly_activity ORDER BY weekday, hour")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(i64, i64, i64)>>>()
+            .unwrap();
+        assert_eq!(hourly, [(0, 1, 2), (0, 2, 1), (1, 2, 1)]);
+
+        let player_daily = tx
+            .prepare("SELECT day, game_count FROM player_daily_activity WHERE id = 1 ORDER BY day")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(i64, i64)>>>()
+            .unwrap();
+        assert_eq!(player_daily, [(day, 3), (day + 86400, 1)]);
+
+        let player_hourly: i64 = tx
+            .query_row(
+                "SELECT game_count FROM player_hourly_activity
+                WHERE id = 1 AND weekday = 0 AND hour = 1",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(player_hourly, 2);
+    }
+
+    #[test]
+    fn smurf_detection() {
+        let now = SMURF_WINDOW + 10_000;
+        let mut conn = test_db();
+        conn.execute_batch(
+            "UPDATE rating_config SET seeding_weight = 0.5, seeding_deviation = 250.0;
+            INSERT INTO player_ratings(id, char_id, wins, losses, value, deviation, last_decay)
+            VALUES(1, 0, 10, 10, 2000.0, 60.0, 10000);",
+        )
+        .unwrap();
+        for id in 100..160 {
+            conn.execute(
+                "INSERT INTO player_ratings(id, char_id, wins, losses, value, deviation, last_decay)
+                VALUES(?, 0, 10, 10, 2400.0, 60.0, 10000)",
+                params![id],
+            )
+            .unwrap();
+        }
+
+        // Both characters win every game in the window, but only the second one is new. It is
+        // seeded, so it doesn't start at the default rating
+        let mut games = vec![game(0, (1, 0), (2, 0), 1)];
+        for i in 0..SMURF_GAMES {
+            games.push(game(20_000 + i * 60, (1, 1), (100 + i, 0), 1));
+            games.push(game(20_030 + i * 60, (1, 0), (130 + i, 0), 1));
+        }
+        insert_games(&conn, &games);
+        update_ratings(&mut conn, None);
+        assert_ne!(rating_before(&conn, 20_000), Rating::default());
+
+        detect_smurfs(&mut conn, now).unwrap();
+        let flagged: Vec<(i64, i64)> = conn
+            .prepare("SELECT id, char_id FROM smurf_flags")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(flagged, [(1, 1)]);
+    }
+
+    #[test]
+    fn smurf_policies() {
+        let rate = |policy: SmurfPolicy| {
+            let mut conn = test_db();
+            conn.execute(
+                "UPDATE rating_config SET smurf_policy = ?",
+                params![policy.name()],
+            )
+            .unwrap();
+            conn.execute_batch(
+                "INSERT INTO smurf_flags VALUES(1, 0, 0, 10, 10, 5.0, 4.0);
+                INSERT INTO alt_links VALUES(3, 4, 0, 1.0, 1.0, 1.0, 1.0);
+                INSERT INTO player_ratings(id, char_id, wins, losses, value, deviation, last_decay)
+                VALUES(4, 0, 10, 10, 2100.0, 60.0, 0);",
+            )
+            .unwrap();
+
+            // Flagged player 1 and unflagged player 2 play the same games, flagged player 3
+            // picks up a character
+            let mut games = Vec::new();
+            for i in 0..20 {
+                games.push(game(100 + i, (1, 0), (100 + i, 0), 1));
+                games.push(game(200 + i, (2, 0), (200 + i, 0), 1));
+            }
+            games.push(game(300, (3, 0), (300, 0), 1));
+            update_ratings(&mut conn, Some(games));
+            conn
+        };
+        let deviation = |conn: &Connection, id: i64| -> f64 {
+            conn.query_row(
+                "SELECT deviation FROM player_ratings WHERE id = ? AND char_id = 0",
+                params![id],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+
+        let conn = rate(SmurfPolicy::ReportOnly);
+        assert_eq!(deviation(&conn, 1), deviation(&conn, 2));
+        assert_eq!(rating_before(&conn, 300), Rating::default());
+
+        let conn = rate(SmurfPolicy::FastConvergence);
+        assert!(deviation(&conn, 2) < SMURF_MIN_DEVIATION);
+        assert_eq!(deviation(&conn, 1), SMURF_MIN_DEVIATION);
+        assert_eq!(rating_before(&conn, 300), Rating::default());
+
+        let conn = rate(SmurfPolicy::AltStartingRating);
+        assert_eq!(deviation(&conn, 1), deviation(&conn, 2));
+        assert_eq!(
+            rating_before(&conn, 300),
+            Rating::new(2100.0, Rating::default().deviation)
+        );
+
+        for policy in SmurfPolicy::ALL {
+            assert_eq!(SmurfPolicy::from_name(policy.name()), Some(policy));
+        }
+    }
+
+    #[test]
+    fn tiers() {
+        assert_eq!(
+            assign_tiers(&[-2.0, -1.0, 0.0, 1.0, 2.0]),
+            ["D", "C", "B", "A", "S"]
+        );
+        assert_eq!(assign_tiers(&[3.0, 3.0, 3.0]), ["B", "B", "B"]);
+        assert_eq!(
+            assign_tiers(&[0.0, 0.0, 0.0, 0.0, 10.0]),
+            ["C", "C", "C", "C", "S"]
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use crate::api;
+use crate::{admin, api, rater};
+use lazy_static::lazy_static;
 use rocket::{
     fs::NamedFile,
     http::{hyper::header::CACHE_CONTROL, Header},
@@ -9,36 +10,77 @@ use rocket::{
 use rocket_dyn_templates::Template;
 use rocket_sync_db_pools::database;
 use rusqlite::Connection;
-use std::path::{Path, PathBuf};
-
-pub const CHAR_NAMES: &[(&str, &str)] = &[
-    ("SO", "Sol"),
-    ("KY", "Ky"),
-    ("MA", "May"),
-    ("AX", "Axl"),
-    ("CH", "Chipp"),
-    ("PO", "Potemkin"),
-    ("FA", "Faust"),
-    ("MI", "Millia"),
-    ("ZA", "Zato-1"),
-    ("RA", "Ramlethal"),
-    ("LE", "Leo"),
-    ("NA", "Nagoriyuki"),
-    ("GI", "Giovanna"),
-    ("AN", "Anji"),
-    ("IN", "I-No"),
-    ("GO", "Goldlewis"),
-    ("JC", "Jack-O'"),
-    ("HA", "Happy Chaos"),
-    ("BA", "Baiken"),
-    ("TE", "Testament"),
-    ("BI", "Bridget"),
-    ("SI", "Sin"),
-    ("BE", "Bedman?"),
-    ("AS", "Asuka"),
-    ("JN", "Johnny"),
-    ("EL", "Elphelt"),
-    ("AB", "A.B.A."),
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+lazy_static! {
+    static ref CHARACTERS: RwLock<&'static [(&'static str, &'static str)]> = RwLock::new(&[]);
+}
+
+/// The roster as `(short name, name)`, indexed by char_id.
+pub fn char_names() -> &'static [(&'static str, &'static str)] {
+    *CHARACTERS.read().unwrap()
+}
+
+/// The roster entry of `char_id`, ids that haven't been loaded yet show up as unknown.
+pub fn character(char_id: usize) -> (&'static str, &'static str) {
+    char_names()
+        .get(char_id)
+        .copied()
+        .unwrap_or(("??", "Unknown"))
+}
+
+/// Loads the roster from the `characters` table. The ids the rater has seen without a roster
+/// entry are filled in as "Unknown (id)", so everything indexed by char_id stays in bounds.
+/// The previous roster is leaked, this only happens at startup and when a character shows up.
+pub fn load_characters(conn: &Connection) -> rusqlite::Result<()> {
+    let mut roster = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT char_id, short, name FROM characters
+        UNION ALL
+        SELECT char_id, NULL, NULL FROM unknown_characters
+        WHERE char_id NOT IN (SELECT char_id FROM characters)
+        ORDER BY char_id ASC",
+    )?;
+    let unknown = |id: usize| -> (&'static str, &'static str) {
+        (
+            Box::leak(id.to_string().into_boxed_str()),
+            Box::leak(format!("Unknown ({})", id).into_boxed_str()),
+        )
+    };
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let char_id: usize = row.get(0)?;
+        let short: Option<String> = row.get(1)?;
+        let name: Option<String> = row.get(2)?;
+        while roster.len() < char_id {
+            roster.push(unknown(roster.len()));
+        }
+        roster.push(match (short, name) {
+            (Some(short), Some(name)) => (
+                &*Box::leak(short.into_boxed_str()),
+                &*Box::leak(name.into_boxed_str()),
+            ),
+            _ => unknown(char_id),
+        });
+    }
+    *CHARACTERS.write().unwrap() = Box::leak(roster.into_boxed_slice());
+    Ok(())
+}
+
+pub const PLATFORMS: &[(&str, &str)] = &[("PS", "PlayStation"), ("XB", "Xbox"), ("PC", "PC")];
+
+pub const REGIONS: &[(&str, &str)] = &[
+    ("NA", "North America"),
+    ("EU", "Europe"),
+    ("JP", "Japan"),
+    ("AS", "Asia"),
+    ("OC", "Oceania"),
+    ("SA", "South America"),
+    ("ME", "Middle East"),
+    ("AF", "Africa"),
 ];
 
 pub async fn run() {
@@ -52,7 +94,10 @@ pub async fn run() {
                 files,
                 top_all,
                 top_char,
+                seasons,
+                season,
                 matchups,
+                tiers,
                 character_popularity,
                 player_distr_forward,
                 player_distribution,
@@ -68,23 +113,54 @@ pub async fn run() {
                 api::stats,
                 api::player_rating,
                 api::player_rating_all,
+                api::player_rating_history,
                 api::player_rating_accuracy,
                 api::top_all,
                 api::top_char,
+                api::rank_history,
+                api::player_sessions,
+                api::percentile,
+                api::seasons,
+                api::season,
+                api::tiers,
+                api::tier_history,
+                api::popularity_trends,
+                api::popularity_history,
+                api::patches,
+                api::fraud,
                 api::search,
                 api::search_exact,
                 api::outcomes,
                 api::outcomes_delta,
+                api::bracket_matchups,
+                api::estimated_matchups,
                 api::floor_rating_distribution,
                 api::rating_experience,
                 api::rating_experience_player,
                 api::player_lookup,
-                api::active_players,
+                api::activity,
+                api::player_activity,
                 api::daily_games,
                 api::weekly_games,
                 api::daily_character_games,
                 api::start_hide_player,
-                api::poll_hide_player
+                api::poll_hide_player,
+                api::start_unhide_player,
+                api::poll_unhide_player,
+                api::start_set_region,
+                api::poll_set_region,
+                api::verification_status,
+                admin::list_status,
+                admin::mark_status,
+                admin::unmark_status,
+                admin::edit_status_notes,
+                admin::moderation_log,
+                admin::report_queue,
+                admin::player_reports,
+                admin::resolve_reports,
+                admin::anomalies,
+                admin::smurfs,
+                api::report_player
             ],
         )
         .register("/", catchers![catch_404, catch_500, catch_503])
@@ -101,7 +177,7 @@ pub struct RatingsDbConn(Connection);
 
 #[get("/")]
 async fn index() -> Redirect {
-    Redirect::to(uri!(top_all))
+    Redirect::to(uri!(top_all(_, _, _)))
 }
 
 #[get("/about")]
@@ -113,7 +189,7 @@ async fn about(conn: RatingsDbConn) -> Cached<Template> {
         all_characters: &'static [(&'static str, &'static str)],
     }
     let context = Context {
-        all_characters: CHAR_NAMES,
+        all_characters: char_names(),
     };
 
     Cached::new(Template::render("about", &context), 999)
@@ -128,7 +204,7 @@ async fn rating_calculator(conn: RatingsDbConn) -> Cached<Template> {
         all_characters: &'static [(&'static str, &'static str)],
     }
     let context = Context {
-        all_characters: CHAR_NAMES,
+        all_characters: char_names(),
     };
 
     Cached::new(Template::render("rating_calculator", &context), 999)
@@ -141,11 +217,13 @@ async fn stats(conn: RatingsDbConn) -> Cached<Template> {
     #[derive(Serialize)]
     struct Context {
         stats: api::Stats,
+        activity: api::GlobalActivity,
         all_characters: &'static [(&'static str, &'static str)],
     }
     let context = Context {
         stats: api::stats_inner(&conn).await,
-        all_characters: CHAR_NAMES,
+        activity: api::activity_inner(&conn, 26).await,
+        all_characters: char_names(),
     };
 
     Cached::new(Template::render("stats", &context), 999)
@@ -165,34 +243,59 @@ async fn supporters(conn: RatingsDbConn) -> Cached<Template> {
             "supporters",
             &Context {
                 players: api::get_supporters(&conn).await,
-                all_characters: CHAR_NAMES,
+                all_characters: char_names(),
             },
         ),
         999,
     )
 }
 
-#[get("/top/all")]
-async fn top_all(conn: RatingsDbConn) -> Cached<Template> {
+#[get("/top/all?<date>&<platform>&<region>")]
+async fn top_all(
+    conn: RatingsDbConn,
+    date: Option<String>,
+    platform: Option<String>,
+    region: Option<String>,
+) -> Cached<Template> {
     api::add_hit(&conn, format!("top/all")).await;
 
     #[derive(Serialize)]
     struct Context {
         players: Vec<api::RankingPlayer>,
+        filter: api::RankingFilter,
         all_characters: &'static [(&'static str, &'static str)],
+        all_platforms: &'static [(&'static str, &'static str)],
+        all_regions: &'static [(&'static str, &'static str)],
     }
 
-    let players = api::top_all_inner(&conn).await;
+    let filter = api::RankingFilter {
+        date,
+        platform,
+        region,
+    }
+    .validated();
+    let players = api::top_all_filtered(&conn, &filter)
+        .await
+        .unwrap_or_default();
     let context = Context {
         players,
-        all_characters: CHAR_NAMES,
+        filter,
+        all_characters: char_names(),
+        all_platforms: PLATFORMS,
+        all_regions: REGIONS,
     };
 
     Cached::new(Template::render("top_100", &context), 999)
 }
 
-#[get("/top/<character_short>")]
-async fn top_char(conn: RatingsDbConn, character_short: &str) -> Option<Cached<Template>> {
+#[get("/top/<character_short>?<date>&<platform>&<region>")]
+async fn top_char(
+    conn: RatingsDbConn,
+    character_short: &str,
+    date: Option<String>,
+    platform: Option<String>,
+    region: Option<String>,
+) -> Option<Cached<Template>> {
     api::add_hit(&conn, format!("top/{}", character_short)).await;
 
     #[derive(Serialize)]
@@ -200,18 +303,32 @@ async fn top_char(conn: RatingsDbConn, character_short: &str) -> Option<Cached<T
         players: Vec<api::RankingPlayer>,
         character: &'static str,
         character_short: &'static str,
+        filter: api::RankingFilter,
         all_characters: &'static [(&'static str, &'static str)],
+        all_platforms: &'static [(&'static str, &'static str)],
+        all_regions: &'static [(&'static str, &'static str)],
     }
 
-    if let Some(char_code) = CHAR_NAMES.iter().position(|(c, _)| *c == character_short) {
-        let (character_short, character) = CHAR_NAMES[char_code];
+    if let Some(char_code) = char_names().iter().position(|(c, _)| *c == character_short) {
+        let (character_short, character) = character(char_code);
 
-        let players = api::top_char_inner(&conn, char_code as i64).await;
+        let filter = api::RankingFilter {
+            date,
+            platform,
+            region,
+        }
+        .validated();
+        let players = api::top_char_filtered(&conn, char_code as i64, &filter)
+            .await
+            .unwrap_or_default();
         let context = Context {
             players,
             character,
             character_short,
-            all_characters: CHAR_NAMES,
+            filter,
+            all_characters: char_names(),
+            all_platforms: PLATFORMS,
+            all_regions: REGIONS,
         };
 
         Some(Cached::new(Template::render("top_100_char", &context), 999))
@@ -220,8 +337,48 @@ async fn top_char(conn: RatingsDbConn, character_short: &str) -> Option<Cached<T
     }
 }
 
-#[get("/matchups")]
-async fn matchups(conn: RatingsDbConn) -> Cached<Template> {
+#[get("/seasons")]
+async fn seasons(conn: RatingsDbConn) -> Cached<Template> {
+    api::add_hit(&conn, format!("seasons")).await;
+
+    #[derive(Serialize)]
+    struct Context {
+        seasons: Vec<api::Season>,
+        all_characters: &'static [(&'static str, &'static str)],
+    }
+
+    let context = Context {
+        seasons: api::seasons_inner(&conn).await,
+        all_characters: char_names(),
+    };
+
+    Cached::new(Template::render("seasons", &context), 999)
+}
+
+#[get("/season/<season_id>")]
+async fn season(conn: RatingsDbConn, season_id: i64) -> Option<Cached<Template>> {
+    api::add_hit(&conn, format!("season/{}", season_id)).await;
+
+    #[derive(Serialize)]
+    struct Context {
+        standings: api::SeasonStandings,
+        all_characters: &'static [(&'static str, &'static str)],
+    }
+
+    let context = Context {
+        standings: api::season_inner(&conn, season_id).await?,
+        all_characters: char_names(),
+    };
+
+    Some(Cached::new(Template::render("season", &context), 999))
+}
+
+#[get("/matchups?<bracket>&<patch>")]
+async fn matchups(
+    conn: RatingsDbConn,
+    bracket: Option<usize>,
+    patch: Option<i64>,
+) -> Cached<Template> {
     api::add_hit(&conn, format!("matchups")).await;
 
     #[derive(Serialize)]
@@ -231,30 +388,93 @@ async fn matchups(conn: RatingsDbConn) -> Cached<Template> {
         matchups_top_1000: Vec<api::CharacterMatchups>,
         matchups_proportional: Vec<api::CharacterMatchups>,
         matchups_top_100: Vec<api::CharacterMatchups>,
+        matchups_bracket: Vec<api::CharacterMatchups>,
+        matchups_estimated: Vec<api::CharacterMatchups>,
+        bracket: usize,
+        rating_brackets: Vec<(usize, String)>,
+        patch: i64,
+        patches: Vec<api::Patch>,
         all_characters: &'static [(&'static str, &'static str)],
     }
 
-    let (matchups_global, matchups_top_1000, matchups_proportional, matchups_top_100) = tokio::join!(
+    let bracket = bracket
+        .filter(|b| *b < rater::POP_RATING_BRACKETS)
+        .unwrap_or(rater::rating_bracket(1500.0));
+    let patch = patch.unwrap_or(0);
+
+    let (
+        matchups_global,
+        matchups_top_1000,
+        matchups_proportional,
+        matchups_top_100,
+        matchups_bracket,
+        matchups_estimated,
+    ) = tokio::join!(
         api::get_matchups(&conn, "global_matchups"),
         api::get_matchups(&conn, "top_1000_matchups"),
         api::get_matchups(&conn, "proportional_matchups"),
         api::get_matchups(&conn, "top_100_matchups"),
+        api::get_bracket_matchups(&conn, bracket, patch),
+        api::get_estimated_matchups(&conn, patch),
     );
+    let patches = api::patches_inner(&conn).await;
 
     let context = Context {
-        character_shortnames: CHAR_NAMES.iter().map(|c| c.0).collect(),
+        character_shortnames: char_names().iter().map(|c| c.0).collect(),
         matchups_global,
         matchups_top_1000,
         matchups_proportional,
         matchups_top_100,
-        all_characters: CHAR_NAMES,
+        matchups_bracket,
+        matchups_estimated,
+        bracket,
+        rating_brackets: (0..rater::POP_RATING_BRACKETS)
+            .map(|b| {
+                let (min, max) = api::rating_bracket_bounds(b);
+                (b, format!("{} - {}", min, max))
+            })
+            .collect(),
+        patch,
+        patches,
+        all_characters: char_names(),
     };
 
     Cached::new(Template::render("matchups", &context), 999)
 }
 
-#[get("/character_popularity")]
-async fn character_popularity(conn: RatingsDbConn) -> Cached<Template> {
+#[get("/tiers")]
+async fn tiers(conn: RatingsDbConn) -> Cached<Template> {
+    api::add_hit(&conn, format!("tiers")).await;
+
+    #[derive(Serialize)]
+    struct Context {
+        tier_list: Option<api::TierList>,
+        history: api::TierHistory,
+        all_characters: &'static [(&'static str, &'static str)],
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let (tier_list, history) = tokio::join!(
+        api::tiers_inner(&conn),
+        api::tier_history_inner(
+            &conn,
+            now - 12 * 7 * 24 * 60 * 60,
+            now,
+            api::TierHistoryResolution::Week
+        ),
+    );
+
+    let context = Context {
+        tier_list,
+        history,
+        all_characters: char_names(),
+    };
+
+    Cached::new(Template::render("tiers", &context), 999)
+}
+
+#[get("/character_popularity?<patch>")]
+async fn character_popularity(conn: RatingsDbConn, patch: Option<i64>) -> Cached<Template> {
     api::add_hit(&conn, format!("character_popularity")).await;
 
     #[derive(Serialize)]
@@ -265,25 +485,32 @@ async fn character_popularity(conn: RatingsDbConn) -> Cached<Template> {
         fraud_stats: Vec<api::FraudStats>,
         fraud_stats_higher_rated: Vec<api::FraudStats>,
         fraud_stats_highest_rated: Vec<api::FraudStats>,
+        popularity_trends: Option<api::PopularityTrends>,
+        patch: i64,
+        patches: Vec<api::Patch>,
         all_characters: &'static [(&'static str, &'static str)],
     }
 
+    let patch = patch.unwrap_or(0);
+
     let (
         (global_character_popularity, rank_character_popularity),
+        popularity_trends,
         fraud_stats,
         fraud_stats_higher_rated,
         fraud_stats_highest_rated,
     ) = tokio::join!(
-        api::character_popularity(&conn),
-        api::get_fraud(&conn),
-        api::get_fraud_higher_rated(&conn),
-        api::get_fraud_highest_rated(&conn),
+        api::character_popularity(&conn, patch),
+        api::popularity_trends_inner(&conn, i64::MAX),
+        api::get_fraud(&conn, rater::FRAUD_THRESHOLDS[0]),
+        api::get_fraud(&conn, rater::FRAUD_THRESHOLDS[1]),
+        api::get_fraud(&conn, rater::FRAUD_THRESHOLDS[2]),
     );
 
     //let mut char_pop: Vec<((f64, _), &'static str)> = global_character_popularity
     //    .into_iter()
     //    .zip(rank_character_popularity.into_iter())
-    //    .zip(CHAR_NAMES.iter().map(|c| c.0))
+    //    .zip(char_names().iter().map(|c| c.0))
     //    .collect();
 
     //char_pop.sort_by(|a, b| b.0.0.partial_cmp(&a.0.0).unwrap());
@@ -300,13 +527,16 @@ async fn character_popularity(conn: RatingsDbConn) -> Cached<Template> {
     //}
 
     let context = Context {
-        character_shortnames: CHAR_NAMES.iter().map(|c| c.0).collect(),
+        character_shortnames: char_names().iter().map(|c| c.0).collect(),
         global_character_popularity,
         rank_character_popularity,
         fraud_stats,
         fraud_stats_higher_rated,
         fraud_stats_highest_rated,
-        all_characters: CHAR_NAMES,
+        popularity_trends,
+        patch,
+        patches: api::patches_inner(&conn).await,
+        all_characters: char_names(),
     };
 
     Cached::new(Template::render("character_popularity", &context), 999)
@@ -335,7 +565,7 @@ async fn player_distribution(conn: RatingsDbConn) -> Cached<Template> {
     let context = Context {
         floors,
         ratings,
-        all_characters: CHAR_NAMES,
+        all_characters: char_names(),
     };
 
     Cached::new(Template::render("player_distribution", &context), 999)
@@ -347,7 +577,7 @@ async fn player(conn: RatingsDbConn, player_id: &str) -> Option<Redirect> {
 
     if let Ok(id) = i64::from_str_radix(player_id, 16) {
         if let Some(char_id) = api::get_player_highest_rated_character(&conn, id).await {
-            let char_short = CHAR_NAMES[char_id as usize].0;
+            let char_short = character(char_id as usize).0;
             Some(Redirect::to(uri!(player_char(
                 player_id = player_id,
                 char_id = char_short,
@@ -371,7 +601,7 @@ async fn player_char_history(
     api::add_hit(&conn, format!("player/{}/{}/history", player_id, char_id)).await;
 
     if let Ok(id) = i64::from_str_radix(player_id, 16) {
-        let char_id = CHAR_NAMES.iter().position(|(c, _)| *c == char_id)? as i64;
+        let char_id = char_names().iter().position(|(c, _)| *c == char_id)? as i64;
         let game_count = game_count.unwrap_or(100);
         let offset = offset.unwrap_or(0);
         let group_games = group_games.unwrap_or(true);
@@ -400,7 +630,7 @@ async fn player_char(
     api::add_hit(&conn, format!("player/{}/{}", player_id, char_id)).await;
 
     if let Ok(id) = i64::from_str_radix(player_id, 16) {
-        let char_id_i64 = CHAR_NAMES.iter().position(|(c, _)| *c == char_id)? as i64;
+        let char_id_i64 = char_names().iter().position(|(c, _)| *c == char_id)? as i64;
 
         #[derive(Serialize)]
         struct Context {
@@ -408,9 +638,42 @@ async fn player_char(
             char_id: String,
             player: api::PlayerDataChar,
             all_characters: &'static [(&'static str, &'static str)],
+            all_regions: &'static [(&'static str, &'static str)],
             hidden_status: bool,
         }
 
+        // Hidden players only get the notice, with the button to enable the profile again
+        if api::is_player_hidden(&conn, id).await {
+            #[derive(Serialize)]
+            struct HiddenPlayer {
+                id: String,
+                name: &'static str,
+                hidden_status: bool,
+            }
+
+            #[derive(Serialize)]
+            struct HiddenContext {
+                player_id: String,
+                char_id: String,
+                player: HiddenPlayer,
+                all_characters: &'static [(&'static str, &'static str)],
+                hidden_status: bool,
+            }
+
+            let context = HiddenContext {
+                player_id: player_id.to_owned(),
+                char_id: char_id.to_owned(),
+                player: HiddenPlayer {
+                    id: format!("{:X}", id),
+                    name: api::HIDDEN_NAME,
+                    hidden_status: true,
+                },
+                all_characters: char_names(),
+                hidden_status: true,
+            };
+            return Some(Cached::new(Template::render("player_char", &context), 999));
+        }
+
         if let Ok(Some(player)) = api::get_player_data_char(&conn, id, char_id_i64).await {
             let mut hidden_status = false;
 
@@ -423,7 +686,8 @@ async fn player_char(
                 char_id: char_id.to_owned(),
                 hidden_status,
                 player,
-                all_characters: CHAR_NAMES,
+                all_characters: char_names(),
+                all_regions: REGIONS,
             };
             Some(Cached::new(Template::render("player_char", &context), 999))
         } else {
@@ -451,7 +715,7 @@ async fn search(conn: RatingsDbConn, name: String) -> Template {
         &Context {
             players,
             search_string: name,
-            all_characters: CHAR_NAMES,
+            all_characters: char_names(),
         },
     )
 }